@@ -2,6 +2,9 @@ use std::collections::HashMap;
 use serde::{Deserialize, Serialize};
 use anyhow::{anyhow, Result};
 
+use crate::devices::{DeviceModel, NOMINAL_TEMPERATURE};
+use crate::waveform::Waveform;
+
 /// Represents a node in the circuit
 #[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
 pub struct Node {
@@ -50,6 +53,20 @@ pub enum ComponentType {
         model_type: String,
         area: Option<f64>,
     },
+    Jfet {
+        model_type: String,
+        area: Option<f64>,
+    },
+    /// Voltage-controlled voltage source (E); nodes are out+, out-, control+, control-
+    Vcvs,
+    /// Current-controlled current source (F), driven by the current through voltage source `control`
+    Cccs { control: String },
+    /// Voltage-controlled current source (G); nodes are out+, out-, control+, control-
+    Vccs,
+    /// Current-controlled voltage source (H), driven by the current through voltage source `control`
+    Ccvs { control: String },
+    /// Mutual inductance (K) coupling every pair of the named inductors; it has no nodes of its own
+    MutualInductance { inductors: Vec<String> },
 }
 
 impl ComponentType {
@@ -60,7 +77,19 @@ impl ComponentType {
             ComponentType::Capacitor | 
             ComponentType::Inductor | 
             ComponentType::VoltageSource | 
-            ComponentType::CurrentSource
+            ComponentType::CurrentSource |
+            ComponentType::Vcvs |
+            ComponentType::Cccs { .. } |
+            ComponentType::Vccs |
+            ComponentType::Ccvs { .. } |
+            ComponentType::MutualInductance { .. }
+        )
+    }
+
+    /// Returns true if this component is a controlled (dependent) source
+    pub fn is_controlled_source(&self) -> bool {
+        matches!(self,
+            ComponentType::Vcvs | ComponentType::Cccs { .. } | ComponentType::Vccs | ComponentType::Ccvs { .. }
         )
     }
 
@@ -69,9 +98,11 @@ impl ComponentType {
         matches!(self, ComponentType::VoltageSource | ComponentType::CurrentSource)
     }
 
-    #[allow(dead_code)]
+    /// Returns true if the component needs a branch current unknown in the MNA system
     pub fn tracks_current(&self) -> bool {
-        matches!(self, ComponentType::VoltageSource | ComponentType::Inductor)
+        matches!(self,
+            ComponentType::VoltageSource | ComponentType::Inductor | ComponentType::Vcvs | ComponentType::Ccvs { .. }
+        )
     }
 }
 
@@ -83,6 +114,15 @@ pub struct Component {
     pub nodes: Vec<String>,
     pub value: f64,
     pub model: Option<String>,
+    /// Transient waveform of an independent source; `value` holds its DC value
+    #[serde(default)]
+    pub waveform: Option<Waveform>,
+    /// Small-signal AC magnitude and phase (in degrees) of an independent source
+    #[serde(default)]
+    pub ac: Option<(f64, f64)>,
+    /// `IC=` initial voltage of a capacitor or current of an inductor, used with UIC
+    #[serde(default)]
+    pub initial_condition: Option<f64>,
 }
 
 impl Component {
@@ -93,6 +133,9 @@ impl Component {
             nodes: vec![node1, node2],
             value: resistance,
             model: None,
+            waveform: None,
+            ac: None,
+            initial_condition: None,
         }
     }
 
@@ -103,6 +146,9 @@ impl Component {
             nodes: vec![node1, node2],
             value: capacitance,
             model: None,
+            waveform: None,
+            ac: None,
+            initial_condition: None,
         }
     }
 
@@ -113,6 +159,9 @@ impl Component {
             nodes: vec![node1, node2],
             value: inductance,
             model: None,
+            waveform: None,
+            ac: None,
+            initial_condition: None,
         }
     }
 
@@ -123,6 +172,9 @@ impl Component {
             nodes: vec![node_pos, node_neg],
             value: voltage,
             model: None,
+            waveform: None,
+            ac: None,
+            initial_condition: None,
         }
     }
 
@@ -133,9 +185,112 @@ impl Component {
             nodes: vec![node_pos, node_neg],
             value: current,
             model: None,
+            waveform: None,
+            ac: None,
+            initial_condition: None,
+        }
+    }
+
+    /// Diode between `anode` and `cathode`, using the named `.MODEL` (SPICE defaults when `None`)
+    pub fn new_diode(name: String, anode: String, cathode: String, model: Option<String>) -> Self {
+        Component {
+            name,
+            component_type: ComponentType::Diode,
+            nodes: vec![anode, cathode],
+            value: 1.0, // area factor
+            model,
+            waveform: None,
+            ac: None,
+            initial_condition: None,
         }
     }
 
+    /// MOSFET with nodes drain, gate, source and bulk, using the named NMOS or PMOS `.MODEL`.
+    /// `width` and `length` default to the model's default channel size when `None`.
+    pub fn new_mosfet(name: String, nodes: Vec<String>, model: String, width: Option<f64>, length: Option<f64>) -> Self {
+        Component {
+            name,
+            component_type: ComponentType::Mosfet { model_type: model.clone(), width, length },
+            nodes,
+            value: 1.0,
+            model: Some(model),
+            waveform: None,
+            ac: None,
+            initial_condition: None,
+        }
+    }
+
+    /// Bipolar transistor with nodes collector, base, emitter and an optional substrate,
+    /// using the named NPN or PNP `.MODEL`
+    pub fn new_bjt(name: String, nodes: Vec<String>, model: String, area: Option<f64>) -> Self {
+        Component {
+            name,
+            component_type: ComponentType::Bjt { model_type: model.clone(), area },
+            nodes,
+            value: 1.0,
+            model: Some(model),
+            waveform: None,
+            ac: None,
+            initial_condition: None,
+        }
+    }
+
+    /// JFET with nodes drain, gate and source, using the named NJF or PJF `.MODEL`
+    pub fn new_jfet(name: String, nodes: Vec<String>, model: String, area: Option<f64>) -> Self {
+        Component {
+            name,
+            component_type: ComponentType::Jfet { model_type: model.clone(), area },
+            nodes,
+            value: 1.0,
+            model: Some(model),
+            waveform: None,
+            ac: None,
+            initial_condition: None,
+        }
+    }
+
+    /// Mutual inductance coupling `inductors` with coupling coefficient `coupling`
+    pub fn new_mutual_inductance(name: String, inductors: Vec<String>, coupling: f64) -> Self {
+        Component {
+            name,
+            component_type: ComponentType::MutualInductance { inductors },
+            nodes: Vec::new(),
+            value: coupling,
+            model: None,
+            waveform: None,
+            ac: None,
+            initial_condition: None,
+        }
+    }
+
+    /// Controlled source of the given type with gain `gain`: E and G take the output and
+    /// controlling node pairs, F and H only the output pair
+    pub fn new_controlled_source(name: String, component_type: ComponentType, nodes: Vec<String>, gain: f64) -> Self {
+        Component {
+            name,
+            component_type,
+            nodes,
+            value: gain,
+            model: None,
+            waveform: None,
+            ac: None,
+            initial_condition: None,
+        }
+    }
+
+    /// Value of an independent source at time `time`, following its waveform if it has one
+    pub fn value_at(&self, time: f64) -> f64 {
+        match &self.waveform {
+            Some(waveform) => waveform.value_at(time),
+            None => self.value,
+        }
+    }
+
+    /// First time after `after` where the waveform of an independent source has a corner
+    pub fn next_breakpoint(&self, after: f64) -> Option<f64> {
+        self.waveform.as_ref().and_then(|waveform| waveform.next_breakpoint(after))
+    }
+
     /// Get the conductance for resistive elements
     pub fn conductance(&self) -> Result<f64> {
         match self.component_type {
@@ -158,16 +313,23 @@ impl Component {
             ComponentType::Inductor | 
             ComponentType::VoltageSource | 
             ComponentType::CurrentSource | 
-            ComponentType::Diode => 2,
+            ComponentType::Diode |
+            ComponentType::Cccs { .. } |
+            ComponentType::Ccvs { .. } => 2,
+            ComponentType::Vcvs | ComponentType::Vccs => 4, // Output pair, controlling pair
             ComponentType::Mosfet { .. } => 4, // Drain, Gate, Source, Bulk
-            ComponentType::Bjt { .. } => 3,    // Collector, Base, Emitter
+            ComponentType::Bjt { .. } => 3,    // Collector, Base, Emitter (plus optional substrate)
+            ComponentType::Jfet { .. } => 3,   // Drain, Gate, Source
+            ComponentType::MutualInductance { .. } => 0,
         }
     }
 
     /// Validate that the component has the correct number of nodes
     pub fn validate(&self) -> Result<()> {
         let expected_nodes = self.terminal_count();
-        if self.nodes.len() != expected_nodes {
+        // BJT 可以带一个可选的衬底节点
+        let optional_nodes = matches!(self.component_type, ComponentType::Bjt { .. }) as usize;
+        if self.nodes.len() < expected_nodes || self.nodes.len() > expected_nodes + optional_nodes {
             return Err(anyhow!(
                 "Component {} expects {} nodes, but has {}",
                 self.name,
@@ -177,20 +339,36 @@ impl Component {
         }
 
         // Additional validation based on component type
-        match self.component_type {
-            ComponentType::Resistor => {
-                if self.value <= 0.0 {
-                    return Err(anyhow!("Resistor {} must have positive resistance", self.name));
-                }
+        match &self.component_type {
+            ComponentType::Resistor if self.value <= 0.0 => {
+                return Err(anyhow!("Resistor {} must have positive resistance", self.name));
             }
-            ComponentType::Capacitor => {
-                if self.value <= 0.0 {
-                    return Err(anyhow!("Capacitor {} must have positive capacitance", self.name));
-                }
+            ComponentType::Capacitor if self.value <= 0.0 => {
+                return Err(anyhow!("Capacitor {} must have positive capacitance", self.name));
             }
-            ComponentType::Inductor => {
-                if self.value <= 0.0 {
-                    return Err(anyhow!("Inductor {} must have positive inductance", self.name));
+            ComponentType::Inductor if self.value <= 0.0 => {
+                return Err(anyhow!("Inductor {} must have positive inductance", self.name));
+            }
+            ComponentType::Diode if self.value <= 0.0 => {
+                return Err(anyhow!("Diode {} must have a positive area", self.name));
+            }
+            ComponentType::Mosfet { width, length, .. }
+                if width.is_some_and(|w| w <= 0.0) || length.is_some_and(|l| l <= 0.0) => {
+                return Err(anyhow!("MOSFET {} must have a positive W and L", self.name));
+            }
+            ComponentType::Bjt { area: Some(area), .. } if *area <= 0.0 => {
+                return Err(anyhow!("BJT {} must have a positive area", self.name));
+            }
+            ComponentType::Jfet { area: Some(area), .. } if *area <= 0.0 => {
+                return Err(anyhow!("JFET {} must have a positive area", self.name));
+            }
+            ComponentType::MutualInductance { inductors } => {
+                if inductors.len() < 2 {
+                    return Err(anyhow!("Mutual inductance {} must couple at least two inductors", self.name));
+                }
+                if !(self.value > 0.0 && self.value <= 1.0) {
+                    return Err(anyhow!("Mutual inductance {} must have a coupling coefficient in (0, 1], got {}",
+                        self.name, self.value));
                 }
             }
             _ => {}
@@ -208,6 +386,23 @@ pub struct Circuit {
     pub components: Vec<Component>,
     pub node_map: HashMap<String, usize>,
     pub ground_node: Option<usize>,
+    /// Device models from `.MODEL` cards, keyed by lowercase name
+    #[serde(default)]
+    pub models: HashMap<String, DeviceModel>,
+    /// Circuit temperature in °C
+    #[serde(default = "default_temperature")]
+    pub temperature: f64,
+    /// `.IC` node voltages: held during the operating point transient analysis starts
+    /// from, or the starting voltages themselves with UIC
+    #[serde(default)]
+    pub initial_conditions: HashMap<String, f64>,
+    /// `.NODESET` node voltages the operating point iteration starts from
+    #[serde(default)]
+    pub nodesets: HashMap<String, f64>,
+}
+
+fn default_temperature() -> f64 {
+    NOMINAL_TEMPERATURE
 }
 
 impl Circuit {
@@ -218,6 +413,25 @@ impl Circuit {
             components: Vec::new(),
             node_map: HashMap::new(),
             ground_node: None,
+            models: HashMap::new(),
+            temperature: NOMINAL_TEMPERATURE,
+            initial_conditions: HashMap::new(),
+            nodesets: HashMap::new(),
+        }
+    }
+
+    /// Add a device model; names are case-insensitive
+    pub fn add_model(&mut self, name: &str, model: DeviceModel) {
+        self.models.insert(name.to_lowercase(), model);
+    }
+
+    /// Look up the model a component refers to
+    pub fn model_for(&self, component: &Component) -> Result<Option<&DeviceModel>> {
+        match &component.model {
+            Some(name) => self.models.get(&name.to_lowercase())
+                .map(Some)
+                .ok_or_else(|| anyhow!("Model {} used by {} is not defined", name, component.name)),
+            None => Ok(None),
         }
     }
 
@@ -312,6 +526,87 @@ impl Circuit {
             .collect()
     }
 
+    /// Get all components with a branch current unknown in the MNA system:
+    /// voltage sources, inductors and the E and H controlled sources
+    pub fn branch_components(&self) -> Vec<&Component> {
+        self.components
+            .iter()
+            .filter(|comp| comp.component_type.tracks_current())
+            .collect()
+    }
+
+    /// Earliest breakpoint of any source waveform after `after`.
+    /// Transient analysis places a time point exactly on each of them.
+    pub fn next_breakpoint(&self, after: f64) -> Option<f64> {
+        self.components
+            .iter()
+            .filter_map(|comp| comp.next_breakpoint(after))
+            .min_by(f64::total_cmp)
+    }
+
+    /// Mutual inductances of the K elements as `(K element, inductor, inductor, M)` with
+    /// M = k*sqrt(L1*L2), one entry per coupled pair of inductors
+    pub fn mutual_inductances(&self) -> Result<Vec<(&Component, &Component, &Component, f64)>> {
+        let mut pairs = Vec::new();
+        for component in &self.components {
+            let ComponentType::MutualInductance { inductors } = &component.component_type else {
+                continue;
+            };
+            let coupled = inductors.iter()
+                .map(|name| match self.find_component(name) {
+                    Some(inductor) if inductor.component_type == ComponentType::Inductor => Ok(inductor),
+                    _ => Err(anyhow!("{} couples {}, which is not an inductor", component.name, name)),
+                })
+                .collect::<Result<Vec<_>>>()?;
+            for (i, first) in coupled.iter().enumerate() {
+                for second in &coupled[i + 1..] {
+                    let mutual = component.value * (first.value * second.value).sqrt();
+                    pairs.push((component, *first, *second, mutual));
+                }
+            }
+        }
+        Ok(pairs)
+    }
+
+    /// Check that every pair of inductors is coupled at most once and that the
+    /// coupling matrix of the coupled inductors is positive (semi)definite, as
+    /// required of a physical set of windings. Unity coupling makes it singular,
+    /// which is allowed.
+    fn validate_coupling(&self) -> Result<()> {
+        let pairs = self.mutual_inductances()?;
+        let mut index: HashMap<&str, usize> = HashMap::new();
+        for (_, first, second, _) in &pairs {
+            for inductor in [first, second] {
+                let next = index.len();
+                index.entry(inductor.name.as_str()).or_insert(next);
+            }
+        }
+
+        let mut coupling = nalgebra::DMatrix::<f64>::identity(index.len(), index.len());
+        for (_, first, second, mutual) in &pairs {
+            let (i, j) = (index[first.name.as_str()], index[second.name.as_str()]);
+            if i == j || coupling[(i, j)] != 0.0 {
+                return Err(anyhow!("Inductors {} and {} are coupled more than once", first.name, second.name));
+            }
+            let k = mutual / (first.value * second.value).sqrt();
+            coupling[(i, j)] = k;
+            coupling[(j, i)] = k;
+        }
+
+        if !coupling.is_empty() && coupling.symmetric_eigenvalues().min() < -1e-9 {
+            let mut names: Vec<&str> = index.keys().copied().collect();
+            names.sort_unstable();
+            return Err(anyhow!("Coupling matrix of inductors {} is not positive definite", names.join(", ")));
+        }
+
+        Ok(())
+    }
+
+    /// Find a component by name, ignoring case as SPICE does
+    pub fn find_component(&self, name: &str) -> Option<&Component> {
+        self.components.iter().find(|comp| comp.name.eq_ignore_ascii_case(name))
+    }
+
     /// Get all current sources  
     pub fn current_sources(&self) -> Vec<&Component> {
         self.components
@@ -328,7 +623,7 @@ impl Circuit {
             .collect()
     }
 
-    /// Get all nonlinear components (D, M, Q)
+    /// Get all nonlinear components (D, M, Q, J)
     pub fn nonlinear_components(&self) -> Vec<&Component> {
         self.components
             .iter()
@@ -362,9 +657,37 @@ impl Circuit {
             return Err(anyhow!("Circuit must have a ground node (named '0', 'gnd', or 'ground')"));
         }
 
-        // Validate all components
+        // Validate all components, the models and controlling sources they refer to
         for component in &self.components {
             component.validate()?;
+            if let ComponentType::Cccs { control } | ComponentType::Ccvs { control } = &component.component_type {
+                match self.find_component(control) {
+                    Some(source) if source.component_type == ComponentType::VoltageSource => {}
+                    _ => return Err(anyhow!("{} is controlled by {}, which is not a voltage source", component.name, control)),
+                }
+            }
+            let expected: &[&str] = match component.component_type {
+                ComponentType::Diode => &["D"],
+                ComponentType::Mosfet { .. } => &["NMOS", "PMOS"],
+                ComponentType::Bjt { .. } => &["NPN", "PNP"],
+                ComponentType::Jfet { .. } => &["NJF", "PJF"],
+                _ => continue,
+            };
+            if let Some(model) = self.model_for(component)? {
+                if !expected.contains(&model.kind()) {
+                    return Err(anyhow!("{} needs a {} model, but {} is a {} model",
+                        component.name, expected.join("/"), component.model.as_deref().unwrap_or(""), model.kind()));
+                }
+            }
+        }
+
+        self.validate_coupling()?;
+
+        // .IC 和 .NODESET 只能引用电路中的节点
+        for (card, voltages) in [(".IC", &self.initial_conditions), (".NODESET", &self.nodesets)] {
+            if let Some(node) = voltages.keys().find(|node| self.get_node_id(node).is_none()) {
+                return Err(anyhow!("{} refers to unknown node {}", card, node));
+            }
         }
 
         // Check for floating nodes
@@ -410,6 +733,10 @@ impl Circuit {
                 ComponentType::Diode => "Diodes",
                 ComponentType::Mosfet { .. } => "MOSFETs",
                 ComponentType::Bjt { .. } => "BJTs",
+                ComponentType::Jfet { .. } => "JFETs",
+                ComponentType::Vcvs | ComponentType::Cccs { .. } |
+                ComponentType::Vccs | ComponentType::Ccvs { .. } => "Controlled Sources",
+                ComponentType::MutualInductance { .. } => "Mutual Inductances",
             };
             *type_counts.entry(type_name).or_insert(0) += 1;
         }
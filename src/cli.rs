@@ -5,7 +5,8 @@ use anyhow::{anyhow, Result};
 pub struct CliArgs {
     pub input_file: String,
     pub output_file: Option<String>,
-    pub analysis_type: AnalysisType,
+    /// Analysis requested on the command line, run instead of those in the netlist
+    pub analysis_type: Option<AnalysisType>,
     pub output_format: OutputFormat,
     pub verbose_level: u8,
 }
@@ -15,6 +16,7 @@ pub enum AnalysisType {
     Operating,
     Transient { tstep: f64, tstop: f64 },
     DcSweep { source: String, start: f64, stop: f64, step: f64 },
+    Ac { sweep_type: String, points: usize, fstart: f64, fstop: f64 },
 }
 
 #[derive(Debug, Clone)]
@@ -53,7 +55,7 @@ impl CliArgs {
                 return Err(anyhow!("Invalid time parameters: tstep must be positive and less than tstop"));
             }
             
-            AnalysisType::Transient { tstep, tstop }
+            Some(AnalysisType::Transient { tstep, tstop })
         } else if let Some(dc_values) = matches.get_many::<String>("dc") {
             let values: Vec<&String> = dc_values.collect();
             if values.len() != 4 {
@@ -65,14 +67,30 @@ impl CliArgs {
             let stop = parse_voltage_value(values[2])?;
             let step = parse_voltage_value(values[3])?;
             
-            if step <= 0.0 {
-                return Err(anyhow!("Step size must be positive"));
+            if step == 0.0 && start != stop {
+                return Err(anyhow!("Step size must be non-zero"));
             }
             
-            AnalysisType::DcSweep { source, start, stop, step }
+            Some(AnalysisType::DcSweep { source, start, stop, step })
+        } else if let Some(ac_values) = matches.get_many::<String>("ac") {
+            let values: Vec<&String> = ac_values.collect();
+            if values.len() != 4 {
+                return Err(anyhow!("AC analysis requires exactly 4 parameters: type, points, fstart, fstop"));
+            }
+
+            let sweep_type = values[0].to_lowercase();
+            if !matches!(sweep_type.as_str(), "dec" | "oct" | "lin") {
+                return Err(anyhow!("AC sweep type must be dec, oct or lin"));
+            }
+            let points = values[1].parse::<usize>()
+                .map_err(|_| anyhow!("Invalid number of AC points: {}", values[1]))?;
+            let fstart = parse_frequency_value(values[2])?;
+            let fstop = parse_frequency_value(values[3])?;
+
+            Some(AnalysisType::Ac { sweep_type, points, fstart, fstop })
         } else {
-            // Default to operating point analysis
-            AnalysisType::Operating
+            // 没有命令行分析时运行网表中的分析
+            None
         };
 
         Ok(CliArgs {
@@ -107,6 +125,22 @@ fn parse_time_value(value: &str) -> Result<f64> {
     }
 }
 
+/// Parse frequency value with unit (e.g., "100Hz", "1kHz", "10meg")
+fn parse_frequency_value(value: &str) -> Result<f64> {
+    let value = value.trim().to_lowercase();
+    let value = value.strip_suffix("hz").unwrap_or(&value);
+
+    if let Some(num_str) = value.strip_suffix("meg") {
+        Ok(num_str.parse::<f64>()? * 1e6)
+    } else if let Some(num_str) = value.strip_suffix('g') {
+        Ok(num_str.parse::<f64>()? * 1e9)
+    } else if let Some(num_str) = value.strip_suffix('k') {
+        Ok(num_str.parse::<f64>()? * 1e3)
+    } else {
+        Ok(value.parse::<f64>()?)
+    }
+}
+
 /// Parse voltage/current value with unit (e.g., "1V", "1.5mA", "10uA")
 fn parse_voltage_value(value: &str) -> Result<f64> {
     let value = value.trim().to_lowercase();
@@ -143,6 +177,14 @@ mod tests {
         assert_eq!(parse_time_value("1").unwrap(), 1.0);
     }
 
+    #[test]
+    fn test_parse_frequency_value() {
+        assert_eq!(parse_frequency_value("100").unwrap(), 100.0);
+        assert_eq!(parse_frequency_value("1kHz").unwrap(), 1e3);
+        assert_eq!(parse_frequency_value("2.5meg").unwrap(), 2.5e6);
+        assert!(parse_frequency_value("fast").is_err());
+    }
+
     #[test]
     fn test_parse_voltage_value() {
         assert_eq!(parse_voltage_value("1V").unwrap(), 1.0);
@@ -0,0 +1,531 @@
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+
+use super::{
+    critical_voltage, depletion_charge, junction_capacitance, junction_potential, limit_junction_voltage,
+    thermal_voltage, Polarity, CELSIUS_TO_KELVIN, GMIN, NOMINAL_TEMPERATURE,
+};
+
+/// Gummel-Poon bipolar transistor parameters from a `.MODEL name NPN|PNP(...)` card
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BjtModel {
+    pub polarity: Polarity,
+    /// Transport saturation current (IS)
+    pub is: f64,
+    /// Ideal maximum forward beta (BF)
+    pub bf: f64,
+    /// Forward emission coefficient (NF)
+    pub nf: f64,
+    /// Forward Early voltage (VAF), infinite when not given
+    pub vaf: f64,
+    /// Corner of forward beta high-current roll-off (IKF), infinite when not given
+    pub ikf: f64,
+    /// Base-emitter leakage saturation current (ISE)
+    pub ise: f64,
+    /// Base-emitter leakage emission coefficient (NE)
+    pub ne: f64,
+    /// Ideal maximum reverse beta (BR)
+    pub br: f64,
+    /// Reverse emission coefficient (NR)
+    pub nr: f64,
+    /// Reverse Early voltage (VAR), infinite when not given
+    pub var: f64,
+    /// Corner of reverse beta high-current roll-off (IKR), infinite when not given
+    pub ikr: f64,
+    /// Base-collector leakage saturation current (ISC)
+    pub isc: f64,
+    /// Base-collector leakage emission coefficient (NC)
+    pub nc: f64,
+    /// Zero-bias base resistance (RB)
+    pub rb: f64,
+    /// Minimum base resistance at high currents (RBM), equal to RB when not given
+    pub rbm: f64,
+    /// Emitter resistance (RE)
+    pub re: f64,
+    /// Collector resistance (RC)
+    pub rc: f64,
+    /// Base-emitter zero-bias depletion capacitance (CJE)
+    pub cje: f64,
+    /// Base-emitter built-in potential (VJE)
+    pub vje: f64,
+    /// Base-emitter junction grading coefficient (MJE)
+    pub mje: f64,
+    /// Ideal forward transit time (TF)
+    pub tf: f64,
+    /// Coefficient for the bias dependence of TF (XTF)
+    pub xtf: f64,
+    /// Voltage describing the VBC dependence of TF (VTF), infinite when not given
+    pub vtf: f64,
+    /// High-current parameter for the effect on TF (ITF)
+    pub itf: f64,
+    /// Base-collector zero-bias depletion capacitance (CJC)
+    pub cjc: f64,
+    /// Base-collector built-in potential (VJC)
+    pub vjc: f64,
+    /// Base-collector junction grading coefficient (MJC)
+    pub mjc: f64,
+    /// Fraction of CJC connected to the internal base node (XCJC)
+    pub xcjc: f64,
+    /// Ideal reverse transit time (TR)
+    pub tr: f64,
+    /// Collector-substrate zero-bias capacitance (CJS)
+    pub cjs: f64,
+    /// Substrate junction built-in potential (VJS)
+    pub vjs: f64,
+    /// Substrate junction grading coefficient (MJS)
+    pub mjs: f64,
+    /// Forward-bias depletion capacitance coefficient (FC)
+    pub fc: f64,
+    /// Forward and reverse beta temperature exponent (XTB)
+    pub xtb: f64,
+    /// Band gap energy in eV (EG)
+    pub eg: f64,
+    /// Saturation current temperature exponent (XTI)
+    pub xti: f64,
+    /// Temperature the parameters were measured at, in °C (TNOM)
+    pub tnom: f64,
+}
+
+impl Default for BjtModel {
+    fn default() -> Self {
+        BjtModel {
+            polarity: Polarity::N,
+            is: 1e-16,
+            bf: 100.0,
+            nf: 1.0,
+            vaf: f64::INFINITY,
+            ikf: f64::INFINITY,
+            ise: 0.0,
+            ne: 1.5,
+            br: 1.0,
+            nr: 1.0,
+            var: f64::INFINITY,
+            ikr: f64::INFINITY,
+            isc: 0.0,
+            nc: 2.0,
+            rb: 0.0,
+            rbm: 0.0,
+            re: 0.0,
+            rc: 0.0,
+            cje: 0.0,
+            vje: 0.75,
+            mje: 0.33,
+            tf: 0.0,
+            xtf: 0.0,
+            vtf: f64::INFINITY,
+            itf: 0.0,
+            cjc: 0.0,
+            vjc: 0.75,
+            mjc: 0.33,
+            xcjc: 1.0,
+            tr: 0.0,
+            cjs: 0.0,
+            vjs: 0.75,
+            mjs: 0.0,
+            fc: 0.5,
+            xtb: 0.0,
+            eg: 1.11,
+            xti: 3.0,
+            tnom: NOMINAL_TEMPERATURE,
+        }
+    }
+}
+
+impl BjtModel {
+    /// Build a model of the given polarity from `name=value` pairs, starting from the SPICE defaults.
+    /// As in SPICE, a zero VAF, VAR, IKF, IKR or VTF means infinite.
+    pub fn from_params(polarity: Polarity, params: &[(String, f64)]) -> Result<BjtModel> {
+        let mut model = BjtModel { polarity, ..BjtModel::default() };
+        let mut rbm_given = false;
+        for (name, value) in params {
+            let field = match name.to_lowercase().as_str() {
+                "is" => &mut model.is,
+                "bf" => &mut model.bf,
+                "nf" => &mut model.nf,
+                "vaf" | "va" => &mut model.vaf,
+                "ikf" | "ik" => &mut model.ikf,
+                "ise" => &mut model.ise,
+                "ne" => &mut model.ne,
+                "br" => &mut model.br,
+                "nr" => &mut model.nr,
+                "var" | "vb" => &mut model.var,
+                "ikr" => &mut model.ikr,
+                "isc" => &mut model.isc,
+                "nc" => &mut model.nc,
+                "rb" => &mut model.rb,
+                "rbm" => {
+                    rbm_given = true;
+                    &mut model.rbm
+                }
+                "re" => &mut model.re,
+                "rc" => &mut model.rc,
+                "cje" => &mut model.cje,
+                "vje" | "pe" => &mut model.vje,
+                "mje" | "me" => &mut model.mje,
+                "tf" => &mut model.tf,
+                "xtf" => &mut model.xtf,
+                "vtf" => &mut model.vtf,
+                "itf" => &mut model.itf,
+                "cjc" => &mut model.cjc,
+                "vjc" | "pc" => &mut model.vjc,
+                "mjc" | "mc" => &mut model.mjc,
+                "xcjc" => &mut model.xcjc,
+                "tr" => &mut model.tr,
+                "cjs" | "ccs" => &mut model.cjs,
+                "vjs" | "ps" => &mut model.vjs,
+                "mjs" | "ms" => &mut model.mjs,
+                "fc" => &mut model.fc,
+                "xtb" => &mut model.xtb,
+                "eg" => &mut model.eg,
+                "xti" => &mut model.xti,
+                "tnom" => &mut model.tnom,
+                // 不影响本模型的参数（PTF 为超前相移，未建模）
+                "level" | "kf" | "af" | "ptf" => continue,
+                other => return Err(anyhow!("Unknown BJT model parameter: {}", other)),
+            };
+            *field = *value;
+        }
+        for field in [&mut model.vaf, &mut model.var, &mut model.ikf, &mut model.ikr, &mut model.vtf] {
+            if *field == 0.0 {
+                *field = f64::INFINITY;
+            }
+        }
+        if !rbm_given {
+            model.rbm = model.rb;
+        }
+        model.validate()?;
+        Ok(model)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.is <= 0.0 || self.bf <= 0.0 || self.br <= 0.0 {
+            return Err(anyhow!("BJT IS, BF and BR must be positive"));
+        }
+        if [self.nf, self.nr, self.ne, self.nc, self.vje, self.vjc, self.vjs].iter().any(|&v| v <= 0.0) {
+            return Err(anyhow!("BJT emission coefficients and junction potentials must be positive"));
+        }
+        if [self.vaf, self.var, self.ikf, self.ikr, self.vtf].iter().any(|&v| v < 0.0) {
+            return Err(anyhow!("BJT VAF, VAR, IKF, IKR and VTF must not be negative"));
+        }
+        let non_negative = [
+            self.ise, self.isc, self.rb, self.rbm, self.re, self.rc,
+            self.cje, self.cjc, self.cjs, self.tf, self.tr, self.xtf, self.itf,
+        ];
+        if non_negative.iter().any(|&v| v < 0.0) {
+            return Err(anyhow!("BJT leakage currents, resistances, capacitances and transit times must not be negative"));
+        }
+        if self.rbm > self.rb {
+            return Err(anyhow!("BJT RBM must not exceed RB"));
+        }
+        let fractions = [self.mje, self.mjc, self.mjs, self.fc];
+        if fractions.iter().any(|v| !(0.0..1.0).contains(v)) || !(0.0..=1.0).contains(&self.xcjc) {
+            return Err(anyhow!("BJT MJE, MJC, MJS and FC must be between 0 and 1, and XCJC at most 1"));
+        }
+        Ok(())
+    }
+
+    /// Parameters of a transistor with the given `area` factor, adjusted to `temperature` in °C
+    pub fn instance(&self, area: f64, temperature: f64) -> BjtInstance {
+        let t = temperature + CELSIUS_TO_KELVIN;
+        let tnom = self.tnom + CELSIUS_TO_KELVIN;
+        let vt = thermal_voltage(temperature);
+
+        // IS(T) = IS * (T/Tnom)^XTI * exp(EG/Vt * (T/Tnom - 1))，beta 按 (T/Tnom)^XTB 变化
+        let ratio_log = (t / tnom).ln();
+        let factor_log = (t / tnom - 1.0) * self.eg / vt + self.xti * ratio_log;
+        let beta_factor = (self.xtb * ratio_log).exp();
+
+        let vje = junction_potential(self.vje, self.tnom, temperature);
+        let vjc = junction_potential(self.vjc, self.tnom, temperature);
+        let vjs = junction_potential(self.vjs, self.tnom, temperature);
+        let cje = junction_capacitance(self.cje, self.mje, self.vje, vje, self.tnom, temperature);
+        let cjc = junction_capacitance(self.cjc, self.mjc, self.vjc, vjc, self.tnom, temperature);
+        let cjs = junction_capacitance(self.cjs, self.mjs, self.vjs, vjs, self.tnom, temperature);
+        let is = area * self.is * factor_log.exp();
+        let inverse = |value: f64| if value.is_finite() { 1.0 / value } else { 0.0 };
+
+        BjtInstance {
+            sign: self.polarity.sign(),
+            vt,
+            is,
+            bf: self.bf * beta_factor,
+            nf: self.nf,
+            ise: area * self.ise * (factor_log / self.ne).exp() / beta_factor,
+            ne: self.ne,
+            br: self.br * beta_factor,
+            nr: self.nr,
+            isc: area * self.isc * (factor_log / self.nc).exp() / beta_factor,
+            nc: self.nc,
+            inverse_vaf: inverse(self.vaf),
+            inverse_var: inverse(self.var),
+            inverse_ikf: inverse(area * self.ikf),
+            inverse_ikr: inverse(area * self.ikr),
+            rb: self.rb / area,
+            rbm: self.rbm / area,
+            re: self.re / area,
+            rc: self.rc / area,
+            cje: area * cje,
+            vje,
+            mje: self.mje,
+            tf: self.tf,
+            xtf: self.xtf,
+            vtf_factor: inverse(1.44 * self.vtf),
+            itf: area * self.itf,
+            cjc_internal: area * cjc * self.xcjc,
+            cjc_external: area * cjc * (1.0 - self.xcjc),
+            vjc,
+            mjc: self.mjc,
+            tr: self.tr,
+            cjs: area * cjs,
+            vjs,
+            mjs: self.mjs,
+            fc: self.fc,
+            vcrit: critical_voltage(is, vt),
+        }
+    }
+}
+
+/// Terminal currents of the intrinsic transistor and their derivatives at one bias point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BjtOperatingPoint {
+    /// Collector current, flowing into the internal collector
+    pub ic: f64,
+    /// Base current, flowing into the internal base
+    pub ib: f64,
+    /// Forward transconductance dIc/dVbe - go
+    pub gm: f64,
+    /// Output conductance, -dIc/dVbc - gmu
+    pub go: f64,
+    /// Base-emitter input conductance dIb/dVbe
+    pub gpi: f64,
+    /// Base-collector conductance dIb/dVbc
+    pub gmu: f64,
+    /// Base resistance at this bias, modulated by the base charge
+    pub rbb: f64,
+    /// Forward diffusion current IS*(exp(Vbe/(NF*Vt)) - 1) and its derivative
+    pub forward: (f64, f64),
+    /// Reverse diffusion current IS*(exp(Vbc/(NR*Vt)) - 1) and its derivative
+    pub reverse: (f64, f64),
+    /// Normalized base charge qb and its derivatives with respect to Vbe and Vbc
+    pub qb: (f64, f64, f64),
+}
+
+/// Stored charges of the transistor at one bias point, with their capacitances
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BjtCharges {
+    /// Base-emitter charge (depletion plus forward diffusion) and dQbe/dVbe, dQbe/dVbc
+    pub qbe: (f64, f64, f64),
+    /// Base-collector charge at the internal base (depletion plus reverse diffusion) and dQbc/dVbc
+    pub qbc: (f64, f64),
+    /// Depletion charge between external base and internal collector, and its capacitance
+    pub qbx: (f64, f64),
+    /// Substrate-collector depletion charge and its capacitance
+    pub qsc: (f64, f64),
+}
+
+/// Temperature- and area-adjusted Gummel-Poon parameters, ready for evaluation.
+/// Voltages passed to the methods are in the NPN sign convention, i.e. already multiplied by `sign`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BjtInstance {
+    /// +1 for NPN, -1 for PNP
+    pub sign: f64,
+    /// Thermal voltage
+    pub vt: f64,
+    pub is: f64,
+    pub bf: f64,
+    pub nf: f64,
+    pub ise: f64,
+    pub ne: f64,
+    pub br: f64,
+    pub nr: f64,
+    pub isc: f64,
+    pub nc: f64,
+    /// 1/VAF, zero for an infinite Early voltage
+    pub inverse_vaf: f64,
+    pub inverse_var: f64,
+    /// 1/IKF, zero when there is no high-injection roll-off
+    pub inverse_ikf: f64,
+    pub inverse_ikr: f64,
+    pub rb: f64,
+    pub rbm: f64,
+    pub re: f64,
+    pub rc: f64,
+    pub cje: f64,
+    pub vje: f64,
+    pub mje: f64,
+    pub tf: f64,
+    pub xtf: f64,
+    /// 1/(1.44*VTF)
+    pub vtf_factor: f64,
+    pub itf: f64,
+    /// Part of CJC at the internal base node
+    pub cjc_internal: f64,
+    /// Part of CJC at the external base node
+    pub cjc_external: f64,
+    pub vjc: f64,
+    pub mjc: f64,
+    pub tr: f64,
+    pub cjs: f64,
+    pub vjs: f64,
+    pub mjs: f64,
+    pub fc: f64,
+    pub vcrit: f64,
+}
+
+/// Ideal diode current `is*(exp(v/nvt) - 1)` and its derivative
+fn diffusion_current(v: f64, is: f64, nvt: f64) -> (f64, f64) {
+    let exp = (v / nvt).exp();
+    (is * (exp - 1.0), is * exp / nvt)
+}
+
+impl BjtInstance {
+    /// Gummel-Poon currents at the internal junction voltages `vbe` and `vbc`
+    pub fn evaluate(&self, vbe: f64, vbc: f64) -> BjtOperatingPoint {
+        let (cbe, gbe) = diffusion_current(vbe, self.is, self.nf * self.vt);
+        let (cbc, gbc) = diffusion_current(vbc, self.is, self.nr * self.vt);
+
+        // 非理想基极电流，GMIN 并联在两个结上
+        let (mut cben, mut gben) = if self.ise > 0.0 { diffusion_current(vbe, self.ise, self.ne * self.vt) } else { (0.0, 0.0) };
+        let (mut cbcn, mut gbcn) = if self.isc > 0.0 { diffusion_current(vbc, self.isc, self.nc * self.vt) } else { (0.0, 0.0) };
+        cben += GMIN * vbe;
+        gben += GMIN;
+        cbcn += GMIN * vbc;
+        gbcn += GMIN;
+
+        // 基区电荷：q1 描述 Early 效应，q2 描述大注入
+        let q1 = 1.0 / (1.0 - self.inverse_vaf * vbc - self.inverse_var * vbe);
+        let (qb, dqb_dvbe, dqb_dvbc) = if self.inverse_ikf == 0.0 && self.inverse_ikr == 0.0 {
+            (q1, q1 * q1 * self.inverse_var, q1 * q1 * self.inverse_vaf)
+        } else {
+            let q2 = self.inverse_ikf * cbe + self.inverse_ikr * cbc;
+            let arg = (1.0 + 4.0 * q2).max(0.0);
+            let sqarg = if arg > 0.0 { arg.sqrt() } else { 1.0 };
+            let qb = q1 * (1.0 + sqarg) / 2.0;
+            (qb,
+             q1 * (qb * self.inverse_var + self.inverse_ikf * gbe / sqarg),
+             q1 * (qb * self.inverse_vaf + self.inverse_ikr * gbc / sqarg))
+        };
+
+        let ic = (cbe - cbc) / qb - cbc / self.br - cbcn;
+        let ib = cbe / self.bf + cben + cbc / self.br + cbcn;
+        let go = (gbc + (cbe - cbc) * dqb_dvbc / qb) / qb;
+        let gm = (gbe - (cbe - cbc) * dqb_dvbe / qb) / qb - go;
+        let rbb = if self.rb > 0.0 { self.rbm + (self.rb - self.rbm) / qb } else { 0.0 };
+
+        BjtOperatingPoint {
+            ic,
+            ib,
+            gm,
+            go,
+            gpi: gbe / self.bf + gben,
+            gmu: gbc / self.br + gbcn,
+            rbb,
+            forward: (cbe, gbe),
+            reverse: (cbc, gbc),
+            qb: (qb, dqb_dvbe, dqb_dvbc),
+        }
+    }
+
+    /// Junction and diffusion charges at the operating point `op` of the internal
+    /// junctions `vbe`, `vbc`, plus the external base-collector voltage `vbx`
+    /// and the substrate-collector voltage `vsc`
+    pub fn charges(&self, op: &BjtOperatingPoint, vbe: f64, vbc: f64, vbx: f64, vsc: f64) -> BjtCharges {
+        let (mut cbe, mut gbe) = op.forward;
+        let (qb, dqb_dvbe, dqb_dvbc) = op.qb;
+        let mut cross = 0.0;
+
+        // 正向渡越时间随偏置变化（XTF、VTF、ITF），扩散电荷为 TF*Ibe/qb
+        if self.tf > 0.0 && vbe > 0.0 {
+            let (mut argtf, mut arg2, mut arg3) = (0.0, 0.0, 0.0);
+            if self.xtf > 0.0 {
+                argtf = self.xtf * (vbc * self.vtf_factor).exp();
+                arg2 = argtf;
+                if self.itf > 0.0 {
+                    let temp = cbe / (cbe + self.itf);
+                    argtf *= temp * temp;
+                    arg2 = argtf * (3.0 - 2.0 * temp);
+                }
+                arg3 = cbe * argtf * self.vtf_factor;
+            }
+            let modified = cbe * (1.0 + argtf) / qb;
+            gbe = (gbe * (1.0 + arg2) - modified * dqb_dvbe) / qb;
+            cross = self.tf * (arg3 - modified * dqb_dvbc) / qb;
+            cbe = modified;
+        }
+
+        let (depletion_be, capacitance_be) = depletion_charge(vbe, self.cje, self.vje, self.mje, self.fc);
+        let (depletion_bc, capacitance_bc) = depletion_charge(vbc, self.cjc_internal, self.vjc, self.mjc, self.fc);
+        let (cbc, gbc) = op.reverse;
+
+        BjtCharges {
+            qbe: (self.tf * cbe + depletion_be, self.tf * gbe + capacitance_be, cross),
+            qbc: (self.tr * cbc + depletion_bc, self.tr * gbc + capacitance_bc),
+            qbx: depletion_charge(vbx, self.cjc_external, self.vjc, self.mjc, self.fc),
+            qsc: depletion_charge(vsc, self.cjs, self.vjs, self.mjs, self.fc),
+        }
+    }
+
+    /// Limit the internal junction voltages between Newton iterations with `pnjlim`.
+    /// Returns the `[vbe, vbc]` to use and whether either was limited.
+    pub fn limit(&self, new: [f64; 2], old: [f64; 2]) -> ([f64; 2], bool) {
+        let (vbe, limited_be) = limit_junction_voltage(new[0], old[0], self.vt, self.vcrit);
+        let (vbc, limited_bc) = limit_junction_voltage(new[1], old[1], self.vt, self.vcrit);
+        ([vbe, vbc], limited_be || limited_bc)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::params;
+
+    #[test]
+    fn test_ebers_moll_limit() {
+        // 没有 Early 效应和大注入时退化为 Ebers-Moll 模型
+        let model = BjtModel::from_params(Polarity::N, &params(&[("IS", 1e-15), ("BF", 200.0), ("VAF", 0.0)])).unwrap();
+        assert!(model.vaf.is_infinite());
+        let bjt = model.instance(2.0, NOMINAL_TEMPERATURE);
+        let op = bjt.evaluate(0.65, -5.0);
+
+        let ideal = 2e-15 * ((0.65 / bjt.vt).exp() - 1.0);
+        assert!((op.ic - ideal).abs() / ideal < 1e-6);
+        assert!((op.ic / op.ib - 200.0).abs() < 1e-2);
+        assert!((op.gm - ideal / bjt.vt).abs() / op.gm < 1e-6);
+        assert!(op.go.abs() < 1e-12);
+
+        assert!(BjtModel::from_params(Polarity::N, &params(&[("bogus", 1.0)])).is_err());
+        assert!(BjtModel::from_params(Polarity::P, &params(&[("rb", 10.0), ("rbm", 20.0)])).is_err());
+    }
+
+    #[test]
+    fn test_gummel_poon_derivatives() {
+        let model = BjtModel::from_params(Polarity::N, &params(&[
+            ("is", 1e-14), ("bf", 150.0), ("vaf", 60.0), ("var", 20.0), ("ikf", 10e-3), ("ikr", 1e-3),
+            ("ise", 1e-13), ("ne", 1.6), ("isc", 1e-12), ("br", 3.0),
+            ("tf", 0.4e-9), ("xtf", 3.0), ("vtf", 4.0), ("itf", 0.4), ("cje", 2e-12), ("cjc", 1e-12),
+        ])).unwrap();
+        let bjt = model.instance(1.0, NOMINAL_TEMPERATURE);
+        let (vbe, vbc, h) = (0.72, -2.0, 1e-7);
+        let op = bjt.evaluate(vbe, vbc);
+        let forward = bjt.evaluate(vbe + h, vbc);
+        let reverse = bjt.evaluate(vbe, vbc + h);
+
+        // 解析导数与数值导数一致
+        let close = |numeric: f64, analytic: f64| (numeric - analytic).abs() <= 1e-4 * analytic.abs().max(1e-9);
+        assert!(close((forward.ic - op.ic) / h, op.gm + op.go));
+        assert!(close((reverse.ic - op.ic) / h, -(op.gmu + op.go)));
+        assert!(close((forward.ib - op.ib) / h, op.gpi));
+        assert!(close((reverse.ib - op.ib) / h, op.gmu));
+
+        // 大注入使电流增益下降
+        let high = bjt.evaluate(0.85, vbc);
+        assert!(high.ic / high.ib < op.ic / op.ib);
+
+        let charges = bjt.charges(&op, vbe, vbc, vbc, -2.0);
+        let qbe_forward = bjt.charges(&forward, vbe + h, vbc, vbc, -2.0).qbe.0;
+        let qbe_reverse = bjt.charges(&reverse, vbe, vbc + h, vbc, -2.0).qbe.0;
+        assert!(close((qbe_forward - charges.qbe.0) / h, charges.qbe.1));
+        assert!(close((qbe_reverse - charges.qbe.0) / h, charges.qbe.2));
+        assert!(charges.qbc.1 > 0.0 && charges.qbc.1 < 1e-12);
+    }
+}
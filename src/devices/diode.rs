@@ -0,0 +1,240 @@
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+
+use super::{
+    critical_voltage, depletion_charge, junction_capacitance, junction_current, junction_potential,
+    limit_junction_voltage, thermal_voltage, CELSIUS_TO_KELVIN, GMIN, NOMINAL_TEMPERATURE,
+};
+
+/// Junction diode parameters from a `.MODEL name D(...)` card
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DiodeModel {
+    /// Saturation current (IS)
+    pub is: f64,
+    /// Emission coefficient (N)
+    pub n: f64,
+    /// Series resistance (RS)
+    pub rs: f64,
+    /// Zero-bias junction capacitance (CJO)
+    pub cjo: f64,
+    /// Junction potential (VJ)
+    pub vj: f64,
+    /// Grading coefficient (M)
+    pub m: f64,
+    /// Transit time (TT)
+    pub tt: f64,
+    /// Reverse breakdown voltage (BV), infinite when not given
+    pub bv: f64,
+    /// Current at the breakdown voltage (IBV)
+    pub ibv: f64,
+    /// Band gap energy in eV (EG)
+    pub eg: f64,
+    /// Saturation current temperature exponent (XTI)
+    pub xti: f64,
+    /// Forward-bias depletion capacitance coefficient (FC)
+    pub fc: f64,
+    /// Temperature the parameters were measured at, in °C (TNOM)
+    pub tnom: f64,
+}
+
+impl Default for DiodeModel {
+    fn default() -> Self {
+        DiodeModel {
+            is: 1e-14,
+            n: 1.0,
+            rs: 0.0,
+            cjo: 0.0,
+            vj: 1.0,
+            m: 0.5,
+            tt: 0.0,
+            bv: f64::INFINITY,
+            ibv: 1e-3,
+            eg: 1.11,
+            xti: 3.0,
+            fc: 0.5,
+            tnom: NOMINAL_TEMPERATURE,
+        }
+    }
+}
+
+impl DiodeModel {
+    /// Build a model from `name=value` pairs, starting from the SPICE defaults
+    pub fn from_params(params: &[(String, f64)]) -> Result<DiodeModel> {
+        let mut model = DiodeModel::default();
+        for (name, value) in params {
+            let field = match name.to_lowercase().as_str() {
+                "is" => &mut model.is,
+                "n" => &mut model.n,
+                "rs" => &mut model.rs,
+                "cjo" | "cj0" | "cj" => &mut model.cjo,
+                "vj" | "pb" => &mut model.vj,
+                "m" | "mj" => &mut model.m,
+                "tt" => &mut model.tt,
+                "bv" => &mut model.bv,
+                "ibv" => &mut model.ibv,
+                "eg" => &mut model.eg,
+                "xti" => &mut model.xti,
+                "fc" => &mut model.fc,
+                "tnom" => &mut model.tnom,
+                // 不影响本模型的参数
+                "level" | "kf" | "af" => continue,
+                other => return Err(anyhow!("Unknown diode model parameter: {}", other)),
+            };
+            *field = *value;
+        }
+        model.validate()?;
+        Ok(model)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.is <= 0.0 || self.n <= 0.0 || self.ibv <= 0.0 {
+            return Err(anyhow!("Diode IS, N and IBV must be positive"));
+        }
+        if self.rs < 0.0 || self.cjo < 0.0 || self.tt < 0.0 {
+            return Err(anyhow!("Diode RS, CJO and TT must not be negative"));
+        }
+        if self.vj <= 0.0 || !(0.0..1.0).contains(&self.m) || !(0.0..1.0).contains(&self.fc) {
+            return Err(anyhow!("Diode VJ must be positive, and M and FC between 0 and 1"));
+        }
+        if self.bv <= 0.0 {
+            return Err(anyhow!("Diode BV must be positive"));
+        }
+        Ok(())
+    }
+
+    /// Parameters of a diode instance with the given area, adjusted to `temperature` in °C
+    pub fn instance(&self, area: f64, temperature: f64) -> DiodeInstance {
+        let t = temperature + CELSIUS_TO_KELVIN;
+        let tnom = self.tnom + CELSIUS_TO_KELVIN;
+        let ratio = t / tnom;
+        let vt = thermal_voltage(temperature);
+        let nvt = self.n * vt;
+
+        // IS(T) = IS * (T/Tnom)^(XTI/N) * exp(EG/(N*Vt) * (T/Tnom - 1))
+        let is = area * self.is * ratio.powf(self.xti / self.n) * (self.eg / nvt * (ratio - 1.0)).exp();
+        let vj = junction_potential(self.vj, self.tnom, temperature);
+        let cjo = area * junction_capacitance(self.cjo, self.m, self.vj, vj, self.tnom, temperature);
+
+        // 击穿电压按 IBV 修正，使 vd = -BV 时电流恰好为 IBV
+        let breakdown = self.bv.is_finite()
+            .then(|| self.bv - nvt * (area * self.ibv / is).ln().max(0.0));
+
+        DiodeInstance {
+            is,
+            nvt,
+            rs: self.rs / area,
+            cjo,
+            vj,
+            m: self.m,
+            tt: self.tt,
+            fc: self.fc,
+            breakdown,
+            vcrit: critical_voltage(is, nvt),
+        }
+    }
+}
+
+/// Temperature- and area-adjusted diode parameters, ready for evaluation
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiodeInstance {
+    pub is: f64,
+    /// Emission coefficient times thermal voltage
+    pub nvt: f64,
+    pub rs: f64,
+    pub cjo: f64,
+    pub vj: f64,
+    pub m: f64,
+    pub tt: f64,
+    pub fc: f64,
+    /// Effective breakdown voltage, if the model has one
+    pub breakdown: Option<f64>,
+    pub vcrit: f64,
+}
+
+impl DiodeInstance {
+    /// Junction current and conductance (including GMIN) at junction voltage `vd`
+    pub fn current(&self, vd: f64) -> (f64, f64) {
+        if vd >= -3.0 * self.nvt {
+            return junction_current(vd, self.is, self.nvt);
+        }
+        match self.breakdown {
+            Some(xbv) if vd < -xbv => {
+                let exp = (-(xbv + vd) / self.nvt).exp();
+                (-self.is * exp + GMIN * vd, self.is * exp / self.nvt + GMIN)
+            }
+            _ => {
+                // SPICE 的反偏近似，在 -3nVt 处与正向特性平滑连接
+                let arg = (3.0 * self.nvt / (vd * std::f64::consts::E)).powi(3);
+                (-self.is * (1.0 + arg) + GMIN * vd, self.is * 3.0 * arg / vd + GMIN)
+            }
+        }
+    }
+
+    /// Stored charge and capacitance at junction voltage `vd`: depletion charge
+    /// (linearized above FC*VJ) plus diffusion charge TT*Id
+    pub fn charge(&self, vd: f64) -> (f64, f64) {
+        let (current, conductance) = self.current(vd);
+        let (depletion, depletion_capacitance) = depletion_charge(vd, self.cjo, self.vj, self.m, self.fc);
+        (self.tt * current + depletion, self.tt * conductance + depletion_capacitance)
+    }
+
+    /// Limit the junction voltage step from `vold` to `vnew`, in forward bias and in breakdown
+    pub fn limit(&self, vnew: f64, vold: f64) -> (f64, bool) {
+        match self.breakdown {
+            Some(xbv) if vnew < (10.0 * self.nvt - xbv).min(0.0) => {
+                let (limited, was_limited) = limit_junction_voltage(-(vnew + xbv), -(vold + xbv), self.nvt, self.vcrit);
+                (-(limited + xbv), was_limited)
+            }
+            _ => limit_junction_voltage(vnew, vold, self.nvt, self.vcrit),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::params;
+
+    #[test]
+    fn test_model_parameters() {
+        let model = DiodeModel::from_params(&params(&[("IS", 2.52e-9), ("Rs", 0.568), ("cjo", 4e-12)])).unwrap();
+        assert_eq!(model.is, 2.52e-9);
+        assert_eq!(model.rs, 0.568);
+        assert_eq!(model.n, 1.0);
+        assert!(DiodeModel::from_params(&params(&[("bogus", 1.0)])).is_err());
+        assert!(DiodeModel::from_params(&params(&[("m", 1.0)])).is_err());
+
+        // 额定温度下不做修正，面积按比例缩放
+        let instance = model.instance(2.0, NOMINAL_TEMPERATURE);
+        assert!((instance.is - 5.04e-9).abs() < 1e-20);
+        assert!((instance.rs - 0.284).abs() < 1e-12);
+        assert!((instance.vj - 1.0).abs() < 1e-12);
+
+        // 温度升高 IS 增大
+        let hot = model.instance(1.0, 127.0);
+        assert!(hot.is > 100.0 * model.is);
+        assert!(hot.vj < model.vj);
+    }
+
+    #[test]
+    fn test_breakdown_and_charge() {
+        let model = DiodeModel::from_params(&params(&[("bv", 5.0), ("ibv", 1e-3), ("cjo", 1e-12), ("tt", 1e-9)])).unwrap();
+        let diode = model.instance(1.0, NOMINAL_TEMPERATURE);
+
+        let (current, _) = diode.current(-5.0);
+        assert!((current + 1e-3).abs() < 1e-9, "{}", current);
+        let (current, _) = diode.current(-1.0);
+        assert!(current.abs() < 1e-11);
+
+        // 零偏时电容等于 CJO，电荷连续
+        let (charge, capacitance) = diode.charge(0.0);
+        assert!(charge.abs() < 1e-25);
+        assert!((capacitance - 1e-12).abs() < 1e-15);
+        let below = diode.charge(0.5 - 1e-9).0;
+        let above = diode.charge(0.5 + 1e-9).0;
+        assert!((above - below).abs() < 1e-20);
+
+        let (limited, was_limited) = diode.limit(-20.0, -4.9);
+        assert!(was_limited && limited > -6.0);
+    }
+}
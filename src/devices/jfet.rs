@@ -0,0 +1,203 @@
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+
+use super::{DiodeInstance, DiodeModel, Polarity, NOMINAL_TEMPERATURE};
+
+/// JFET parameters from a `.MODEL name NJF|PJF(...)` card
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JfetModel {
+    pub polarity: Polarity,
+    /// Threshold (pinch-off) voltage (VTO); as in SPICE it is not negated for PJF
+    pub vto: f64,
+    /// Transconductance parameter in A/V² (BETA)
+    pub beta: f64,
+    /// Channel-length modulation in 1/V (LAMBDA)
+    pub lambda: f64,
+    /// Gate junction saturation current (IS)
+    pub is: f64,
+    /// Drain ohmic resistance (RD)
+    pub rd: f64,
+    /// Source ohmic resistance (RS)
+    pub rs: f64,
+    /// Zero-bias gate-source junction capacitance (CGS)
+    pub cgs: f64,
+    /// Zero-bias gate-drain junction capacitance (CGD)
+    pub cgd: f64,
+    /// Gate junction potential (PB)
+    pub pb: f64,
+    /// Forward-bias depletion capacitance coefficient (FC)
+    pub fc: f64,
+    /// Temperature the parameters were measured at, in °C (TNOM)
+    pub tnom: f64,
+}
+
+impl Default for JfetModel {
+    fn default() -> Self {
+        JfetModel {
+            polarity: Polarity::N,
+            vto: -2.0,
+            beta: 1e-4,
+            lambda: 0.0,
+            is: 1e-14,
+            rd: 0.0,
+            rs: 0.0,
+            cgs: 0.0,
+            cgd: 0.0,
+            pb: 1.0,
+            fc: 0.5,
+            tnom: NOMINAL_TEMPERATURE,
+        }
+    }
+}
+
+impl JfetModel {
+    /// Build a model of the given polarity from `name=value` pairs, starting from the SPICE defaults
+    pub fn from_params(polarity: Polarity, params: &[(String, f64)]) -> Result<JfetModel> {
+        let mut model = JfetModel { polarity, ..JfetModel::default() };
+        for (name, value) in params {
+            let field = match name.to_lowercase().as_str() {
+                "vto" | "vt0" => &mut model.vto,
+                "beta" => &mut model.beta,
+                "lambda" => &mut model.lambda,
+                "is" => &mut model.is,
+                "rd" => &mut model.rd,
+                "rs" => &mut model.rs,
+                "cgs" => &mut model.cgs,
+                "cgd" => &mut model.cgd,
+                "pb" => &mut model.pb,
+                "fc" => &mut model.fc,
+                "tnom" => &mut model.tnom,
+                // 不影响本模型的参数
+                "level" | "kf" | "af" => continue,
+                other => return Err(anyhow!("Unknown JFET model parameter: {}", other)),
+            };
+            *field = *value;
+        }
+        model.validate()?;
+        Ok(model)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.beta <= 0.0 || self.is <= 0.0 || self.pb <= 0.0 {
+            return Err(anyhow!("JFET BETA, IS and PB must be positive"));
+        }
+        if [self.lambda, self.rd, self.rs, self.cgs, self.cgd].iter().any(|&v| v < 0.0) {
+            return Err(anyhow!("JFET LAMBDA, RD, RS, CGS and CGD must not be negative"));
+        }
+        if !(0.0..1.0).contains(&self.fc) {
+            return Err(anyhow!("JFET FC must be between 0 and 1"));
+        }
+        Ok(())
+    }
+
+    /// Parameters of a transistor with the given `area` factor at `temperature` in °C.
+    /// The gate junctions follow the temperature like a diode; VTO and BETA do not.
+    pub fn instance(&self, area: f64, temperature: f64) -> JfetInstance {
+        let junction = |cj0: f64| DiodeModel {
+            is: self.is,
+            cjo: cj0,
+            vj: self.pb,
+            m: 0.5,
+            fc: self.fc,
+            tnom: self.tnom,
+            ..DiodeModel::default()
+        }.instance(area, temperature);
+
+        JfetInstance {
+            sign: self.polarity.sign(),
+            vto: self.vto,
+            beta: area * self.beta,
+            lambda: self.lambda,
+            rd: self.rd / area,
+            rs: self.rs / area,
+            gate_source: junction(self.cgs),
+            gate_drain: junction(self.cgd),
+        }
+    }
+}
+
+/// Area- and temperature-adjusted JFET parameters, ready for evaluation.
+/// Voltages passed to the methods are in the n-channel sign convention.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JfetInstance {
+    /// +1 for NJF, -1 for PJF
+    pub sign: f64,
+    pub vto: f64,
+    pub beta: f64,
+    pub lambda: f64,
+    pub rd: f64,
+    pub rs: f64,
+    pub gate_source: DiodeInstance,
+    pub gate_drain: DiodeInstance,
+}
+
+impl JfetInstance {
+    /// Quadratic (Shichman-Hodges) drain current in normal mode (`vds >= 0`),
+    /// returned as `(ids, gm, gds)`
+    pub fn channel(&self, vgs: f64, vds: f64) -> (f64, f64, f64) {
+        let vgst = vgs - self.vto;
+        if vgst <= 0.0 {
+            return (0.0, 0.0, 0.0);
+        }
+
+        let betap = self.beta * (1.0 + self.lambda * vds);
+        if vgst >= vds {
+            // 线性区
+            let ids = betap * vds * (2.0 * vgst - vds);
+            (ids,
+             2.0 * betap * vds,
+             2.0 * betap * (vgst - vds) + self.beta * self.lambda * vds * (2.0 * vgst - vds))
+        } else {
+            // 饱和区
+            (betap * vgst * vgst, 2.0 * betap * vgst, self.beta * self.lambda * vgst * vgst)
+        }
+    }
+
+    /// Limit the gate junction voltages `[vgs, vgd]` between Newton iterations.
+    /// Returns the voltages to use and whether either was limited.
+    pub fn limit(&self, new: [f64; 2], old: [f64; 2]) -> ([f64; 2], bool) {
+        let (vgs, limited_gs) = self.gate_source.limit(new[0], old[0]);
+        let (vgd, limited_gd) = self.gate_drain.limit(new[1], old[1]);
+        ([vgs, vgd], limited_gs || limited_gd)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::params;
+
+    #[test]
+    fn test_drain_current() {
+        let model = JfetModel::from_params(Polarity::N, &params(&[("VTO", -2.0), ("BETA", 1e-3), ("LAMBDA", 0.01)])).unwrap();
+        let jfet = model.instance(1.0, NOMINAL_TEMPERATURE);
+
+        // 夹断、饱和（IDSS = BETA * VTO^2）与线性区
+        assert_eq!(jfet.channel(-2.5, 5.0), (0.0, 0.0, 0.0));
+        let (ids, gm, gds) = jfet.channel(0.0, 10.0);
+        assert!((ids - 4e-3 * 1.1).abs() < 1e-12);
+        assert!((gm - 4e-3 * 1.1).abs() < 1e-12);
+        assert!((gds - 4e-5).abs() < 1e-12);
+
+        let h = 1e-7;
+        let (ids, gm, gds) = jfet.channel(-1.0, 0.5);
+        assert!(((jfet.channel(-1.0 + h, 0.5).0 - ids) / h - gm).abs() < 1e-8);
+        assert!(((jfet.channel(-1.0, 0.5 + h).0 - ids) / h - gds).abs() < 1e-8);
+        let edge = jfet.channel(-1.0, 1.0).0;
+        assert!((edge - jfet.channel(-1.0, 1.0 + 1e-9).0).abs() < 1e-11);
+    }
+
+    #[test]
+    fn test_model_parameters() {
+        let model = JfetModel::from_params(Polarity::P, &params(&[("rd", 10.0), ("cgs", 2e-12), ("is", 1e-15)])).unwrap();
+        let jfet = model.instance(2.0, NOMINAL_TEMPERATURE);
+        assert_eq!(jfet.sign, -1.0);
+        assert_eq!(jfet.rd, 5.0);
+        assert!((jfet.gate_source.is - 2e-15).abs() < 1e-25);
+        assert!((jfet.gate_source.cjo - 4e-12).abs() < 1e-22);
+        assert_eq!(jfet.gate_drain.cjo, 0.0);
+
+        assert!(JfetModel::from_params(Polarity::N, &params(&[("vgs", 1.0)])).is_err());
+        assert!(JfetModel::from_params(Polarity::N, &params(&[("beta", 0.0)])).is_err());
+    }
+}
@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+
+pub mod bjt;
+pub mod diode;
+pub mod jfet;
+pub mod mosfet;
+
+pub use bjt::{BjtInstance, BjtModel};
+pub use diode::{DiodeInstance, DiodeModel};
+pub use jfet::{JfetInstance, JfetModel};
+pub use mosfet::{MosfetInstance, MosfetModel};
+
+/// Boltzmann constant over electron charge, in V/K
+pub const BOLTZMANN_OVER_Q: f64 = 8.617_333e-5;
+
+/// Offset between degrees Celsius and kelvin
+pub const CELSIUS_TO_KELVIN: f64 = 273.15;
+
+/// Nominal temperature of model parameters, in °C
+pub const NOMINAL_TEMPERATURE: f64 = 27.0;
+
+/// Minimum conductance placed across every pn junction to keep the matrix nonsingular
+pub const GMIN: f64 = 1e-12;
+
+/// Thermal voltage kT/q at `temperature` in °C
+pub fn thermal_voltage(temperature: f64) -> f64 {
+    BOLTZMANN_OVER_Q * (temperature + CELSIUS_TO_KELVIN)
+}
+
+/// Silicon band gap in eV at `temperature` in kelvin
+pub fn silicon_band_gap(kelvin: f64) -> f64 {
+    1.16 - 7.02e-4 * kelvin * kelvin / (kelvin + 1108.0)
+}
+
+/// Current and small-signal conductance of an ideal pn junction (plus GMIN) at voltage `vd`,
+/// where `nvt` is the emission coefficient times the thermal voltage
+pub fn junction_current(vd: f64, saturation_current: f64, nvt: f64) -> (f64, f64) {
+    let exp = (vd / nvt).exp();
+    let current = saturation_current * (exp - 1.0) + GMIN * vd;
+    let conductance = saturation_current * exp / nvt + GMIN;
+    (current, conductance)
+}
+
+/// Junction voltage above which the exponential starts to dominate and limiting kicks in
+pub fn critical_voltage(saturation_current: f64, nvt: f64) -> f64 {
+    nvt * (nvt / (std::f64::consts::SQRT_2 * saturation_current)).ln()
+}
+
+/// Built-in junction potential at `temperature`, scaled from its value `vj` at `tnom` (both in °C)
+pub fn junction_potential(vj: f64, tnom: f64, temperature: f64) -> f64 {
+    let t = temperature + CELSIUS_TO_KELVIN;
+    let t0 = tnom + CELSIUS_TO_KELVIN;
+    let ratio = t / t0;
+    vj * ratio - 3.0 * thermal_voltage(temperature) * ratio.ln() - silicon_band_gap(t0) * ratio + silicon_band_gap(t)
+}
+
+/// Zero-bias junction capacitance at `temperature`, given the grading coefficient `m`
+/// and the junction potential `vj` at `tnom` and `vj_t` at `temperature`
+pub fn junction_capacitance(cj0: f64, m: f64, vj: f64, vj_t: f64, tnom: f64, temperature: f64) -> f64 {
+    cj0 * (1.0 + m * (4e-4 * (temperature - tnom) - (vj_t / vj - 1.0)))
+}
+
+/// Depletion charge and capacitance of a junction at voltage `v`,
+/// linearized above `fc*vj` so the capacitance stays finite in forward bias
+pub fn depletion_charge(v: f64, cj0: f64, vj: f64, m: f64, fc: f64) -> (f64, f64) {
+    if cj0 <= 0.0 {
+        return (0.0, 0.0);
+    }
+    if v < fc * vj {
+        let arg = 1.0 - v / vj;
+        (cj0 * vj / (1.0 - m) * (1.0 - arg.powf(1.0 - m)), cj0 * arg.powf(-m))
+    } else {
+        let f1 = vj / (1.0 - m) * (1.0 - (1.0 - fc).powf(1.0 - m));
+        let f2 = (1.0 - fc).powf(1.0 + m);
+        let f3 = 1.0 - fc * (1.0 + m);
+        let vfc = fc * vj;
+        (cj0 * (f1 + (f3 * (v - vfc) + m / (2.0 * vj) * (v * v - vfc * vfc)) / f2),
+         cj0 / f2 * (f3 + m * v / vj))
+    }
+}
+
+/// SPICE `pnjlim`: limit the change of a junction voltage between Newton iterations
+/// so the exponential cannot overflow. Returns the voltage to use and whether it was limited.
+pub fn limit_junction_voltage(vnew: f64, vold: f64, nvt: f64, vcrit: f64) -> (f64, bool) {
+    if vnew <= vcrit || (vnew - vold).abs() <= 2.0 * nvt {
+        return (vnew, false);
+    }
+
+    let limited = if vold > 0.0 {
+        let arg = 1.0 + (vnew - vold) / nvt;
+        if arg > 0.0 { vold + nvt * arg.ln() } else { vcrit }
+    } else {
+        nvt * (vnew / nvt).ln()
+    };
+    (limited, true)
+}
+
+/// Device polarity: N-type devices (NMOS, NPN, NJF) use the model equations as written,
+/// P-type devices (PMOS, PNP, PJF) with all terminal voltages and currents negated
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Polarity {
+    N,
+    P,
+}
+
+impl Polarity {
+    /// +1 for N-type, -1 for P-type
+    pub fn sign(self) -> f64 {
+        match self {
+            Polarity::N => 1.0,
+            Polarity::P => -1.0,
+        }
+    }
+}
+
+/// Semiconductor device model defined by a `.MODEL` card
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum DeviceModel {
+    Diode(DiodeModel),
+    Mosfet(MosfetModel),
+    Bjt(BjtModel),
+    Jfet(JfetModel),
+}
+
+impl DeviceModel {
+    /// Build a model from the type and `name=value` parameters of a `.MODEL` card
+    pub fn from_card(kind: &str, params: &[(String, f64)]) -> Result<DeviceModel> {
+        match kind.to_uppercase().as_str() {
+            "D" => Ok(DeviceModel::Diode(DiodeModel::from_params(params)?)),
+            "NMOS" => Ok(DeviceModel::Mosfet(MosfetModel::from_params(Polarity::N, params)?)),
+            "PMOS" => Ok(DeviceModel::Mosfet(MosfetModel::from_params(Polarity::P, params)?)),
+            "NPN" => Ok(DeviceModel::Bjt(BjtModel::from_params(Polarity::N, params)?)),
+            "PNP" => Ok(DeviceModel::Bjt(BjtModel::from_params(Polarity::P, params)?)),
+            "NJF" => Ok(DeviceModel::Jfet(JfetModel::from_params(Polarity::N, params)?)),
+            "PJF" => Ok(DeviceModel::Jfet(JfetModel::from_params(Polarity::P, params)?)),
+            other => Err(anyhow!("Unsupported model type: {}", other)),
+        }
+    }
+
+    /// Model type as written on the `.MODEL` card
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DeviceModel::Diode(_) => "D",
+            DeviceModel::Mosfet(model) => match model.polarity {
+                Polarity::N => "NMOS",
+                Polarity::P => "PMOS",
+            },
+            DeviceModel::Bjt(model) => match model.polarity {
+                Polarity::N => "NPN",
+                Polarity::P => "PNP",
+            },
+            DeviceModel::Jfet(model) => match model.polarity {
+                Polarity::N => "NJF",
+                Polarity::P => "PJF",
+            },
+        }
+    }
+}
+
+/// Model parameters as the parser hands them over, for the device tests
+#[cfg(test)]
+pub(crate) fn params(pairs: &[(&str, f64)]) -> Vec<(String, f64)> {
+    pairs.iter().map(|(name, value)| (name.to_string(), *value)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const THERMAL_VOLTAGE: f64 = 0.025865;
+
+    #[test]
+    fn test_junction_current() {
+        let (current, conductance) = junction_current(0.6, 1e-14, THERMAL_VOLTAGE);
+        let expected = 1e-14 * ((0.6 / THERMAL_VOLTAGE).exp() - 1.0) + GMIN * 0.6;
+        assert!((current - expected).abs() / expected < 1e-9);
+        assert!((conductance - (expected + 1e-14) / THERMAL_VOLTAGE).abs() / conductance < 1e-6);
+
+        let (reverse, _) = junction_current(-5.0, 1e-14, THERMAL_VOLTAGE);
+        assert!((reverse + 1e-14).abs() < 1e-11);
+        assert!((thermal_voltage(NOMINAL_TEMPERATURE) - 0.025865).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_limit_junction_voltage() {
+        let vcrit = critical_voltage(1e-14, THERMAL_VOLTAGE);
+        assert!(vcrit > 0.6 && vcrit < 0.8);
+
+        // 小步长或低于临界电压时不限制
+        assert_eq!(limit_junction_voltage(0.5, 0.0, THERMAL_VOLTAGE, vcrit), (0.5, false));
+        assert_eq!(limit_junction_voltage(0.75, 0.74, THERMAL_VOLTAGE, vcrit), (0.75, false));
+
+        let (limited, was_limited) = limit_junction_voltage(10.0, 0.7, THERMAL_VOLTAGE, vcrit);
+        assert!(was_limited);
+        assert!(limited > 0.7 && limited < 1.0);
+    }
+}
@@ -0,0 +1,432 @@
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+
+use super::{junction_potential, silicon_band_gap, DiodeInstance, DiodeModel, Polarity, CELSIUS_TO_KELVIN, NOMINAL_TEMPERATURE};
+
+/// Permittivity of silicon dioxide, in F/m
+const OXIDE_PERMITTIVITY: f64 = 3.9 * 8.854_214_871e-12;
+
+/// Channel width and length used when an instance does not give W or L, in meters
+pub const DEFAULT_CHANNEL_SIZE: f64 = 100e-6;
+
+/// Level-1 (Shichman-Hodges) MOSFET parameters from a `.MODEL name NMOS|PMOS(...)` card
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MosfetModel {
+    pub polarity: Polarity,
+    /// Zero-bias threshold voltage (VTO), negative for enhancement PMOS
+    pub vto: f64,
+    /// Transconductance parameter in A/V² (KP)
+    pub kp: f64,
+    /// Body-effect coefficient in √V (GAMMA)
+    pub gamma: f64,
+    /// Surface potential (PHI)
+    pub phi: f64,
+    /// Channel-length modulation in 1/V (LAMBDA)
+    pub lambda: f64,
+    /// Gate oxide thickness in meters (TOX); zero disables the intrinsic gate capacitances
+    pub tox: f64,
+    /// Surface mobility in cm²/Vs (U0), used for KP when only TOX is given
+    pub u0: f64,
+    /// Lateral diffusion (LD)
+    pub ld: f64,
+    /// Gate-source overlap capacitance per meter of width (CGSO)
+    pub cgso: f64,
+    /// Gate-drain overlap capacitance per meter of width (CGDO)
+    pub cgdo: f64,
+    /// Gate-bulk overlap capacitance per meter of length (CGBO)
+    pub cgbo: f64,
+    /// Zero-bias bulk-drain junction capacitance (CBD)
+    pub cbd: f64,
+    /// Zero-bias bulk-source junction capacitance (CBS)
+    pub cbs: f64,
+    /// Bulk junction saturation current (IS)
+    pub is: f64,
+    /// Bulk junction potential (PB)
+    pub pb: f64,
+    /// Bulk junction grading coefficient (MJ)
+    pub mj: f64,
+    /// Forward-bias depletion capacitance coefficient (FC)
+    pub fc: f64,
+    /// Temperature the parameters were measured at, in °C (TNOM)
+    pub tnom: f64,
+}
+
+impl Default for MosfetModel {
+    fn default() -> Self {
+        MosfetModel {
+            polarity: Polarity::N,
+            vto: 0.0,
+            kp: 2e-5,
+            gamma: 0.0,
+            phi: 0.6,
+            lambda: 0.0,
+            tox: 0.0,
+            u0: 600.0,
+            ld: 0.0,
+            cgso: 0.0,
+            cgdo: 0.0,
+            cgbo: 0.0,
+            cbd: 0.0,
+            cbs: 0.0,
+            is: 1e-14,
+            pb: 0.8,
+            mj: 0.5,
+            fc: 0.5,
+            tnom: NOMINAL_TEMPERATURE,
+        }
+    }
+}
+
+impl MosfetModel {
+    /// Build a model of the given polarity from `name=value` pairs, starting from the SPICE defaults.
+    /// Without KP, a given TOX derives it from U0 as U0*COX.
+    pub fn from_params(polarity: Polarity, params: &[(String, f64)]) -> Result<MosfetModel> {
+        let mut model = MosfetModel { polarity, ..MosfetModel::default() };
+        let mut kp_given = false;
+        for (name, value) in params {
+            let field = match name.to_lowercase().as_str() {
+                "vto" | "vt0" => &mut model.vto,
+                "kp" => {
+                    kp_given = true;
+                    &mut model.kp
+                }
+                "gamma" => &mut model.gamma,
+                "phi" => &mut model.phi,
+                "lambda" => &mut model.lambda,
+                "tox" => &mut model.tox,
+                "u0" | "uo" => &mut model.u0,
+                "ld" => &mut model.ld,
+                "cgso" => &mut model.cgso,
+                "cgdo" => &mut model.cgdo,
+                "cgbo" => &mut model.cgbo,
+                "cbd" => &mut model.cbd,
+                "cbs" => &mut model.cbs,
+                "is" => &mut model.is,
+                "pb" => &mut model.pb,
+                "mj" => &mut model.mj,
+                "fc" => &mut model.fc,
+                "tnom" => &mut model.tnom,
+                "level" if *value == 1.0 => continue,
+                "level" => return Err(anyhow!("Only LEVEL=1 MOSFET models are supported")),
+                // 不影响本模型的参数
+                "kf" | "af" => continue,
+                other => return Err(anyhow!("Unknown MOSFET model parameter: {}", other)),
+            };
+            *field = *value;
+        }
+        if !kp_given && model.tox > 0.0 {
+            model.kp = model.u0 * 1e-4 * OXIDE_PERMITTIVITY / model.tox;
+        }
+        model.validate()?;
+        Ok(model)
+    }
+
+    fn validate(&self) -> Result<()> {
+        if self.kp <= 0.0 || self.phi <= 0.0 || self.is <= 0.0 || self.pb <= 0.0 {
+            return Err(anyhow!("MOSFET KP, PHI, IS and PB must be positive"));
+        }
+        if self.gamma < 0.0 || self.lambda < 0.0 || self.tox < 0.0 || self.ld < 0.0 {
+            return Err(anyhow!("MOSFET GAMMA, LAMBDA, TOX and LD must not be negative"));
+        }
+        if [self.cgso, self.cgdo, self.cgbo, self.cbd, self.cbs].iter().any(|&c| c < 0.0) {
+            return Err(anyhow!("MOSFET capacitances must not be negative"));
+        }
+        if !(0.0..1.0).contains(&self.mj) || !(0.0..1.0).contains(&self.fc) {
+            return Err(anyhow!("MOSFET MJ and FC must be between 0 and 1"));
+        }
+        Ok(())
+    }
+
+    /// Parameters of a transistor with channel `width` x `length`, adjusted to `temperature` in °C.
+    /// Mobility, surface potential, threshold and the bulk junctions follow the temperature.
+    pub fn instance(&self, width: f64, length: f64, temperature: f64) -> Result<MosfetInstance> {
+        let effective_length = length - 2.0 * self.ld;
+        if width <= 0.0 || effective_length <= 0.0 {
+            return Err(anyhow!("MOSFET needs a positive width and effective length (L - 2*LD)"));
+        }
+
+        let t = temperature + CELSIUS_TO_KELVIN;
+        let tnom = self.tnom + CELSIUS_TO_KELVIN;
+        let ratio = t / tnom;
+        let sign = self.polarity.sign();
+
+        let kp = self.kp * ratio.powf(-1.5);
+        let phi = junction_potential(self.phi, self.tnom, temperature);
+        // 阈值电压随表面势和禁带宽度变化，按 n 沟道符号约定存储
+        let vbi = sign * self.vto - self.gamma * self.phi.sqrt()
+            + 0.5 * (phi - self.phi)
+            + sign * 0.5 * (silicon_band_gap(tnom) - silicon_band_gap(t));
+
+        let junction = |cj0: f64| DiodeModel {
+            is: self.is,
+            cjo: cj0,
+            vj: self.pb,
+            m: self.mj,
+            fc: self.fc,
+            tnom: self.tnom,
+            ..DiodeModel::default()
+        }.instance(1.0, temperature);
+
+        Ok(MosfetInstance {
+            sign,
+            vbi,
+            beta: kp * width / effective_length,
+            gamma: self.gamma,
+            phi,
+            lambda: self.lambda,
+            cox: if self.tox > 0.0 { OXIDE_PERMITTIVITY / self.tox * width * effective_length } else { 0.0 },
+            cgs_overlap: self.cgso * width,
+            cgd_overlap: self.cgdo * width,
+            cgb_overlap: self.cgbo * effective_length,
+            bulk_drain: junction(self.cbd),
+            bulk_source: junction(self.cbs),
+        })
+    }
+}
+
+/// Channel current and its derivatives at one bias point
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ChannelOperatingPoint {
+    /// Drain-to-source current
+    pub ids: f64,
+    /// dIds/dVgs
+    pub gm: f64,
+    /// dIds/dVds
+    pub gds: f64,
+    /// dIds/dVbs
+    pub gmbs: f64,
+}
+
+/// Temperature- and geometry-adjusted MOSFET parameters, ready for evaluation.
+/// Voltages passed to the methods are in the n-channel sign convention,
+/// i.e. already multiplied by `sign`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MosfetInstance {
+    /// +1 for NMOS, -1 for PMOS
+    pub sign: f64,
+    /// Built-in part of the threshold voltage: VTH = vbi + GAMMA*sqrt(PHI - VBS)
+    pub vbi: f64,
+    /// KP * W / Leff
+    pub beta: f64,
+    pub gamma: f64,
+    pub phi: f64,
+    pub lambda: f64,
+    /// Total gate oxide capacitance COX * W * Leff
+    pub cox: f64,
+    pub cgs_overlap: f64,
+    pub cgd_overlap: f64,
+    pub cgb_overlap: f64,
+    pub bulk_drain: DiodeInstance,
+    pub bulk_source: DiodeInstance,
+}
+
+impl MosfetInstance {
+    /// sqrt(PHI - vbs) and its derivative, linearized for forward body bias as in SPICE
+    fn body_factor(&self, vbs: f64) -> (f64, f64) {
+        if vbs <= 0.0 {
+            let sarg = (self.phi - vbs).sqrt();
+            (sarg, -0.5 / sarg)
+        } else {
+            let sphi = self.phi.sqrt();
+            let sarg = sphi - vbs / (2.0 * sphi);
+            if sarg > 0.0 { (sarg, -0.5 / sphi) } else { (0.0, 0.0) }
+        }
+    }
+
+    /// Threshold voltage including the body effect
+    pub fn threshold(&self, vbs: f64) -> f64 {
+        self.vbi + self.gamma * self.body_factor(vbs).0
+    }
+
+    /// Shichman-Hodges drain current in normal mode (`vds >= 0`)
+    pub fn channel(&self, vgs: f64, vds: f64, vbs: f64) -> ChannelOperatingPoint {
+        let (sarg, dsarg) = self.body_factor(vbs);
+        let vgst = vgs - self.vbi - self.gamma * sarg;
+        if vgst <= 0.0 {
+            return ChannelOperatingPoint { ids: 0.0, gm: 0.0, gds: 0.0, gmbs: 0.0 };
+        }
+
+        let modulation = 1.0 + self.lambda * vds;
+        let (ids, gm, gds) = if vgst <= vds {
+            // 饱和区
+            let ids = 0.5 * self.beta * vgst * vgst;
+            (ids * modulation, self.beta * vgst * modulation, self.lambda * ids)
+        } else {
+            // 线性区
+            let ids = self.beta * vds * (vgst - 0.5 * vds);
+            (ids * modulation,
+             self.beta * vds * modulation,
+             self.beta * (vgst - vds) * modulation + self.lambda * ids)
+        };
+        // dVth/dVbs = GAMMA * dsarg/dVbs
+        ChannelOperatingPoint { ids, gm, gds, gmbs: -gm * self.gamma * dsarg }
+    }
+
+    /// Meyer gate capacitances (Cgs, Cgd, Cgb) in normal mode, without the overlap capacitances
+    pub fn gate_capacitances(&self, vgs: f64, vds: f64, vbs: f64) -> (f64, f64, f64) {
+        let (cox, phi) = (self.cox, self.phi);
+        let vgst = vgs - self.threshold(vbs);
+        if vgst <= -phi {
+            (0.0, 0.0, cox)
+        } else if vgst <= -0.5 * phi {
+            (0.0, 0.0, -vgst * cox / phi)
+        } else if vgst <= 0.0 {
+            (2.0 * cox / 3.0 + 4.0 * vgst * cox / (3.0 * phi), 0.0, -vgst * cox / phi)
+        } else if vgst <= vds {
+            (2.0 * cox / 3.0, 0.0, 0.0)
+        } else {
+            let vddif = 2.0 * vgst - vds;
+            let vddif1 = vgst - vds;
+            let cgs = 2.0 * cox / 3.0 * (1.0 - vddif1 * vddif1 / (vddif * vddif));
+            let cgd = 2.0 * cox / 3.0 * (1.0 - vgst * vgst / (vddif * vddif));
+            (cgs, cgd, 0.0)
+        }
+    }
+
+    /// SPICE voltage limiting between Newton iterations for the bias `[vgs, vds, vbs]`:
+    /// `fetlim` on the gate voltage, `limvds` on the drain voltage and `pnjlim`
+    /// on the forward-biased bulk junction. Returns the bias to use and whether it was limited.
+    pub fn limit(&self, new: [f64; 3], old: [f64; 3]) -> ([f64; 3], bool) {
+        let [mut vgs, mut vds, mut vbs] = new;
+        let [old_vgs, old_vds, old_vbs] = old;
+        let von = self.threshold(old_vbs);
+
+        if old_vds >= 0.0 {
+            vgs = limit_gate_voltage(vgs, old_vgs, von);
+            vds = limit_drain_voltage(vds, old_vds);
+        } else {
+            // 反向工作时限制栅漏电压
+            let vgd = limit_gate_voltage(vgs - vds, old_vgs - old_vds, von);
+            vds = -limit_drain_voltage(-vds, -old_vds);
+            vgs = vgd + vds;
+        }
+
+        if vds >= 0.0 {
+            vbs = self.bulk_source.limit(vbs, old_vbs).0;
+        } else {
+            let vbd = self.bulk_drain.limit(vbs - vds, old_vbs - old_vds).0;
+            vbs = vbd + vds;
+        }
+
+        let limited = [vgs, vds, vbs].iter().zip(&new).any(|(a, b)| (a - b).abs() > 1e-12);
+        ([vgs, vds, vbs], limited)
+    }
+}
+
+/// SPICE `fetlim`: limit the gate voltage step depending on how far `vold` is from the threshold `vto`
+fn limit_gate_voltage(vnew: f64, vold: f64, vto: f64) -> f64 {
+    let vtsthi = (2.0 * (vold - vto)).abs() + 2.0;
+    let vtstlo = vtsthi / 2.0 + 2.0;
+    let vtox = vto + 3.5;
+    let delv = vnew - vold;
+
+    if vold >= vto {
+        if vold >= vtox {
+            if delv <= 0.0 {
+                // 从强导通区向关断方向变化
+                if vnew >= vtox {
+                    if -delv > vtstlo { vold - vtstlo } else { vnew }
+                } else {
+                    vnew.max(vto + 2.0)
+                }
+            } else if delv >= vtsthi {
+                vold + vtsthi
+            } else {
+                vnew
+            }
+        } else if delv <= 0.0 {
+            vnew.max(vto - 0.5)
+        } else {
+            vnew.min(vto + 4.0)
+        }
+    } else if delv <= 0.0 {
+        if -delv > vtsthi { vold - vtsthi } else { vnew }
+    } else if vnew <= vto + 0.5 {
+        if delv > vtstlo { vold + vtstlo } else { vnew }
+    } else {
+        vto + 0.5
+    }
+}
+
+/// SPICE `limvds`: limit the growth of the drain-source voltage
+fn limit_drain_voltage(vnew: f64, vold: f64) -> f64 {
+    if vold >= 3.5 {
+        if vnew > vold {
+            vnew.min(3.0 * vold + 2.0)
+        } else if vnew < 3.5 {
+            vnew.max(2.0)
+        } else {
+            vnew
+        }
+    } else if vnew > vold {
+        vnew.min(4.0)
+    } else {
+        vnew.max(-0.5)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::devices::params;
+
+    #[test]
+    fn test_drain_current_regions() {
+        let model = MosfetModel::from_params(Polarity::N, &params(&[("VTO", 1.0), ("KP", 1e-4), ("LAMBDA", 0.02)])).unwrap();
+        let mos = model.instance(10e-6, 1e-6, NOMINAL_TEMPERATURE).unwrap();
+        assert!((mos.beta - 1e-3).abs() < 1e-12);
+
+        // 截止区
+        assert_eq!(mos.channel(0.5, 2.0, 0.0).ids, 0.0);
+
+        // 饱和区：Ids = beta/2 * (Vgs - Vt)^2 * (1 + lambda*Vds)
+        let sat = mos.channel(3.0, 5.0, 0.0);
+        assert!((sat.ids - 0.5e-3 * 4.0 * 1.1).abs() < 1e-12);
+        assert!((sat.gm - 1e-3 * 2.0 * 1.1).abs() < 1e-12);
+        assert!((sat.gds - 0.02 * 2e-3).abs() < 1e-12);
+
+        // 线性区与饱和区在 Vds = Vgs - Vt 处连续
+        let edge = mos.channel(3.0, 2.0, 0.0);
+        let below = mos.channel(3.0, 2.0 - 1e-9, 0.0);
+        assert!((edge.ids - below.ids).abs() < 1e-11);
+
+        // 数值导数校验
+        let h = 1e-6;
+        let lin = mos.channel(3.0, 1.0, 0.0);
+        let dgs = (mos.channel(3.0 + h, 1.0, 0.0).ids - lin.ids) / h;
+        let dds = (mos.channel(3.0, 1.0 + h, 0.0).ids - lin.ids) / h;
+        assert!((dgs - lin.gm).abs() < 1e-8);
+        assert!((dds - lin.gds).abs() < 1e-8);
+    }
+
+    #[test]
+    fn test_body_effect_and_capacitances() {
+        let model = MosfetModel::from_params(Polarity::P, &params(&[
+            ("vto", -0.8), ("gamma", 0.5), ("phi", 0.7), ("tox", 20e-9), ("cgso", 1e-10),
+        ])).unwrap();
+        assert!((model.kp - 600e-4 * OXIDE_PERMITTIVITY / 20e-9).abs() < 1e-12);
+        assert!(MosfetModel::from_params(Polarity::N, &params(&[("level", 3.0)])).is_err());
+        assert!(MosfetModel::from_params(Polarity::N, &params(&[("bogus", 1.0)])).is_err());
+
+        let mos = model.instance(20e-6, 2e-6, NOMINAL_TEMPERATURE).unwrap();
+        assert_eq!(mos.sign, -1.0);
+        assert!((mos.threshold(0.0) - 0.8).abs() < 1e-12);
+        // 反向体偏压升高阈值电压
+        let vth = 0.8 + 0.5 * ((0.7f64 + 2.0).sqrt() - 0.7f64.sqrt());
+        assert!((mos.threshold(-2.0) - vth).abs() < 1e-12);
+        let h = 1e-6;
+        let op = mos.channel(3.0, 4.0, -2.0);
+        let dbs = (mos.channel(3.0, 4.0, -2.0 + h).ids - op.ids) / h;
+        assert!((dbs - op.gmbs).abs() < 1e-8);
+
+        // 截止时栅电容全部到衬底，饱和时 Cgs = 2/3 Cox
+        assert_eq!(mos.gate_capacitances(-1.0, 1.0, 0.0), (0.0, 0.0, mos.cox));
+        let (cgs, cgd, _) = mos.gate_capacitances(3.0, 4.0, 0.0);
+        assert!((cgs - 2.0 * mos.cox / 3.0).abs() < 1e-20 && cgd == 0.0);
+        assert!((mos.cgs_overlap - 2e-15).abs() < 1e-25);
+
+        assert!(model.instance(20e-6, 0.0, NOMINAL_TEMPERATURE).is_err());
+        let ([vgs, vds, _], limited) = mos.limit([10.0, 10.0, 0.0], [0.0, 0.0, 0.0]);
+        assert!(limited && vgs < 10.0 && vds <= 4.0);
+    }
+}
@@ -0,0 +1,611 @@
+use std::collections::{HashMap, HashSet};
+use anyhow::{anyhow, Result};
+
+/// Parsed parameter expression
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Parameter(String),
+    Unary(UnaryOp, Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(String, Vec<Expr>),
+    Conditional(Box<Expr>, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOp {
+    Negate,
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or,
+}
+
+impl Expr {
+    /// Parse an expression such as `2*Rload` or `sqrt(L*C)`
+    pub fn parse(text: &str) -> Result<Expr> {
+        let tokens = lex(text)?;
+        let mut parser = ExprParser { tokens: &tokens, pos: 0 };
+        let expr = parser.parse_conditional()?;
+        if parser.pos != tokens.len() {
+            return Err(anyhow!("Unexpected '{}' in expression '{}'", tokens[parser.pos], text));
+        }
+        Ok(expr)
+    }
+
+    /// Evaluate the expression against a parameter table (names are lowercase)
+    pub fn evaluate(&self, parameters: &HashMap<String, f64>) -> Result<f64> {
+        match self {
+            Expr::Number(value) => Ok(*value),
+            Expr::Parameter(name) => parameters.get(name)
+                .copied()
+                .or_else(|| constant(name))
+                .ok_or_else(|| anyhow!("Undefined parameter: {}", name)),
+            Expr::Unary(op, operand) => {
+                let value = operand.evaluate(parameters)?;
+                Ok(match op {
+                    UnaryOp::Negate => -value,
+                    UnaryOp::Not => bool_value(value == 0.0),
+                })
+            }
+            Expr::Binary(op, lhs, rhs) => {
+                let a = lhs.evaluate(parameters)?;
+                // 逻辑运算短路求值
+                match op {
+                    BinaryOp::And if a == 0.0 => return Ok(0.0),
+                    BinaryOp::Or if a != 0.0 => return Ok(1.0),
+                    _ => {}
+                }
+                let b = rhs.evaluate(parameters)?;
+                match op {
+                    BinaryOp::Add => Ok(a + b),
+                    BinaryOp::Subtract => Ok(a - b),
+                    BinaryOp::Multiply => Ok(a * b),
+                    BinaryOp::Divide if b == 0.0 => Err(anyhow!("Division by zero in expression")),
+                    BinaryOp::Divide => Ok(a / b),
+                    BinaryOp::Modulo if b == 0.0 => Err(anyhow!("Division by zero in expression")),
+                    BinaryOp::Modulo => Ok(a % b),
+                    BinaryOp::Power => Ok(a.powf(b)),
+                    BinaryOp::Equal => Ok(bool_value(a == b)),
+                    BinaryOp::NotEqual => Ok(bool_value(a != b)),
+                    BinaryOp::Less => Ok(bool_value(a < b)),
+                    BinaryOp::LessEqual => Ok(bool_value(a <= b)),
+                    BinaryOp::Greater => Ok(bool_value(a > b)),
+                    BinaryOp::GreaterEqual => Ok(bool_value(a >= b)),
+                    BinaryOp::And | BinaryOp::Or => Ok(bool_value(b != 0.0)),
+                }
+            }
+            Expr::Conditional(condition, then, otherwise) => {
+                if condition.evaluate(parameters)? != 0.0 {
+                    then.evaluate(parameters)
+                } else {
+                    otherwise.evaluate(parameters)
+                }
+            }
+            Expr::Call(name, args) => {
+                if name == "if" {
+                    if args.len() != 3 {
+                        return Err(anyhow!("if() expects 3 arguments, got {}", args.len()));
+                    }
+                    return if args[0].evaluate(parameters)? != 0.0 {
+                        args[1].evaluate(parameters)
+                    } else {
+                        args[2].evaluate(parameters)
+                    };
+                }
+                let values = args.iter()
+                    .map(|arg| arg.evaluate(parameters))
+                    .collect::<Result<Vec<f64>>>()?;
+                call_function(name, &values)
+            }
+        }
+    }
+
+    /// Names of all parameters referenced by the expression
+    pub fn dependencies(&self) -> HashSet<String> {
+        let mut names = HashSet::new();
+        self.collect_dependencies(&mut names);
+        names
+    }
+
+    fn collect_dependencies(&self, names: &mut HashSet<String>) {
+        match self {
+            Expr::Number(_) => {}
+            Expr::Parameter(name) => { names.insert(name.clone()); }
+            Expr::Unary(_, operand) => operand.collect_dependencies(names),
+            Expr::Binary(_, lhs, rhs) => {
+                lhs.collect_dependencies(names);
+                rhs.collect_dependencies(names);
+            }
+            Expr::Conditional(condition, then, otherwise) => {
+                condition.collect_dependencies(names);
+                then.collect_dependencies(names);
+                otherwise.collect_dependencies(names);
+            }
+            Expr::Call(_, args) => {
+                for arg in args {
+                    arg.collect_dependencies(names);
+                }
+            }
+        }
+    }
+}
+
+/// Strip `{...}` or `'...'` delimiters from an expression value
+pub fn strip_delimiters(text: &str) -> &str {
+    let text = text.trim();
+    text.strip_prefix('{').and_then(|rest| rest.strip_suffix('}'))
+        .or_else(|| text.strip_prefix('\'').and_then(|rest| rest.strip_suffix('\'')))
+        .unwrap_or(text)
+        .trim()
+}
+
+/// Evaluate a single expression string against a parameter table
+pub fn evaluate(text: &str, parameters: &HashMap<String, f64>) -> Result<f64> {
+    Expr::parse(strip_delimiters(text))?.evaluate(parameters)
+}
+
+/// Evaluate a set of parameter definitions in dependency order.
+///
+/// Definitions may refer to each other in any order and to names already in
+/// `scope`. Later definitions of the same name replace earlier ones. Returns
+/// `scope` extended with the new values.
+pub fn evaluate_parameters(definitions: &[(String, String)], scope: &HashMap<String, f64>) -> Result<HashMap<String, f64>> {
+    let mut expressions: HashMap<String, Expr> = HashMap::new();
+    let mut order = Vec::new();
+    for (name, text) in definitions {
+        let expr = Expr::parse(strip_delimiters(text))
+            .map_err(|e| anyhow!("Parameter '{}': {}", name, e))?;
+        if expressions.insert(name.clone(), expr).is_none() {
+            order.push(name.clone());
+        }
+    }
+
+    let mut values = scope.clone();
+    let mut done = HashSet::new();
+    for name in &order {
+        let mut path = Vec::new();
+        evaluate_parameter(name, &expressions, &mut values, &mut done, &mut path)?;
+    }
+    Ok(values)
+}
+
+fn evaluate_parameter(
+    name: &str,
+    expressions: &HashMap<String, Expr>,
+    values: &mut HashMap<String, f64>,
+    done: &mut HashSet<String>,
+    path: &mut Vec<String>,
+) -> Result<()> {
+    if done.contains(name) {
+        return Ok(());
+    }
+    if let Some(start) = path.iter().position(|p| p == name) {
+        let mut cycle = path[start..].to_vec();
+        cycle.push(name.to_string());
+        return Err(anyhow!("Circular parameter dependency: {}", cycle.join(" -> ")));
+    }
+
+    let expr = &expressions[name];
+    path.push(name.to_string());
+    for dependency in expr.dependencies() {
+        if expressions.contains_key(&dependency) {
+            evaluate_parameter(&dependency, expressions, values, done, path)?;
+        }
+    }
+    path.pop();
+
+    let value = expr.evaluate(values)
+        .map_err(|e| anyhow!("Parameter '{}': {}", name, e))?;
+    values.insert(name.to_string(), value);
+    done.insert(name.to_string());
+    Ok(())
+}
+
+/// Parse a SPICE number with an optional scale suffix and unit, e.g. `2.2k`, `1meg`, `10uF`
+pub fn parse_number(text: &str) -> Result<f64> {
+    match split_number(text) {
+        Some((value, rest)) if rest.chars().all(|c| c.is_ascii_alphabetic()) => {
+            Ok(value * scale_factor(rest))
+        }
+        _ => Err(anyhow!("Invalid value: {}", text)),
+    }
+}
+
+/// Split the leading numeric literal off `text`, returning the value and the remainder
+fn split_number(text: &str) -> Option<(f64, &str)> {
+    let bytes = text.as_bytes();
+    let mut end = 0;
+    if end < bytes.len() && (bytes[end] == b'+' || bytes[end] == b'-') {
+        end += 1;
+    }
+    let digits_start = end;
+    while end < bytes.len() && bytes[end].is_ascii_digit() {
+        end += 1;
+    }
+    if end < bytes.len() && bytes[end] == b'.' {
+        end += 1;
+        while end < bytes.len() && bytes[end].is_ascii_digit() {
+            end += 1;
+        }
+    }
+    if end == digits_start || (end == digits_start + 1 && bytes[digits_start] == b'.') {
+        return None;
+    }
+    // 指数部分：只有在 e 后面跟数字时才算
+    if end < bytes.len() && (bytes[end] == b'e' || bytes[end] == b'E') {
+        let mut exp_end = end + 1;
+        if exp_end < bytes.len() && (bytes[exp_end] == b'+' || bytes[exp_end] == b'-') {
+            exp_end += 1;
+        }
+        if exp_end < bytes.len() && bytes[exp_end].is_ascii_digit() {
+            while exp_end < bytes.len() && bytes[exp_end].is_ascii_digit() {
+                exp_end += 1;
+            }
+            end = exp_end;
+        }
+    }
+    let value = text[..end].parse::<f64>().ok()?;
+    Some((value, &text[end..]))
+}
+
+/// Scale factor of a SPICE suffix; trailing unit letters are ignored
+fn scale_factor(suffix: &str) -> f64 {
+    let suffix = suffix.to_lowercase();
+    if suffix.starts_with("meg") {
+        1e6
+    } else if suffix.starts_with("mil") {
+        25.4e-6
+    } else {
+        match suffix.chars().next() {
+            Some('t') => 1e12,
+            Some('g') => 1e9,
+            Some('k') => 1e3,
+            Some('m') => 1e-3,
+            Some('u') | Some('µ') => 1e-6,
+            Some('n') => 1e-9,
+            Some('p') => 1e-12,
+            Some('f') => 1e-15,
+            Some('a') => 1e-18,
+            _ => 1.0,
+        }
+    }
+}
+
+fn bool_value(condition: bool) -> f64 {
+    if condition { 1.0 } else { 0.0 }
+}
+
+fn constant(name: &str) -> Option<f64> {
+    match name {
+        "pi" => Some(std::f64::consts::PI),
+        _ => None,
+    }
+}
+
+fn call_function(name: &str, args: &[f64]) -> Result<f64> {
+    let expect = |count: usize| -> Result<()> {
+        if args.len() == count {
+            Ok(())
+        } else {
+            Err(anyhow!("{}() expects {} argument(s), got {}", name, count, args.len()))
+        }
+    };
+
+    match name {
+        "min" | "max" => {
+            if args.is_empty() {
+                return Err(anyhow!("{}() expects at least 1 argument", name));
+            }
+            let fold: fn(f64, f64) -> f64 = if name == "min" { f64::min } else { f64::max };
+            Ok(args[1..].iter().copied().fold(args[0], fold))
+        }
+        "pow" => {
+            expect(2)?;
+            Ok(args[0].powf(args[1]))
+        }
+        "pwr" => {
+            // 保留底数符号的幂函数
+            expect(2)?;
+            Ok(args[0].signum() * args[0].abs().powf(args[1]))
+        }
+        "atan2" => {
+            expect(2)?;
+            Ok(args[0].atan2(args[1]))
+        }
+        _ => {
+            expect(1)?;
+            let x = args[0];
+            match name {
+                "sqrt" if x < 0.0 => Err(anyhow!("sqrt() of negative value {}", x)),
+                "sqrt" => Ok(x.sqrt()),
+                "exp" => Ok(x.exp()),
+                "log" | "ln" if x <= 0.0 => Err(anyhow!("{}() of non-positive value {}", name, x)),
+                "log" | "ln" => Ok(x.ln()),
+                "log10" if x <= 0.0 => Err(anyhow!("log10() of non-positive value {}", x)),
+                "log10" => Ok(x.log10()),
+                "abs" => Ok(x.abs()),
+                "sin" => Ok(x.sin()),
+                "cos" => Ok(x.cos()),
+                "tan" => Ok(x.tan()),
+                "asin" => Ok(x.asin()),
+                "acos" => Ok(x.acos()),
+                "atan" => Ok(x.atan()),
+                "sinh" => Ok(x.sinh()),
+                "cosh" => Ok(x.cosh()),
+                "tanh" => Ok(x.tanh()),
+                "floor" => Ok(x.floor()),
+                "ceil" => Ok(x.ceil()),
+                "int" => Ok(x.trunc()),
+                "nint" | "round" => Ok(x.round()),
+                "sgn" | "sign" => Ok(if x == 0.0 { 0.0 } else { x.signum() }),
+                _ => Err(anyhow!("Unknown function: {}", name)),
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(&'static str),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Number(value) => write!(f, "{}", value),
+            Token::Ident(name) => write!(f, "{}", name),
+            Token::Op(op) => write!(f, "{}", op),
+        }
+    }
+}
+
+const OPERATORS: [&str; 20] = [
+    "**", "==", "!=", "<=", ">=", "&&", "||",
+    "+", "-", "*", "/", "%", "^", "<", ">", "!", "?", ":", "(", ")",
+];
+
+fn lex(text: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut rest = text;
+
+    while let Some(c) = rest.chars().next() {
+        if c.is_whitespace() {
+            rest = &rest[c.len_utf8()..];
+        } else if c == ',' {
+            tokens.push(Token::Op(","));
+            rest = &rest[1..];
+        } else if c.is_ascii_digit() || (c == '.' && rest[1..].starts_with(|d: char| d.is_ascii_digit())) {
+            let (value, after) = split_number(rest)
+                .ok_or_else(|| anyhow!("Invalid number in expression '{}'", text))?;
+            let unit_len = after.find(|ch: char| !ch.is_ascii_alphabetic()).unwrap_or(after.len());
+            tokens.push(Token::Number(value * scale_factor(&after[..unit_len])));
+            rest = &after[unit_len..];
+        } else if c.is_alphabetic() || c == '_' {
+            let len = rest.find(|ch: char| !(ch.is_alphanumeric() || ch == '_' || ch == '.'))
+                .unwrap_or(rest.len());
+            tokens.push(Token::Ident(rest[..len].to_lowercase()));
+            rest = &rest[len..];
+        } else if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(**op)) {
+            tokens.push(Token::Op(op));
+            rest = &rest[op.len()..];
+        } else {
+            return Err(anyhow!("Unexpected character '{}' in expression '{}'", c, text));
+        }
+    }
+
+    if tokens.is_empty() {
+        return Err(anyhow!("Empty expression"));
+    }
+    Ok(tokens)
+}
+
+/// Recursive-descent parser, lowest precedence first
+struct ExprParser<'a> {
+    tokens: &'a [Token],
+    pos: usize,
+}
+
+impl ExprParser<'_> {
+    fn peek_op(&self) -> Option<&'static str> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Op(op)) => Some(op),
+            _ => None,
+        }
+    }
+
+    fn accept(&mut self, op: &str) -> bool {
+        if self.peek_op() == Some(op) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, op: &str) -> Result<()> {
+        if self.accept(op) {
+            Ok(())
+        } else {
+            match self.tokens.get(self.pos) {
+                Some(token) => Err(anyhow!("Expected '{}' but found '{}'", op, token)),
+                None => Err(anyhow!("Expected '{}' at end of expression", op)),
+            }
+        }
+    }
+
+    fn parse_conditional(&mut self) -> Result<Expr> {
+        let condition = self.parse_binary(0)?;
+        if self.accept("?") {
+            let then = self.parse_conditional()?;
+            self.expect(":")?;
+            let otherwise = self.parse_conditional()?;
+            return Ok(Expr::Conditional(Box::new(condition), Box::new(then), Box::new(otherwise)));
+        }
+        Ok(condition)
+    }
+
+    /// Left-associative binary operators, by precedence level
+    fn parse_binary(&mut self, level: usize) -> Result<Expr> {
+        const LEVELS: [&[(&str, BinaryOp)]; 5] = [
+            &[("||", BinaryOp::Or)],
+            &[("&&", BinaryOp::And)],
+            &[
+                ("==", BinaryOp::Equal), ("!=", BinaryOp::NotEqual),
+                ("<=", BinaryOp::LessEqual), (">=", BinaryOp::GreaterEqual),
+                ("<", BinaryOp::Less), (">", BinaryOp::Greater),
+            ],
+            &[("+", BinaryOp::Add), ("-", BinaryOp::Subtract)],
+            &[("*", BinaryOp::Multiply), ("/", BinaryOp::Divide), ("%", BinaryOp::Modulo)],
+        ];
+
+        if level == LEVELS.len() {
+            return self.parse_unary();
+        }
+
+        let mut lhs = self.parse_binary(level + 1)?;
+        'outer: loop {
+            for (symbol, op) in LEVELS[level] {
+                if self.accept(symbol) {
+                    let rhs = self.parse_binary(level + 1)?;
+                    lhs = Expr::Binary(*op, Box::new(lhs), Box::new(rhs));
+                    continue 'outer;
+                }
+            }
+            return Ok(lhs);
+        }
+    }
+
+    fn parse_unary(&mut self) -> Result<Expr> {
+        if self.accept("-") {
+            return Ok(Expr::Unary(UnaryOp::Negate, Box::new(self.parse_unary()?)));
+        }
+        if self.accept("+") {
+            return self.parse_unary();
+        }
+        if self.accept("!") {
+            return Ok(Expr::Unary(UnaryOp::Not, Box::new(self.parse_unary()?)));
+        }
+        self.parse_power()
+    }
+
+    fn parse_power(&mut self) -> Result<Expr> {
+        let base = self.parse_primary()?;
+        if self.accept("**") || self.accept("^") {
+            // 右结合，且允许 2**-1
+            let exponent = self.parse_unary()?;
+            return Ok(Expr::Binary(BinaryOp::Power, Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn parse_primary(&mut self) -> Result<Expr> {
+        let token = self.tokens.get(self.pos).cloned()
+            .ok_or_else(|| anyhow!("Unexpected end of expression"))?;
+        self.pos += 1;
+
+        match token {
+            Token::Number(value) => Ok(Expr::Number(value)),
+            Token::Ident(name) => {
+                if !self.accept("(") {
+                    return Ok(Expr::Parameter(name));
+                }
+                let mut args = Vec::new();
+                if !self.accept(")") {
+                    loop {
+                        args.push(self.parse_conditional()?);
+                        if self.accept(")") {
+                            break;
+                        }
+                        self.expect(",")?;
+                    }
+                }
+                Ok(Expr::Call(name, args))
+            }
+            Token::Op("(") => {
+                let expr = self.parse_conditional()?;
+                self.expect(")")?;
+                Ok(expr)
+            }
+            Token::Op(op) => Err(anyhow!("Unexpected '{}' in expression", op)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(text: &str) -> f64 {
+        evaluate(text, &HashMap::new()).unwrap()
+    }
+
+    #[test]
+    fn test_arithmetic_and_functions() {
+        assert_eq!(eval("1 + 2 * 3"), 7.0);
+        assert_eq!(eval("(1 + 2) * 3"), 9.0);
+        assert_eq!(eval("2 ** 3 ** 2"), 512.0);
+        assert_eq!(eval("-2^2"), -4.0);
+        assert_eq!(eval("{max(1, 5, 3) - min(4, 2)}"), 3.0);
+        assert_eq!(eval("'sqrt(16) + abs(-1)'"), 5.0);
+        assert_eq!(eval("pow(2, 10)"), 1024.0);
+        assert_eq!(eval("if(1 > 2, 10, 20)"), 20.0);
+        assert_eq!(eval("3 >= 3 ? 1k : 2k"), 1000.0);
+        assert!((eval("2.2u * 1meg") - 2.2).abs() < 1e-12);
+        assert!((eval("log(exp(2))") - 2.0).abs() < 1e-12);
+        assert!(evaluate("1/0", &HashMap::new()).is_err());
+        assert!(evaluate("foo(1)", &HashMap::new()).is_err());
+    }
+
+    #[test]
+    fn test_parameter_dependencies() {
+        let definitions = vec![
+            ("f0".to_string(), "{1/(2*pi*sqrt(l*c))}".to_string()),
+            ("l".to_string(), "1u".to_string()),
+            ("c".to_string(), "{cval*2}".to_string()),
+        ];
+        let mut scope = HashMap::new();
+        scope.insert("cval".to_string(), 0.5e-9);
+
+        let values = evaluate_parameters(&definitions, &scope).unwrap();
+        assert_eq!(values["c"], 1e-9);
+        assert!((values["f0"] - 5.0329e6).abs() < 1e3);
+
+        let cyclic = vec![
+            ("a".to_string(), "b+1".to_string()),
+            ("b".to_string(), "c+1".to_string()),
+            ("c".to_string(), "a+1".to_string()),
+        ];
+        let err = evaluate_parameters(&cyclic, &HashMap::new()).unwrap_err();
+        assert!(err.to_string().contains("Circular"));
+    }
+
+    #[test]
+    fn test_parse_number() {
+        assert_eq!(parse_number("1k").unwrap(), 1e3);
+        assert!((parse_number("10uF").unwrap() - 10e-6).abs() < 1e-18);
+        assert_eq!(parse_number("1.5MEG").unwrap(), 1.5e6);
+        assert_eq!(parse_number("-2.5e-3").unwrap(), -2.5e-3);
+        assert_eq!(parse_number("5V").unwrap(), 5.0);
+        assert_eq!(parse_number("10mA").unwrap(), 10e-3);
+        assert!(parse_number("abc").is_err());
+        assert!(parse_number("1k2").is_err());
+    }
+}
@@ -0,0 +1,202 @@
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+
+/// Highest order of the Gear (backward differentiation) formulas
+pub const MAX_GEAR_ORDER: usize = 6;
+
+/// Local truncation error constants of the Gear formulas of order 1 to 6:
+/// LTE = C * h^(k+1) * q^(k+1) at a fixed step h
+const GEAR_ERROR_CONSTANTS: [f64; MAX_GEAR_ORDER] = [1.0 / 2.0, 2.0 / 9.0, 3.0 / 22.0, 12.0 / 125.0, 10.0 / 137.0, 20.0 / 343.0];
+
+/// Local truncation error constant of the trapezoidal rule
+const TRAPEZOIDAL_ERROR_CONSTANT: f64 = 1.0 / 12.0;
+
+/// Tolerances of the local truncation error test (SPICE TRTOL, RELTOL, ABSTOL and CHGTOL)
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TruncationTolerances {
+    /// Factor by which the truncation error may exceed the Newton tolerances
+    pub trtol: f64,
+    pub reltol: f64,
+    /// Absolute tolerance on currents
+    pub abstol: f64,
+    /// Absolute tolerance on charges
+    pub chgtol: f64,
+}
+
+/// Numerical integration method for the charges and fluxes of transient analysis,
+/// selected with `.OPTIONS METHOD=TRAP|GEAR`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum IntegrationMethod {
+    /// Trapezoidal rule, second order (the SPICE default)
+    Trapezoidal,
+    /// Variable-order, variable-step Gear formulas up to the configured maximum order
+    Gear,
+}
+
+impl IntegrationMethod {
+    /// Method named by `.OPTIONS METHOD=`
+    pub fn from_name(name: &str) -> Result<IntegrationMethod> {
+        match name.to_lowercase().as_str() {
+            "trap" | "trapezoidal" => Ok(IntegrationMethod::Trapezoidal),
+            "gear" => Ok(IntegrationMethod::Gear),
+            other => Err(anyhow!("Unknown integration method: {}", other)),
+        }
+    }
+}
+
+/// Integration formula for one time step. The current of a charge q is
+/// `i_n = coefficients[0]*q_n + ... + coefficients[order]*q_(n-order) - current_factor*i_(n-1)`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Integrator {
+    pub order: usize,
+    pub coefficients: [f64; MAX_GEAR_ORDER + 1],
+    /// 1 for the trapezoidal rule, which also depends on the previous current
+    pub current_factor: f64,
+}
+
+impl Integrator {
+    /// Backward Euler over a step `dt`: i = (q - q_prev) / dt
+    pub fn backward_euler(dt: f64) -> Integrator {
+        Integrator::gear(&[dt, 0.0])
+    }
+
+    /// Trapezoidal rule over a step `dt`: i = 2(q - q_prev) / dt - i_prev
+    pub fn trapezoidal(dt: f64) -> Integrator {
+        let mut coefficients = [0.0; MAX_GEAR_ORDER + 1];
+        coefficients[0] = 2.0 / dt;
+        coefficients[1] = -2.0 / dt;
+        Integrator { order: 2, coefficients, current_factor: 1.0 }
+    }
+
+    /// Gear formula through the time points `times`, newest first: the derivative at
+    /// `times[0]` of the polynomial interpolating the charges, of order `times.len() - 1`
+    pub fn gear(times: &[f64]) -> Integrator {
+        let order = times.len() - 1;
+        let mut coefficients = [0.0; MAX_GEAR_ORDER + 1];
+        coefficients[0] = times[1..].iter().map(|&t| 1.0 / (times[0] - t)).sum();
+        for j in 1..=order {
+            // Lagrange 基函数 j 在 times[0] 处的导数
+            let mut value = 1.0 / (times[j] - times[0]);
+            for (m, &t) in times.iter().enumerate().skip(1) {
+                if m != j {
+                    value *= (times[0] - t) / (times[j] - t);
+                }
+            }
+            coefficients[j] = value;
+        }
+        Integrator { order, coefficients, current_factor: 0.0 }
+    }
+
+    /// Formula of `method` at `order` through `times`, newest first. Order 1 is
+    /// Backward Euler for both methods; `times` must hold at least `order + 1` points.
+    pub fn new(method: IntegrationMethod, order: usize, times: &[f64]) -> Integrator {
+        match method {
+            IntegrationMethod::Trapezoidal if order >= 2 => Integrator::trapezoidal(times[0] - times[1]),
+            IntegrationMethod::Trapezoidal => Integrator::backward_euler(times[0] - times[1]),
+            IntegrationMethod::Gear => Integrator::gear(&times[..=order.clamp(1, MAX_GEAR_ORDER)]),
+        }
+    }
+
+    /// Coefficient of the present charge, i.e. the companion conductance of a unit capacitance
+    pub fn conductance(&self) -> f64 {
+        self.coefficients[0]
+    }
+
+    /// Largest step for which the local truncation error of one charge stays within
+    /// `tolerances`. The error is estimated from the difference between the corrector
+    /// result and the polynomial predictor through the earlier charges, scaled by the
+    /// error constant of the formula. `times` and `charges` hold the present point and
+    /// the `order + 1` points before it, newest first; `currents` the present and
+    /// previous current. A step of the same length is returned for a charge that is
+    /// exactly on tolerance; infinity if the predictor is exact.
+    pub fn truncation_timestep(&self, times: &[f64], charges: &[f64], currents: [f64; 2],
+                               tolerances: &TruncationTolerances) -> f64 {
+        let order = self.order;
+        let points = order + 1;
+        let step = times[0] - times[1];
+
+        // 预测值：前 order+1 个点的插值多项式在当前时刻的值
+        let mut predicted = 0.0;
+        for j in 1..=points {
+            let mut weight = 1.0;
+            for m in (1..=points).filter(|&m| m != j) {
+                weight *= (times[0] - times[m]) / (times[j] - times[m]);
+            }
+            predicted += weight * charges[j];
+        }
+
+        // 预测误差为 q^(k+1)/(k+1)! * Π(t - t_j)，校正误差为 C * h^(k+1) * q^(k+1)
+        let constant = if self.current_factor != 0.0 {
+            TRAPEZOIDAL_ERROR_CONSTANT
+        } else {
+            GEAR_ERROR_CONSTANTS[order - 1]
+        };
+        let factorial: f64 = (1..=points).map(|n| n as f64).product();
+        let product: f64 = times[1..=points].iter().map(|&t| times[0] - t).product();
+        let error = constant * step.powi(points as i32) * factorial / product * (charges[0] - predicted).abs();
+
+        let current_tolerance = tolerances.abstol + tolerances.reltol * currents[0].abs().max(currents[1].abs());
+        let charge_tolerance = tolerances.reltol * charges[0].abs().max(charges[1].abs()).max(tolerances.chgtol) / step;
+        let allowed = tolerances.trtol * current_tolerance.max(charge_tolerance) * step;
+        if error <= 0.0 {
+            return f64::INFINITY;
+        }
+        step * (allowed / error).powf(1.0 / points as f64)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_fixed_step_formulas() {
+        let euler = Integrator::backward_euler(0.5);
+        assert_eq!((euler.order, euler.coefficients[0], euler.coefficients[1]), (1, 2.0, -2.0));
+
+        let trap = Integrator::new(IntegrationMethod::Trapezoidal, 2, &[1.0, 0.5]);
+        assert_eq!((trap.conductance(), trap.coefficients[1], trap.current_factor), (4.0, -4.0, 1.0));
+        // 重新起步时梯形法退化为后向欧拉
+        assert_eq!(Integrator::new(IntegrationMethod::Trapezoidal, 1, &[1.0, 0.5]), euler);
+
+        // 等步长二阶 Gear：(3q_n - 4q_(n-1) + q_(n-2)) / 2h
+        let gear = Integrator::new(IntegrationMethod::Gear, 2, &[3.0, 2.0, 1.0]);
+        let expected = [1.5, -2.0, 0.5];
+        for (coefficient, expected) in gear.coefficients.iter().zip(expected) {
+            assert!((coefficient - expected).abs() < 1e-12);
+        }
+        assert!(IntegrationMethod::from_name("euler").is_err());
+        assert_eq!(IntegrationMethod::from_name("GEAR").unwrap(), IntegrationMethod::Gear);
+    }
+
+    #[test]
+    fn test_truncation_timestep() {
+        let tolerances = TruncationTolerances { trtol: 1.0, reltol: 1e-3, abstol: 1e-12, chgtol: 1e-14 };
+        // q = t^2：后向欧拉的截断误差为 h^2/2 * q'' = h^2
+        let times = [3.0, 2.0, 1.0];
+        let charges = times.map(|t| t * t);
+        let euler = Integrator::backward_euler(1.0);
+        let allowed: f64 = 1e-3 * 9.0;
+        let step = euler.truncation_timestep(&times, &charges, [6.0, 4.0], &tolerances);
+        assert!((step - allowed.sqrt()).abs() < 1e-12);
+
+        // 多项式次数不超过阶数时预测精确
+        let gear = Integrator::gear(&times);
+        let charges = [3.0, 2.0, 1.0, 0.0].map(|t: f64| 2.0 * t * t + 1.0);
+        let step = gear.truncation_timestep(&[3.0, 2.0, 1.0, 0.0], &charges, [12.0, 8.0], &tolerances);
+        assert!(step > 1e3, "{}", step);
+    }
+
+    #[test]
+    fn test_gear_differentiates_polynomials_exactly() {
+        // 变步长下 k 阶公式对 k 次多项式精确
+        let times = [1.0, 0.9, 0.75, 0.7, 0.4];
+        for order in 1..times.len() {
+            let gear = Integrator::gear(&times[..=order]);
+            let poly = |t: f64| (0..=order).map(|power| t.powi(power as i32)).sum::<f64>();
+            let derivative: f64 = (1..=order).map(|power| power as f64).sum();
+            let estimate: f64 = times[..=order].iter().enumerate().map(|(j, &t)| gear.coefficients[j] * poly(t)).sum();
+            assert!((estimate - derivative).abs() < 1e-9, "order {}: {}", order, estimate);
+        }
+    }
+}
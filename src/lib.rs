@@ -1,10 +1,20 @@
+#![allow(non_snake_case)] // crate name follows the project name
+
 pub mod circuit;
+pub mod devices;
+pub mod expression;
+pub mod integration;
 pub mod cli;
 pub mod mna;
 pub mod output;
 pub mod parser;
+pub mod preconditioner;
 pub mod simulator;
 pub mod solver;
+pub mod sparse_lu;
+pub mod sparse_matrix;
+pub mod sweep;
+pub mod waveform;
 
 // Re-export commonly used types
 pub use circuit::{Circuit, Component, Node};
@@ -3,16 +3,9 @@ use colored::*;
 use log::{error, info};
 use std::path::Path;
 
-mod circuit;
-mod cli;
-mod mna;
-mod output;
-mod parser;
-mod simulator;
-mod solver;
-
-use crate::cli::CliArgs;
-use crate::simulator::Simulator;
+use RustSim::cli::{self, CliArgs};
+use RustSim::simulator::Simulator;
+use RustSim::sweep::Sweep;
 
 fn main() {
     env_logger::init();
@@ -57,6 +50,13 @@ fn create_cli() -> Command {
                 .num_args(4)
                 .help("DC sweep analysis"),
         )
+        .arg(
+            Arg::new("ac")
+                .long("ac")
+                .value_names(["TYPE", "POINTS", "FSTART", "FSTOP"])
+                .num_args(4)
+                .help("AC analysis: dec, oct or lin sweep of the frequency"),
+        )
         .arg(
             Arg::new("verbose")
                 .short('v')
@@ -90,17 +90,26 @@ fn run_application(matches: &ArgMatches) -> anyhow::Result<()> {
     let mut simulator = Simulator::new();
     simulator.load_netlist(&args.input_file)?;
     
+    // 命令行给出的分析优先，否则依次运行网表中声明的分析
     match args.analysis_type {
-        cli::AnalysisType::Transient { tstep, tstop } => {
+        Some(cli::AnalysisType::Transient { tstep, tstop }) => {
             info!("Running transient analysis: tstep={}, tstop={}", tstep, tstop);
             simulator.run_transient_analysis(tstep, tstop)?;
         }
-        cli::AnalysisType::DcSweep { source, start, stop, step } => {
+        Some(cli::AnalysisType::DcSweep { source, start, stop, step }) => {
             info!("Running DC sweep: source={}, range=[{}, {}], step={}", 
                   source, start, stop, step);
             simulator.run_dc_sweep(&source, start, stop, step)?;
         }
-        cli::AnalysisType::Operating => {
+        Some(cli::AnalysisType::Ac { sweep_type, points, fstart, fstop }) => {
+            info!("Running AC analysis: {} {} points, [{}, {}]Hz", sweep_type, points, fstart, fstop);
+            simulator.run_ac_analysis(&Sweep::frequency(&sweep_type, points, fstart, fstop)?)?;
+        }
+        None if !simulator.analyses().is_empty() => {
+            info!("Running the {} analyses declared in the netlist", simulator.analyses().len());
+            simulator.run_netlist_analyses()?;
+        }
+        Some(cli::AnalysisType::Operating) | None => {
             info!("Running operating point analysis");
             simulator.run_operating_point()?;
         }
@@ -1,37 +1,132 @@
 use nalgebra::{DMatrix, DVector};
+use num_complex::Complex64;
 use sprs::{CsMat, TriMat};
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::rc::Rc;
 use anyhow::{anyhow, Result};
 
 use crate::circuit::{Circuit, Component, ComponentType};
+use crate::devices::mosfet::DEFAULT_CHANNEL_SIZE;
+use crate::devices::{
+    BjtInstance, BjtModel, DeviceModel, DiodeInstance, DiodeModel, JfetInstance, JfetModel, MosfetInstance, MosfetModel,
+};
+use crate::integration::{IntegrationMethod, Integrator, TruncationTolerances, MAX_GEAR_ORDER};
+use crate::sparse_lu::{LuFactorization, DEFAULT_MAX_PIVOT_GROWTH, DEFAULT_PIVOT_TOLERANCE};
+use crate::sparse_matrix::{SparseMatrix, StampSlots};
+
+/// Conductance that holds a node at its `.IC` or `.NODESET` voltage, as in SPICE
+const NODE_FORCING_CONDUCTANCE: f64 = 1e10;
+
+/// Largest system for which `MnaSystem::dense_matrix` builds a dense copy
+pub const DENSE_VIEW_LIMIT: usize = 2000;
 
 /// MNA system representation: [A][x] = [z]
 /// where A is the system matrix, x is the unknown vector, and z is the RHS vector
 #[derive(Debug, Clone)]
 pub struct MnaSystem {
-    /// System matrix A (coefficient matrix)
-    pub matrix: DMatrix<f64>,
+    /// System matrix A (coefficient matrix), assembled in place in the sparse
+    /// pattern derived from the circuit topology
+    pub matrix: SparseMatrix,
+    /// Value slots of the positions each component stamps, keyed by component name
+    stamp_slots: HashMap<String, Rc<StampSlots>>,
+    /// Value slots of the node diagonals, for the `.IC` and `.NODESET` forcing
+    diagonal_slots: Vec<usize>,
     /// Right-hand side vector z
     pub rhs: DVector<f64>,
     /// Unknown vector x (node voltages + voltage source currents)
     pub unknowns: DVector<f64>,
     /// Mapping from node IDs to matrix row/column indices
     pub node_map: HashMap<usize, usize>,
-    /// Mapping from voltage source names to current variable indices
+    /// Mapping from voltage source, inductor and E/H controlled source names to current variable indices
     pub voltage_source_map: HashMap<String, usize>,
     /// Total system size
     pub size: usize,
-    /// Number of nodes (excluding ground)
+    /// Number of nodes (excluding ground), including device-internal nodes
     pub num_nodes: usize,
-    /// Number of voltage sources
+    /// Number of branch current unknowns (voltage sources, inductors and E/H controlled sources)
     pub num_voltage_sources: usize,
+    /// Junction voltages the nonlinear devices were last linearized at, for voltage limiting
+    pub junction_voltages: HashMap<String, f64>,
+    /// Matrix indices of device-internal nodes (e.g. behind a diode's series resistance),
+    /// keyed by `component#terminal`
+    pub internal_nodes: HashMap<String, usize>,
+    /// While an AC system is assembled, collects the matrix C of G + jωC: charge companions
+    /// stamp their capacitance here instead of adding C/dt to `matrix`
+    reactive: Option<SparseMatrix>,
+    /// Charges of the reactive elements at the present iterate of a transient step,
+    /// keyed by `component#charge` (capacitors and inductors by their name)
+    charge_states: HashMap<String, ChargeState>,
+    /// Charge states at the accepted time points of a transient analysis, newest first
+    charge_history: VecDeque<ChargeHistory>,
+    /// Time steps accepted since the integration was last restarted
+    steps_since_restart: usize,
+}
+
+/// Charge (or flux, for inductors) of a reactive element and the current
+/// (or voltage) that is its time derivative
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ChargeState {
+    pub charge: f64,
+    pub current: f64,
+}
+
+/// Charge states of the reactive elements at an accepted time point
+#[derive(Debug, Clone)]
+struct ChargeHistory {
+    time: f64,
+    states: HashMap<String, ChargeState>,
+}
+
+/// Small-signal system (G + jωC)x = b of a circuit linearized at its DC operating point
+#[derive(Debug, Clone)]
+pub struct AcSystem {
+    /// Conductance matrix G, including the small-signal conductances of the devices
+    pub conductance: SparseMatrix,
+    /// Capacitances, device charges included, and negated inductances on the branch rows
+    pub reactive: SparseMatrix,
+    /// Excitation from the AC magnitude and phase of the independent sources
+    pub excitation: DVector<Complex64>,
+    /// Complex sparse LU; all frequencies share the pattern and usually the pivots
+    factorization: LuFactorization<Complex64>,
+}
+
+impl AcSystem {
+    /// System matrix G + jωC at angular frequency `omega`, on the union of both patterns
+    pub fn matrix(&self, omega: f64) -> CsMat<Complex64> {
+        let mut triplets = TriMat::new((self.conductance.size(), self.conductance.size()));
+        for (row, col, g) in self.conductance.entries() {
+            triplets.add_triplet(row, col, Complex64::new(g, 0.0));
+        }
+        for (row, col, c) in self.reactive.entries() {
+            triplets.add_triplet(row, col, Complex64::new(0.0, omega * c));
+        }
+        triplets.to_csc()
+    }
+
+    /// Complex node voltages and branch currents at `frequency` in Hz
+    pub fn solve(&mut self, frequency: f64) -> Result<DVector<Complex64>> {
+        let omega = 2.0 * std::f64::consts::PI * frequency;
+        self.factorization.factor(&self.matrix(omega), DEFAULT_PIVOT_TOLERANCE, DEFAULT_MAX_PIVOT_GROWTH)
+            .map_err(|e| anyhow!("AC system at {} Hz: {}", frequency, e))?;
+        Ok(DVector::from_vec(self.factorization.solve(self.excitation.as_slice())?))
+    }
+}
+
+/// Time step information the reactive elements need for their charge companion models
+#[derive(Debug, Clone, Copy)]
+pub struct ChargeStep<'a> {
+    pub integrator: Integrator,
+    /// Solution (node voltages followed by branch currents) at the previous time point
+    pub prev_solution: &'a DVector<f64>,
 }
 
 impl MnaSystem {
     /// Create a new MNA system for the given circuit
     pub fn new(circuit: &Circuit) -> Result<Self> {
-        let num_nodes = circuit.node_count(); // Non-ground nodes
-        let voltage_sources = circuit.voltage_sources();
+        let internal_names = internal_node_names(circuit)?;
+        let num_nodes = circuit.node_count() + internal_names.len(); // Non-ground nodes
+        // 电压源、电感以及 E、H 受控源各占一个支路电流未知量
+        let voltage_sources = circuit.branch_components();
         let num_voltage_sources = voltage_sources.len();
         let size = num_nodes + num_voltage_sources;
 
@@ -49,18 +144,35 @@ impl MnaSystem {
             }
         }
 
+        // Internal nodes follow the circuit nodes
+        let internal_nodes: HashMap<String, usize> = internal_names.into_iter()
+            .enumerate()
+            .map(|(i, name)| (name, circuit.node_count() + i))
+            .collect();
+
         // Create voltage source mapping
         let mut voltage_source_map = HashMap::new();
         for (i, vs) in voltage_sources.iter().enumerate() {
             voltage_source_map.insert(vs.name.clone(), num_nodes + i);
         }
 
-        let matrix = DMatrix::zeros(size, size);
+        // 矩阵结构和各元件印记的值槽都只由拓扑确定一次
+        let terminals = component_terminals(circuit, &node_map, &internal_nodes, &voltage_source_map)?;
+        let matrix = SparseMatrix::from_pattern(size, stamp_pattern(circuit, num_nodes, &terminals, &voltage_source_map));
+        let stamp_slots = circuit.components.iter()
+            .zip(terminals)
+            .map(|(component, terminals)| (component.name.clone(), Rc::new(StampSlots::new(&matrix, terminals))))
+            .collect();
+        let diagonal_slots = (0..num_nodes)
+            .map(|i| matrix.slot(i, i).expect("node diagonals are in the pattern"))
+            .collect();
         let rhs = DVector::zeros(size);
         let unknowns = DVector::zeros(size);
 
         Ok(MnaSystem {
             matrix,
+            stamp_slots,
+            diagonal_slots,
             rhs,
             unknowns,
             node_map,
@@ -68,13 +180,44 @@ impl MnaSystem {
             size,
             num_nodes,
             num_voltage_sources,
+            junction_voltages: HashMap::new(),
+            internal_nodes,
+            reactive: None,
+            charge_states: HashMap::new(),
+            charge_history: VecDeque::new(),
+            steps_since_restart: 0,
         })
     }
 
     /// Assemble the MNA system for DC analysis
     pub fn assemble_dc(&mut self, circuit: &Circuit) -> Result<()> {
+        self.assemble_static(circuit, None)
+    }
+
+    /// Assemble the DC system with the independent sources at their transient values at
+    /// `time`, for the operating point a transient analysis starts from
+    pub fn assemble_dc_at(&mut self, circuit: &Circuit, time: f64) -> Result<()> {
+        self.assemble_static(circuit, Some(time))
+    }
+
+    /// Hold the named nodes at the given voltages through a large conductance to
+    /// ground, for the `.IC` and `.NODESET` cards of an operating point solve
+    pub fn stamp_node_forcing(&mut self, circuit: &Circuit, voltages: &HashMap<String, f64>) -> Result<()> {
+        for (node, &voltage) in voltages {
+            let index = self.node_index(circuit, node)?;
+            if let Some(i) = index {
+                self.matrix.values_mut()[self.diagonal_slots[i]] += NODE_FORCING_CONDUCTANCE;
+            }
+            self.stamp_current(None, index, NODE_FORCING_CONDUCTANCE * voltage);
+        }
+        Ok(())
+    }
+
+    /// Assemble the resistive part of the system, with independent sources
+    /// evaluated at `time` (their DC values when `None`)
+    fn assemble_static(&mut self, circuit: &Circuit, time: Option<f64>) -> Result<()> {
         // Clear existing system
-        self.matrix.fill(0.0);
+        self.matrix.clear();
         self.rhs.fill(0.0);
 
         // Process linear components (R, L, C)
@@ -84,41 +227,642 @@ impl MnaSystem {
 
         // Process current sources
         for component in circuit.current_sources() {
-            self.add_current_source(circuit, component)?;
+            self.add_current_source(circuit, component, time)?;
         }
 
         // Process voltage sources
         for component in circuit.voltage_sources() {
-            self.add_voltage_source(circuit, component)?;
+            self.add_voltage_source(circuit, component, time)?;
         }
 
         Ok(())
     }
 
-    /// Assemble the MNA system for transient analysis
-    pub fn assemble_transient(&mut self, circuit: &Circuit, dt: f64, prev_voltages: &DVector<f64>) -> Result<()> {
-        // Start with DC assembly
-        self.assemble_dc(circuit)?;
+    /// Assemble the MNA system for transient analysis at `time`, with the capacitors
+    /// and inductors replaced by the companion models of `step`
+    pub fn assemble_transient(&mut self, circuit: &Circuit, time: f64, step: ChargeStep) -> Result<()> {
+        // Start with DC assembly, sources at their time-dependent values
+        self.assemble_static(circuit, Some(time))?;
 
         // Add capacitor contributions for transient analysis
         for component in &circuit.components {
             if let ComponentType::Capacitor = component.component_type {
-                self.add_capacitor_transient(circuit, component, dt, prev_voltages)?;
+                self.add_capacitor_transient(circuit, component, step)?;
             }
         }
 
         // Add inductor contributions for transient analysis
+        let prev_fluxes = self.inductor_fluxes(circuit, step.prev_solution)?;
         for component in &circuit.components {
             if let ComponentType::Inductor = component.component_type {
-                self.add_inductor_transient(circuit, component, dt, prev_voltages)?;
+                self.add_inductor_transient(component, prev_fluxes[&component.name], &step.integrator)?;
             }
         }
 
+        // Couple the branch equations of inductors linked by K elements
+        for (coupling, first, second, mutual) in circuit.mutual_inductances()? {
+            self.add_mutual_inductance_transient(coupling, first, second, mutual, &step.integrator)?;
+        }
+
         Ok(())
     }
 
+    /// Linearize the circuit around the DC operating point `operating_point` (the full
+    /// solution vector) for small-signal AC analysis. The companion models are stamped
+    /// for a unit Backward Euler step while the reactive matrix collects their capacitances
+    /// and inductances, so AC and transient analysis share the device charge models.
+    pub fn assemble_ac(&mut self, circuit: &Circuit, operating_point: &DVector<f64>) -> Result<AcSystem> {
+        let mut reactive = self.matrix.clone();
+        reactive.clear();
+        self.reactive = Some(reactive);
+        let step = ChargeStep { integrator: Integrator::backward_euler(1.0), prev_solution: operating_point };
+        let assembled = self.assemble_transient(circuit, 0.0, step)
+            .and_then(|_| self.stamp_nonlinear(circuit, operating_point.as_slice(), Some(step)));
+        let reactive = self.reactive.take().unwrap_or_else(|| SparseMatrix::from_pattern(self.size, []));
+        self.charge_states.clear();
+        assembled?;
+
+        // 独立源只贡献交流幅度和相位
+        let mut excitation = DVector::zeros(self.size);
+        for component in circuit.components.iter().filter(|c| c.component_type.is_source()) {
+            let Some((magnitude, phase)) = component.ac else {
+                continue;
+            };
+            let value = Complex64::from_polar(magnitude, phase.to_radians());
+            if component.component_type == ComponentType::VoltageSource {
+                excitation[self.branch_index(&component.name)?] += value;
+            } else {
+                // 与直流分析中电流源的方向一致
+                if let Some(idx) = self.node_index(circuit, &component.nodes[0])? {
+                    excitation[idx] += value;
+                }
+                if let Some(idx) = self.node_index(circuit, &component.nodes[1])? {
+                    excitation[idx] -= value;
+                }
+            }
+        }
+
+        Ok(AcSystem { conductance: self.matrix.clone(), reactive, excitation, factorization: LuFactorization::new() })
+    }
+
+    /// Forget the linearization points of the nonlinear devices, e.g. before a new
+    /// Newton-Raphson solve that should not limit against a stale iterate
+    pub fn reset_junctions(&mut self) {
+        self.junction_voltages.clear();
+    }
+
+    /// Start the charge history of a transient analysis at `time`. The first
+    /// steps after it integrate with Backward Euler.
+    pub fn start_transient(&mut self, time: f64) {
+        self.charge_states.clear();
+        self.charge_history.clear();
+        self.charge_history.push_front(ChargeHistory { time, states: HashMap::new() });
+        self.steps_since_restart = 0;
+    }
+
+    /// Set the charge stored under `key` at the start of the transient analysis, e.g. the
+    /// `IC=` charge of a capacitor with UIC, in place of the one of the initial solution
+    pub fn set_initial_charge(&mut self, key: &str, charge: f64) {
+        if let Some(start) = self.charge_history.back_mut() {
+            start.states.insert(key.to_string(), ChargeState { charge, current: 0.0 });
+        }
+    }
+
+    /// Restart the integration after a discontinuity: the next steps are taken with
+    /// Backward Euler, which damps the ringing of the trapezoidal rule, and the order
+    /// is raised again as new time points are accepted
+    pub fn restart_integration(&mut self) {
+        self.steps_since_restart = 0;
+    }
+
+    /// Integration formula of `method` for a step from the last accepted time point to
+    /// `time`. Trapezoidal steps and Gear orders above 1 need accepted points since the
+    /// last restart, so the order ramps up to `max_order` after each restart.
+    pub fn integrator(&self, method: IntegrationMethod, max_order: usize, time: f64) -> Result<Integrator> {
+        if self.charge_history.is_empty() {
+            return Err(anyhow!("Transient analysis has not been started"));
+        }
+        // 重新起步后梯形法先走三步后向欧拉，衰减跨过跳变那一步留下的电流误差
+        let order = match method {
+            IntegrationMethod::Trapezoidal => self.steps_since_restart.saturating_sub(1).clamp(1, 2),
+            IntegrationMethod::Gear => self.steps_since_restart.clamp(1, max_order.clamp(1, MAX_GEAR_ORDER)),
+        };
+        let times: Vec<f64> = std::iter::once(time)
+            .chain(self.charge_history.iter().map(|point| point.time))
+            .collect();
+        Ok(Integrator::new(method, order.min(times.len() - 1), &times))
+    }
+
+    /// Record the charges of the capacitors and inductors at the converged solution of
+    /// a transient step, next to the device charges recorded in its last iteration
+    pub fn record_charges(&mut self, circuit: &Circuit, step: ChargeStep) -> Result<()> {
+        let solution = self.unknowns.clone();
+        let voltage = |values: &DVector<f64>, index: Option<usize>| index.map_or(0.0, |i| values.get(i).copied().unwrap_or(0.0));
+        for component in circuit.components.iter().filter(|c| c.component_type == ComponentType::Capacitor) {
+            let node1 = self.node_index(circuit, &component.nodes[0])?;
+            let node2 = self.node_index(circuit, &component.nodes[1])?;
+            let charge = component.value * (voltage(&solution, node1) - voltage(&solution, node2));
+            let prev_charge = self.previous_charge(&component.name,
+                component.value * (voltage(step.prev_solution, node1) - voltage(step.prev_solution, node2)));
+            self.integrate_charge(component.name.clone(), charge, prev_charge, &step.integrator);
+        }
+
+        let fluxes = self.inductor_fluxes(circuit, &solution)?;
+        let prev_fluxes = self.inductor_fluxes(circuit, step.prev_solution)?;
+        for (name, flux) in fluxes {
+            let prev_flux = prev_fluxes[&name];
+            self.integrate_charge(name, flux, prev_flux, &step.integrator);
+        }
+        Ok(())
+    }
+
+    /// Largest step the local truncation error of the recorded charges allows for a
+    /// step of `integrator` ending at `time`. `None` while too few points have been
+    /// accepted since the last restart to predict the charges.
+    pub fn truncation_timestep(&self, integrator: &Integrator, time: f64, tolerances: &TruncationTolerances) -> Option<f64> {
+        let points = integrator.order + 1;
+        if self.steps_since_restart < integrator.order || self.charge_history.len() < points {
+            return None;
+        }
+        let mut times = vec![time];
+        times.extend(self.charge_history.iter().take(points).map(|point| point.time));
+
+        let mut timestep = f64::INFINITY;
+        for (key, state) in &self.charge_states {
+            // 历史中缺少该电荷时（例如结电容刚变为非零）不参与判断
+            let history: Option<Vec<ChargeState>> = self.charge_history.iter().take(points)
+                .map(|point| point.states.get(key).copied())
+                .collect();
+            let Some(history) = history else {
+                continue;
+            };
+            let mut charges = vec![state.charge];
+            charges.extend(history.iter().map(|past| past.charge));
+            let currents = [state.current, history[0].current];
+            timestep = timestep.min(integrator.truncation_timestep(&times, &charges, currents, tolerances));
+        }
+        Some(timestep)
+    }
+
+    /// Accept the transient step ending at `time`: its recorded charges become the
+    /// most recent point of the history the next steps integrate from
+    pub fn accept_time_point(&mut self, time: f64) {
+        let states = std::mem::take(&mut self.charge_states);
+        self.charge_history.push_front(ChargeHistory { time, states });
+        self.charge_history.truncate(MAX_GEAR_ORDER + 1);
+        self.steps_since_restart += 1;
+    }
+
+    /// Discard the charges recorded for a rejected step before it is retried
+    pub fn reject_time_point(&mut self) {
+        self.charge_states.clear();
+    }
+
+    /// Charge stored under `key` at the previous time point: `from_solution` as computed
+    /// from the previous solution, unless an initial charge was set for the first step
+    fn previous_charge(&self, key: &str, from_solution: f64) -> f64 {
+        match self.charge_history.front() {
+            Some(point) if self.charge_history.len() == 1 => point.states.get(key).map_or(from_solution, |state| state.charge),
+            _ => from_solution,
+        }
+    }
+
+    /// Current of the charge stored under `key` when it holds `charge` now and held
+    /// `prev_charge` at the previous time point; older charges and the previous
+    /// current come from the history
+    fn charge_current(&self, key: &str, charge: f64, prev_charge: f64, integrator: &Integrator) -> f64 {
+        // 没有记录的元件（如电容为零的结）按零电荷处理
+        let state = |index: usize| self.charge_history.get(index)
+            .and_then(|point| point.states.get(key))
+            .copied()
+            .unwrap_or_default();
+        let mut current = integrator.coefficients[0] * charge + integrator.coefficients[1] * prev_charge;
+        if integrator.current_factor != 0.0 {
+            current -= integrator.current_factor * state(0).current;
+        }
+        for j in 2..=integrator.order.min(MAX_GEAR_ORDER) {
+            current += integrator.coefficients[j] * state(j - 1).charge;
+        }
+        current
+    }
+
+    /// Integrate the charge stored under `key` over the present step and record it
+    /// for the history; returns its current
+    fn integrate_charge(&mut self, key: String, charge: f64, prev_charge: f64, integrator: &Integrator) -> f64 {
+        let current = self.charge_current(&key, charge, prev_charge, integrator);
+        self.charge_states.insert(key, ChargeState { charge, current });
+        current
+    }
+
+    /// Stamp the nonlinear devices linearized around the iterate `solution`.
+    /// In transient analysis `step` adds the companion models of their stored charge.
+    /// Junction voltages are limited against the previous linearization point;
+    /// returns true if any of them was limited.
+    pub fn stamp_nonlinear(&mut self, circuit: &Circuit, solution: &[f64], step: Option<ChargeStep>) -> Result<bool> {
+        let mut limited = false;
+        for component in circuit.nonlinear_components() {
+            limited |= match component.component_type {
+                ComponentType::Diode => self.stamp_diode(circuit, component, solution, step)?,
+                ComponentType::Mosfet { .. } => self.stamp_mosfet(circuit, component, solution, step)?,
+                ComponentType::Bjt { .. } => self.stamp_bjt(circuit, component, solution, step)?,
+                ComponentType::Jfet { .. } => self.stamp_jfet(circuit, component, solution, step)?,
+                _ => return Err(anyhow!("No device model available for {}", component.name)),
+            };
+        }
+        Ok(limited)
+    }
+
+    /// Stamp the companion model of a diode: conductance gd in parallel with
+    /// the current source id - gd*vd across the junction, the series resistance
+    /// to the internal anode node, and the junction charge in transient analysis
+    fn stamp_diode(&mut self, circuit: &Circuit, component: &Component, solution: &[f64], step: Option<ChargeStep>) -> Result<bool> {
+        let diode = diode_instance(circuit, component)?;
+        let slots = self.slots_of(component)?;
+        let anode = self.node_index(circuit, &component.nodes[0])?;
+        let cathode = self.node_index(circuit, &component.nodes[1])?;
+        let junction = match self.internal_nodes.get(&format!("{}#anode", component.name)) {
+            Some(&internal) => {
+                self.stamp_conductance(&slots, anode, Some(internal), 1.0 / diode.rs);
+                Some(internal)
+            }
+            None => anode,
+        };
+        let voltage = |values: &[f64], index: Option<usize>| index.map_or(0.0, |i| values[i]);
+
+        let vnew = voltage(solution, junction) - voltage(solution, cathode);
+        let (vd, limited) = match self.junction_voltages.get(&component.name) {
+            Some(&vold) => diode.limit(vnew, vold),
+            None => (vnew, false),
+        };
+        self.junction_voltages.insert(component.name.clone(), vd);
+
+        let (current, conductance) = diode.current(vd);
+        self.stamp_conductance(&slots, junction, cathode, conductance);
+        self.stamp_current(junction, cathode, current - conductance * vd);
+
+        // Companion model of the junction charge: i = i(q(vd)) linearized around vd
+        if let Some(step) = step {
+            let prev = step.prev_solution.as_slice();
+            let vd_prev = voltage(prev, junction) - voltage(prev, cathode);
+            let (charge, capacitance) = diode.charge(vd);
+            let (prev_charge, _) = diode.charge(vd_prev);
+            if capacitance > 0.0 {
+                let integrator = step.integrator;
+                let current = self.integrate_charge(format!("{}#qd", component.name), charge, prev_charge, &integrator);
+                self.stamp_capacitance(&slots, [junction, cathode], [junction, cathode], capacitance, integrator.conductance());
+                self.stamp_current(junction, cathode, current - integrator.conductance() * capacitance * vd);
+            }
+        }
+
+        Ok(limited)
+    }
+
+    /// Stamp the linearized Level-1 MOSFET: the channel current as a conductance gds
+    /// plus transconductances gm and gmbs from the effective source, the two bulk
+    /// junctions, and in transient analysis the gate and junction capacitances.
+    /// Drain and source swap roles when the transistor operates in reverse (Vds < 0).
+    fn stamp_mosfet(&mut self, circuit: &Circuit, component: &Component, solution: &[f64], step: Option<ChargeStep>) -> Result<bool> {
+        let mos = mosfet_instance(circuit, component)?;
+        let slots = self.slots_of(component)?;
+        let nodes = component.nodes.iter()
+            .map(|name| self.node_index(circuit, name))
+            .collect::<Result<Vec<_>>>()?;
+        let (drain, gate, source, bulk) = (nodes[0], nodes[1], nodes[2], nodes[3]);
+        let voltage = |values: &[f64], index: Option<usize>| index.map_or(0.0, |i| values[i]);
+        // n 沟道符号约定下的偏置 [Vgs, Vds, Vbs]
+        let bias = |values: &[f64]| {
+            let vs = voltage(values, source);
+            [gate, drain, bulk].map(|node| mos.sign * (voltage(values, node) - vs))
+        };
+
+        let keys = ["vgs", "vds", "vbs"].map(|terminal| format!("{}#{}", component.name, terminal));
+        let new = bias(solution);
+        let old = keys.iter().map(|key| self.junction_voltages.get(key).copied()).collect::<Option<Vec<f64>>>();
+        let ([vgs, vds, vbs], limited) = match old {
+            Some(old) => mos.limit(new, [old[0], old[1], old[2]]),
+            None => (new, false),
+        };
+        for (key, value) in keys.into_iter().zip([vgs, vds, vbs]) {
+            self.junction_voltages.insert(key, value);
+        }
+
+        // 反向工作时交换漏源
+        let reversed = vds < 0.0;
+        let (eff_drain, eff_source, vgs_eff, vds_eff, vbs_eff) = if reversed {
+            (source, drain, vgs - vds, -vds, vbs - vds)
+        } else {
+            (drain, source, vgs, vds, vbs)
+        };
+        let op = mos.channel(vgs_eff, vds_eff, vbs_eff);
+        self.stamp_transconductance(&slots, eff_drain, eff_source, eff_drain, eff_source, op.gds);
+        self.stamp_transconductance(&slots, eff_drain, eff_source, gate, eff_source, op.gm);
+        self.stamp_transconductance(&slots, eff_drain, eff_source, bulk, eff_source, op.gmbs);
+        let ieq = op.ids - op.gm * vgs_eff - op.gds * vds_eff - op.gmbs * vbs_eff;
+        self.stamp_current(eff_drain, eff_source, mos.sign * ieq);
+
+        let junctions = [(&mos.bulk_source, source, vbs), (&mos.bulk_drain, drain, vbs - vds)];
+        for (junction, node, vbx) in junctions {
+            let (current, conductance) = junction.current(vbx);
+            self.stamp_conductance(&slots, bulk, node, conductance);
+            self.stamp_current(bulk, node, mos.sign * (current - conductance * vbx));
+        }
+
+        if let Some(step) = step {
+            let prev = step.prev_solution.as_slice();
+            let [_, prev_vds, prev_vbs] = bias(prev);
+
+            // 结电荷：i = i(q(v)) 在 v 处线性化
+            let integrator = step.integrator;
+            let junctions = [
+                ("qbs", &mos.bulk_source, source, vbs, prev_vbs),
+                ("qbd", &mos.bulk_drain, drain, vbs - vds, prev_vbs - prev_vds),
+            ];
+            for (name, junction, node, vbx, prev_vbx) in junctions {
+                let (charge, capacitance) = junction.charge(vbx);
+                if capacitance > 0.0 {
+                    let key = format!("{}#{}", component.name, name);
+                    let current = self.integrate_charge(key, charge, junction.charge(prev_vbx).0, &integrator);
+                    self.stamp_capacitance(&slots, [bulk, node], [bulk, node], capacitance, integrator.conductance());
+                    self.stamp_current(bulk, node, mos.sign * (current - integrator.conductance() * capacitance * vbx));
+                }
+            }
+
+            // Meyer 栅电容在当前迭代点取值，按线性电容处理
+            let (mut cgs, mut cgd, cgb) = mos.gate_capacitances(vgs_eff, vds_eff, vbs_eff);
+            if reversed {
+                std::mem::swap(&mut cgs, &mut cgd);
+            }
+            let gate_capacitors = [
+                ("qgs", source, cgs + mos.cgs_overlap),
+                ("qgd", drain, cgd + mos.cgd_overlap),
+                ("qgb", bulk, cgb + mos.cgb_overlap),
+            ];
+            for (name, node, capacitance) in gate_capacitors {
+                if capacitance > 0.0 {
+                    let v = voltage(solution, gate) - voltage(solution, node);
+                    let prev_voltage = voltage(prev, gate) - voltage(prev, node);
+                    let key = format!("{}#{}", component.name, name);
+                    let current = self.integrate_charge(key, capacitance * v, capacitance * prev_voltage, &integrator);
+                    self.stamp_capacitance(&slots, [gate, node], [gate, node], capacitance, integrator.conductance());
+                    self.stamp_current(gate, node, current - integrator.conductance() * capacitance * v);
+                }
+            }
+        }
+
+        Ok(limited)
+    }
+
+    /// Stamp the linearized Gummel-Poon transistor between its internal nodes: the collector
+    /// current from C' to E' and the base current from B' to E' as conductances plus
+    /// transconductances, the series resistances RC, RB (charge-modulated) and RE, and in
+    /// transient analysis the junction, diffusion and substrate charges
+    fn stamp_bjt(&mut self, circuit: &Circuit, component: &Component, solution: &[f64], step: Option<ChargeStep>) -> Result<bool> {
+        let bjt = bjt_instance(circuit, component)?;
+        let slots = self.slots_of(component)?;
+        let collector = self.node_index(circuit, &component.nodes[0])?;
+        let base = self.node_index(circuit, &component.nodes[1])?;
+        let emitter = self.node_index(circuit, &component.nodes[2])?;
+        // 没有衬底节点时衬底接地
+        let substrate = match component.nodes.get(3) {
+            Some(name) => self.node_index(circuit, name)?,
+            None => None,
+        };
+        let internal = |terminal: &str, external: Option<usize>| {
+            self.internal_nodes.get(&format!("{}#{}", component.name, terminal)).copied().map_or(external, Some)
+        };
+        let (collector_int, base_int, emitter_int) = (internal("collector", collector), internal("base", base), internal("emitter", emitter));
+
+        let voltage = |values: &[f64], index: Option<usize>| index.map_or(0.0, |i| values[i]);
+        let across = |values: &[f64], node1: Option<usize>, node2: Option<usize>| {
+            bjt.sign * (voltage(values, node1) - voltage(values, node2))
+        };
+
+        let keys = ["vbe", "vbc"].map(|junction| format!("{}#{}", component.name, junction));
+        let new = [across(solution, base_int, emitter_int), across(solution, base_int, collector_int)];
+        let old = keys.iter().map(|key| self.junction_voltages.get(key).copied()).collect::<Option<Vec<f64>>>();
+        let ([vbe, vbc], limited) = match old {
+            Some(old) => bjt.limit(new, [old[0], old[1]]),
+            None => (new, false),
+        };
+        for (key, value) in keys.into_iter().zip([vbe, vbc]) {
+            self.junction_voltages.insert(key, value);
+        }
+
+        let op = bjt.evaluate(vbe, vbc);
+        if collector_int != collector {
+            self.stamp_conductance(&slots, collector, collector_int, 1.0 / bjt.rc);
+        }
+        if base_int != base {
+            self.stamp_conductance(&slots, base, base_int, 1.0 / op.rbb);
+        }
+        if emitter_int != emitter {
+            self.stamp_conductance(&slots, emitter, emitter_int, 1.0 / bjt.re);
+        }
+
+        // Ic = Ic0 + (gm + go)*dVbe - (gmu + go)*dVbc
+        self.stamp_transconductance(&slots, collector_int, emitter_int, base_int, emitter_int, op.gm + op.go);
+        self.stamp_transconductance(&slots, collector_int, emitter_int, base_int, collector_int, -(op.gmu + op.go));
+        let ieq = op.ic - (op.gm + op.go) * vbe + (op.gmu + op.go) * vbc;
+        self.stamp_current(collector_int, emitter_int, bjt.sign * ieq);
+
+        // Ib = Ib0 + gpi*dVbe + gmu*dVbc
+        self.stamp_transconductance(&slots, base_int, emitter_int, base_int, emitter_int, op.gpi);
+        self.stamp_transconductance(&slots, base_int, emitter_int, base_int, collector_int, op.gmu);
+        self.stamp_current(base_int, emitter_int, bjt.sign * (op.ib - op.gpi * vbe - op.gmu * vbc));
+
+        if let Some(step) = step {
+            let prev = step.prev_solution.as_slice();
+            let vbx = across(solution, base, collector_int);
+            let vsc = across(solution, substrate, collector_int);
+            let (prev_vbe, prev_vbc) = (across(prev, base_int, emitter_int), across(prev, base_int, collector_int));
+            let charges = bjt.charges(&op, vbe, vbc, vbx, vsc);
+            let prev_charges = bjt.charges(&bjt.evaluate(prev_vbe, prev_vbc), prev_vbe, prev_vbc,
+                across(prev, base, collector_int), across(prev, substrate, collector_int));
+
+            // Qbe 同时依赖 Vbe 和 Vbc
+            let integrator = step.integrator;
+            let scale = integrator.conductance();
+            let (qbe, cbe, cross) = charges.qbe;
+            let current = self.integrate_charge(format!("{}#qbe", component.name), qbe, prev_charges.qbe.0, &integrator);
+            self.stamp_capacitance(&slots, [base_int, emitter_int], [base_int, emitter_int], cbe, scale);
+            self.stamp_capacitance(&slots, [base_int, emitter_int], [base_int, collector_int], cross, scale);
+            self.stamp_current(base_int, emitter_int, bjt.sign * (current - scale * (cbe * vbe + cross * vbc)));
+
+            let two_terminal = [
+                ("qbc", base_int, collector_int, charges.qbc, prev_charges.qbc.0, vbc),
+                ("qbx", base, collector_int, charges.qbx, prev_charges.qbx.0, vbx),
+                ("qsc", substrate, collector_int, charges.qsc, prev_charges.qsc.0, vsc),
+            ];
+            for (name, node1, node2, (charge, capacitance), prev_charge, v) in two_terminal {
+                if capacitance > 0.0 {
+                    let current = self.integrate_charge(format!("{}#{}", component.name, name), charge, prev_charge, &integrator);
+                    self.stamp_capacitance(&slots, [node1, node2], [node1, node2], capacitance, scale);
+                    self.stamp_current(node1, node2, bjt.sign * (current - scale * capacitance * v));
+                }
+            }
+        }
+
+        Ok(limited)
+    }
+
+    /// Stamp the linearized JFET: the channel current between the internal drain and
+    /// source as gds plus gm from the effective source, the two gate junctions, RD and RS,
+    /// and in transient analysis the gate junction charges. Drain and source swap roles
+    /// when Vds < 0.
+    fn stamp_jfet(&mut self, circuit: &Circuit, component: &Component, solution: &[f64], step: Option<ChargeStep>) -> Result<bool> {
+        let jfet = jfet_instance(circuit, component)?;
+        let slots = self.slots_of(component)?;
+        let drain = self.node_index(circuit, &component.nodes[0])?;
+        let gate = self.node_index(circuit, &component.nodes[1])?;
+        let source = self.node_index(circuit, &component.nodes[2])?;
+        let internal = |terminal: &str, external: Option<usize>| {
+            self.internal_nodes.get(&format!("{}#{}", component.name, terminal)).copied().map_or(external, Some)
+        };
+        let (drain_int, source_int) = (internal("drain", drain), internal("source", source));
+
+        let voltage = |values: &[f64], index: Option<usize>| index.map_or(0.0, |i| values[i]);
+        let across = |values: &[f64], node1: Option<usize>, node2: Option<usize>| {
+            jfet.sign * (voltage(values, node1) - voltage(values, node2))
+        };
+
+        let keys = ["vgs", "vgd"].map(|junction| format!("{}#{}", component.name, junction));
+        let new = [across(solution, gate, source_int), across(solution, gate, drain_int)];
+        let old = keys.iter().map(|key| self.junction_voltages.get(key).copied()).collect::<Option<Vec<f64>>>();
+        let ([vgs, vgd], limited) = match old {
+            Some(old) => jfet.limit(new, [old[0], old[1]]),
+            None => (new, false),
+        };
+        for (key, value) in keys.into_iter().zip([vgs, vgd]) {
+            self.junction_voltages.insert(key, value);
+        }
+
+        if drain_int != drain {
+            self.stamp_conductance(&slots, drain, drain_int, 1.0 / jfet.rd);
+        }
+        if source_int != source {
+            self.stamp_conductance(&slots, source, source_int, 1.0 / jfet.rs);
+        }
+
+        // 反向工作时交换漏源
+        let vds = vgs - vgd;
+        let (eff_drain, eff_source, vgs_eff, vds_eff) = if vds >= 0.0 {
+            (drain_int, source_int, vgs, vds)
+        } else {
+            (source_int, drain_int, vgd, -vds)
+        };
+        let (ids, gm, gds) = jfet.channel(vgs_eff, vds_eff);
+        self.stamp_transconductance(&slots, eff_drain, eff_source, eff_drain, eff_source, gds);
+        self.stamp_transconductance(&slots, eff_drain, eff_source, gate, eff_source, gm);
+        self.stamp_current(eff_drain, eff_source, jfet.sign * (ids - gm * vgs_eff - gds * vds_eff));
+
+        let junctions = [(&jfet.gate_source, source_int, vgs), (&jfet.gate_drain, drain_int, vgd)];
+        for (junction, node, v) in junctions {
+            let (current, conductance) = junction.current(v);
+            self.stamp_conductance(&slots, gate, node, conductance);
+            self.stamp_current(gate, node, jfet.sign * (current - conductance * v));
+        }
+
+        // 栅结电荷
+        if let Some(step) = step {
+            let prev = step.prev_solution.as_slice();
+            let integrator = step.integrator;
+            let junctions = [
+                ("qgs", &jfet.gate_source, source_int, vgs, across(prev, gate, source_int)),
+                ("qgd", &jfet.gate_drain, drain_int, vgd, across(prev, gate, drain_int)),
+            ];
+            for (name, junction, node, v, prev_v) in junctions {
+                let (charge, capacitance) = junction.charge(v);
+                if capacitance > 0.0 {
+                    let key = format!("{}#{}", component.name, name);
+                    let current = self.integrate_charge(key, charge, junction.charge(prev_v).0, &integrator);
+                    self.stamp_capacitance(&slots, [gate, node], [gate, node], capacitance, integrator.conductance());
+                    self.stamp_current(gate, node, jfet.sign * (current - integrator.conductance() * capacitance * v));
+                }
+            }
+        }
+
+        Ok(limited)
+    }
+
+    /// Matrix index of a node, `None` for ground
+    fn node_index(&self, circuit: &Circuit, name: &str) -> Result<Option<usize>> {
+        let id = circuit.get_node_id(name)
+            .ok_or_else(|| anyhow!("Node {} not found", name))?;
+        Ok(self.node_map.get(&id).copied())
+    }
+
+    /// Stamp slots of a component, resolved when the system was built
+    fn slots_of(&self, component: &Component) -> Result<Rc<StampSlots>> {
+        self.stamp_slots.get(&component.name).cloned()
+            .ok_or_else(|| anyhow!("Component {} is not part of the MNA system", component.name))
+    }
+
+    /// Add `value` to the matrix entry `(row, col)` through the slots of its component
+    fn stamp_entry(&mut self, slots: &StampSlots, row: usize, col: usize, value: f64) {
+        self.matrix.values_mut()[slots.slot(row, col)] += value;
+    }
+
+    /// Stamp a conductance between two nodes
+    fn stamp_conductance(&mut self, slots: &StampSlots, node1: Option<usize>, node2: Option<usize>, conductance: f64) {
+        if let Some(idx1) = node1 {
+            self.stamp_entry(slots, idx1, idx1, conductance);
+        }
+        if let Some(idx2) = node2 {
+            self.stamp_entry(slots, idx2, idx2, conductance);
+        }
+        if let (Some(idx1), Some(idx2)) = (node1, node2) {
+            self.stamp_entry(slots, idx1, idx2, -conductance);
+            self.stamp_entry(slots, idx2, idx1, -conductance);
+        }
+    }
+
+    /// Stamp a current from `out1` to `out2` equal to `transconductance` times the voltage from `in1` to `in2`
+    fn stamp_transconductance(&mut self, slots: &StampSlots, out1: Option<usize>, out2: Option<usize>, in1: Option<usize>, in2: Option<usize>,
+                              transconductance: f64) {
+        stamp_coupling(&mut self.matrix, slots, [out1, out2], [in1, in2], transconductance);
+    }
+
+    /// Stamp the companion conductance `scale`*C of a charge flowing from `outputs[0]` to
+    /// `outputs[1]` whose derivative with respect to the voltage from `inputs[0]` to
+    /// `inputs[1]` is `capacitance`,
+    /// where `scale` is the integrator's coefficient of the present charge (1/dt for
+    /// Backward Euler). While an AC system is assembled the capacitance itself goes
+    /// into the reactive matrix.
+    fn stamp_capacitance(&mut self, slots: &StampSlots, outputs: [Option<usize>; 2], inputs: [Option<usize>; 2],
+                         capacitance: f64, scale: f64) {
+        match self.reactive.as_mut() {
+            Some(reactive) => stamp_coupling(reactive, slots, outputs, inputs, capacitance),
+            None => stamp_coupling(&mut self.matrix, slots, outputs, inputs, capacitance * scale),
+        }
+    }
+
+    /// Stamp the companion term -`scale`*L of a (mutual) inductance coupling the branch
+    /// equation of `branch1` to the current of `branch2`, or -L into the reactive matrix
+    fn stamp_inductance(&mut self, slots: &StampSlots, branch1: usize, branch2: usize, inductance: f64, scale: f64) {
+        let slot = slots.slot(branch1, branch2);
+        match self.reactive.as_mut() {
+            Some(reactive) => reactive.values_mut()[slot] -= inductance,
+            None => self.matrix.values_mut()[slot] -= inductance * scale,
+        }
+    }
+
+    /// Stamp a current flowing from `node1` to `node2` through the element
+    fn stamp_current(&mut self, node1: Option<usize>, node2: Option<usize>, current: f64) {
+        if let Some(idx1) = node1 {
+            self.rhs[idx1] -= current;
+        }
+        if let Some(idx2) = node2 {
+            self.rhs[idx2] += current;
+        }
+    }
+
     /// Add a linear component (R, L, C) to the system
     fn add_linear_component(&mut self, circuit: &Circuit, component: &Component) -> Result<()> {
+        // 互感没有节点，直流下耦合电感都是短路，只在瞬态分析中加入互感项
+        if let ComponentType::MutualInductance { .. } = component.component_type {
+            return Ok(());
+        }
+
         let node1_name = &component.nodes[0];
         let node2_name = &component.nodes[1];
 
@@ -127,26 +871,16 @@ impl MnaSystem {
         let node2_id = circuit.get_node_id(node2_name)
             .ok_or_else(|| anyhow!("Node {} not found", node2_name))?;
 
-        let node1_idx = self.node_map.get(&node1_id);
-        let node2_idx = self.node_map.get(&node2_id);
+        let node1_idx = self.node_map.get(&node1_id).copied();
+        let node2_idx = self.node_map.get(&node2_id).copied();
 
         match component.component_type {
             ComponentType::Resistor => {
                 let conductance = component.conductance()?;
-                
-                // Add to diagonal elements
-                if let Some(&idx1) = node1_idx {
-                    self.matrix[(idx1, idx1)] += conductance;
-                }
-                if let Some(&idx2) = node2_idx {
-                    self.matrix[(idx2, idx2)] += conductance;
-                }
+                let slots = self.slots_of(component)?;
 
-                // Add to off-diagonal elements
-                if let (Some(&idx1), Some(&idx2)) = (node1_idx, node2_idx) {
-                    self.matrix[(idx1, idx2)] -= conductance;
-                    self.matrix[(idx2, idx1)] -= conductance;
-                }
+                // Add to the diagonal and off-diagonal elements
+                self.stamp_conductance(&slots, node1_idx, node2_idx, conductance);
             }
             ComponentType::Capacitor => {
                 // For DC analysis, capacitors are open circuits (infinite impedance)
@@ -154,73 +888,102 @@ impl MnaSystem {
             }
             ComponentType::Inductor => {
                 // For DC analysis, inductors are short circuits (zero impedance)
-                // This requires special handling with current variables
+                // carrying their own branch current
                 self.add_inductor_dc(circuit, component)?;
             }
+            ref controlled if controlled.is_controlled_source() => {
+                self.add_controlled_source(circuit, component)?;
+            }
             _ => {}
         }
 
         Ok(())
     }
 
-    /// Add inductor for DC analysis (treated as short circuit)
-    fn add_inductor_dc(&mut self, circuit: &Circuit, component: &Component) -> Result<()> {
-        // For DC analysis, inductor acts like a voltage source with 0V
-        // This constrains the voltage across the inductor to be zero
-        
-        let node1_name = &component.nodes[0];
-        let node2_name = &component.nodes[1];
-
-        let node1_id = circuit.get_node_id(node1_name)
-            .ok_or_else(|| anyhow!("Node {} not found", node1_name))?;
-        let node2_id = circuit.get_node_id(node2_name)
-            .ok_or_else(|| anyhow!("Node {} not found", node2_name))?;
-
-        let node1_idx = self.node_map.get(&node1_id);
-        let node2_idx = self.node_map.get(&node2_id);
-
-        // Find or create current variable for this inductor
-        let current_idx = if let Some(&idx) = self.voltage_source_map.get(&component.name) {
-            idx
-        } else {
-            // Add as additional unknown
-            let _idx = self.size;
-            // Note: This would require expanding the system dynamically
-            // For simplicity, we'll treat inductors as very small resistors for DC
-            let small_resistance = 1e-12;
-            let conductance = 1.0 / small_resistance;
-            
-            if let Some(&idx1) = node1_idx {
-                self.matrix[(idx1, idx1)] += conductance;
+    /// Add a linear controlled source. The output current of F and G flows from the
+    /// positive node through the source to the negative node, as in SPICE; F and H are
+    /// controlled by the branch current of their voltage source, which flows the same way.
+    /// E and H add the branch equation V(out+) - V(out-) = gain * control.
+    fn add_controlled_source(&mut self, circuit: &Circuit, component: &Component) -> Result<()> {
+        let nodes = component.nodes.iter()
+            .map(|name| self.node_index(circuit, name))
+            .collect::<Result<Vec<_>>>()?;
+        let gain = component.value;
+        let slots = self.slots_of(component)?;
+
+        match &component.component_type {
+            ComponentType::Vcvs => {
+                let branch = self.branch_index(&component.name)?;
+                self.stamp_branch(&slots, nodes[0], nodes[1], branch);
+                if let Some(idx) = nodes[2] {
+                    self.stamp_entry(&slots, branch, idx, -gain);
+                }
+                if let Some(idx) = nodes[3] {
+                    self.stamp_entry(&slots, branch, idx, gain);
+                }
             }
-            if let Some(&idx2) = node2_idx {
-                self.matrix[(idx2, idx2)] += conductance;
+            ComponentType::Vccs => {
+                self.stamp_transconductance(&slots, nodes[0], nodes[1], nodes[2], nodes[3], gain);
             }
-            if let (Some(&idx1), Some(&idx2)) = (node1_idx, node2_idx) {
-                self.matrix[(idx1, idx2)] -= conductance;
-                self.matrix[(idx2, idx1)] -= conductance;
+            ComponentType::Cccs { control } => {
+                let controlling = self.control_branch(circuit, control)?;
+                if let Some(idx) = nodes[0] {
+                    self.stamp_entry(&slots, idx, controlling, gain);
+                }
+                if let Some(idx) = nodes[1] {
+                    self.stamp_entry(&slots, idx, controlling, -gain);
+                }
             }
-            return Ok(());
-        };
+            ComponentType::Ccvs { control } => {
+                let branch = self.branch_index(&component.name)?;
+                let controlling = self.control_branch(circuit, control)?;
+                self.stamp_branch(&slots, nodes[0], nodes[1], branch);
+                self.stamp_entry(&slots, branch, controlling, -gain);
+            }
+            _ => return Err(anyhow!("Component {} is not a controlled source", component.name)),
+        }
+
+        Ok(())
+    }
+
+    /// Row/column of the branch current of a voltage source or E/H controlled source
+    fn branch_index(&self, name: &str) -> Result<usize> {
+        self.voltage_source_map.get(name).copied()
+            .ok_or_else(|| anyhow!("Branch current of {} not found in mapping", name))
+    }
+
+    /// Branch current index of the voltage source controlling an F or H element
+    fn control_branch(&self, circuit: &Circuit, control: &str) -> Result<usize> {
+        let source = circuit.find_component(control)
+            .ok_or_else(|| anyhow!("Controlling source {} not found", control))?;
+        self.branch_index(&source.name)
+    }
 
-        // Add voltage constraint: V_node1 - V_node2 = 0
-        if let Some(&idx1) = node1_idx {
-            self.matrix[(current_idx, idx1)] = 1.0;
-            self.matrix[(idx1, current_idx)] = 1.0;
+    /// Stamp a branch current flowing from `node1` through the element to `node2`:
+    /// it leaves `node1` in KCL, and its branch equation starts with V(node1) - V(node2)
+    fn stamp_branch(&mut self, slots: &StampSlots, node1: Option<usize>, node2: Option<usize>, branch: usize) {
+        if let Some(idx1) = node1 {
+            self.stamp_entry(slots, idx1, branch, 1.0);
+            self.stamp_entry(slots, branch, idx1, 1.0);
         }
-        if let Some(&idx2) = node2_idx {
-            self.matrix[(current_idx, idx2)] = -1.0;
-            self.matrix[(idx2, current_idx)] = -1.0;
+        if let Some(idx2) = node2 {
+            self.stamp_entry(slots, idx2, branch, -1.0);
+            self.stamp_entry(slots, branch, idx2, -1.0);
         }
+    }
 
-        // RHS is 0 for inductor voltage constraint
-        self.rhs[current_idx] = 0.0;
-
+    /// Add inductor for DC analysis: a short circuit, i.e. the branch equation V(node1) - V(node2) = 0
+    fn add_inductor_dc(&mut self, circuit: &Circuit, component: &Component) -> Result<()> {
+        let node1 = self.node_index(circuit, &component.nodes[0])?;
+        let node2 = self.node_index(circuit, &component.nodes[1])?;
+        let branch = self.branch_index(&component.name)?;
+        let slots = self.slots_of(component)?;
+        self.stamp_branch(&slots, node1, node2, branch);
         Ok(())
     }
 
     /// Add a current source to the system
-    fn add_current_source(&mut self, circuit: &Circuit, component: &Component) -> Result<()> {
+    fn add_current_source(&mut self, circuit: &Circuit, component: &Component, time: Option<f64>) -> Result<()> {
         let node1_name = &component.nodes[0]; // Positive terminal
         let node2_name = &component.nodes[1]; // Negative terminal
 
@@ -229,7 +992,7 @@ impl MnaSystem {
         let node2_id = circuit.get_node_id(node2_name)
             .ok_or_else(|| anyhow!("Node {} not found", node2_name))?;
 
-        let current = component.value;
+        let current = time.map_or(component.value, |t| component.value_at(t));
 
         // Add current to RHS vector
         if let Some(&idx1) = self.node_map.get(&node1_id) {
@@ -243,37 +1006,26 @@ impl MnaSystem {
     }
 
     /// Add a voltage source to the system
-    fn add_voltage_source(&mut self, circuit: &Circuit, component: &Component) -> Result<()> {
-        let node1_name = &component.nodes[0]; // Positive terminal
-        let node2_name = &component.nodes[1]; // Negative terminal
-
-        let node1_id = circuit.get_node_id(node1_name)
-            .ok_or_else(|| anyhow!("Node {} not found", node1_name))?;
-        let node2_id = circuit.get_node_id(node2_name)
-            .ok_or_else(|| anyhow!("Node {} not found", node2_name))?;
-
-        let voltage = component.value;
-        let vs_idx = self.voltage_source_map.get(&component.name)
-            .ok_or_else(|| anyhow!("Voltage source {} not found in mapping", component.name))?;
+    fn add_voltage_source(&mut self, circuit: &Circuit, component: &Component, time: Option<f64>) -> Result<()> {
+        let node1 = self.node_index(circuit, &component.nodes[0])?; // Positive terminal
+        let node2 = self.node_index(circuit, &component.nodes[1])?; // Negative terminal
+        let branch = self.branch_index(&component.name)?;
+        let slots = self.slots_of(component)?;
+        let voltage = time.map_or(component.value, |t| component.value_at(t));
 
         // Add voltage constraint: V_node1 - V_node2 = V_source
-        if let Some(&idx1) = self.node_map.get(&node1_id) {
-            self.matrix[(*vs_idx, idx1)] = 1.0;
-            self.matrix[(idx1, *vs_idx)] = 1.0;
-        }
-        if let Some(&idx2) = self.node_map.get(&node2_id) {
-            self.matrix[(*vs_idx, idx2)] = -1.0;
-            self.matrix[(idx2, *vs_idx)] = -1.0;
-        }
+        // 受控源先于电压源印记，同一位置的元素必须累加
+        self.stamp_branch(&slots, node1, node2, branch);
 
         // Set RHS for voltage source constraint
-        self.rhs[*vs_idx] = voltage;
+        self.rhs[branch] = voltage;
 
         Ok(())
     }
 
-    /// Add capacitor contribution for transient analysis
-    fn add_capacitor_transient(&mut self, circuit: &Circuit, component: &Component, dt: f64, prev_voltages: &DVector<f64>) -> Result<()> {
+    /// Add capacitor contribution for transient analysis: the companion conductance
+    /// in parallel with the current the integrator carries over from earlier time points
+    fn add_capacitor_transient(&mut self, circuit: &Circuit, component: &Component, step: ChargeStep) -> Result<()> {
         let node1_name = &component.nodes[0];
         let node2_name = &component.nodes[1];
 
@@ -282,74 +1034,76 @@ impl MnaSystem {
         let node2_id = circuit.get_node_id(node2_name)
             .ok_or_else(|| anyhow!("Node {} not found", node2_name))?;
 
-        let node1_idx = self.node_map.get(&node1_id);
-        let node2_idx = self.node_map.get(&node2_id);
+        let node1_idx = self.node_map.get(&node1_id).copied();
+        let node2_idx = self.node_map.get(&node2_id).copied();
 
         let capacitance = component.value;
-        let conductance = capacitance / dt; // Backward Euler
+        let prev_voltage = |index: Option<usize>| index.and_then(|i| step.prev_solution.get(i)).copied().unwrap_or(0.0);
+        let prev_charge = self.previous_charge(&component.name, capacitance * (prev_voltage(node1_idx) - prev_voltage(node2_idx)));
 
-        // Add to matrix (same as resistor with G = C/dt)
-        if let Some(&idx1) = node1_idx {
-            self.matrix[(idx1, idx1)] += conductance;
-        }
-        if let Some(&idx2) = node2_idx {
-            self.matrix[(idx2, idx2)] += conductance;
-        }
-        if let (Some(&idx1), Some(&idx2)) = (node1_idx, node2_idx) {
-            self.matrix[(idx1, idx2)] -= conductance;
-            self.matrix[(idx2, idx1)] -= conductance;
-        }
+        // Add to matrix (same as resistor with G = C*scale)
+        let slots = self.slots_of(component)?;
+        self.stamp_capacitance(&slots, [node1_idx, node2_idx], [node1_idx, node2_idx], capacitance, step.integrator.conductance());
 
-        // Add current source term based on previous voltage
-        if prev_voltages.len() >= self.num_nodes {
-            let prev_v1 = if let Some(&idx1) = node1_idx { prev_voltages[idx1] } else { 0.0 };
-            let prev_v2 = if let Some(&idx2) = node2_idx { prev_voltages[idx2] } else { 0.0 };
-            let prev_voltage_across = prev_v1 - prev_v2;
-            let current_source = conductance * prev_voltage_across;
+        // 当前电荷为零时的电流即历史项
+        let history = self.charge_current(&component.name, 0.0, prev_charge, &step.integrator);
+        self.stamp_current(node1_idx, node2_idx, history);
 
-            if let Some(&idx1) = node1_idx {
-                self.rhs[idx1] += current_source;
-            }
-            if let Some(&idx2) = node2_idx {
-                self.rhs[idx2] -= current_source;
-            }
-        }
+        Ok(())
+    }
+
+    /// Add inductor contribution for transient analysis. The branch voltage is the
+    /// derivative of the flux `L*i` (plus mutual terms), so the DC branch equation gains
+    /// `-scale*L*i` on the left and the history term of the flux on the right.
+    fn add_inductor_transient(&mut self, component: &Component, prev_flux: f64, integrator: &Integrator) -> Result<()> {
+        let branch = self.branch_index(&component.name)?;
+        let slots = self.slots_of(component)?;
+        self.stamp_inductance(&slots, branch, branch, component.value, integrator.conductance());
+        self.rhs[branch] += self.charge_current(&component.name, 0.0, prev_flux, integrator);
 
         Ok(())
     }
 
-    /// Add inductor contribution for transient analysis
-    fn add_inductor_transient(&mut self, _circuit: &Circuit, _component: &Component, _dt: f64, _prev_currents: &DVector<f64>) -> Result<()> {
-        // Inductor transient analysis requires tracking current through the inductor
-        // This is more complex and would require expanding the system
-        // For now, we'll skip this implementation
+    /// Add the mutual inductance `mutual` that the K element `coupling` sets up between two
+    /// inductors for transient analysis. The first node of each inductor is its dotted end,
+    /// so the flux of each inductor includes `mutual` times the current of the other.
+    fn add_mutual_inductance_transient(&mut self, coupling: &Component, first: &Component, second: &Component, mutual: f64,
+                                       integrator: &Integrator) -> Result<()> {
+        let branch1 = self.branch_index(&first.name)?;
+        let branch2 = self.branch_index(&second.name)?;
+        let slots = self.slots_of(coupling)?;
+
+        self.stamp_inductance(&slots, branch1, branch2, mutual, integrator.conductance());
+        self.stamp_inductance(&slots, branch2, branch1, mutual, integrator.conductance());
+
         Ok(())
     }
 
-    /// Convert to sparse matrix format for efficient solving
-    pub fn to_sparse(&self) -> (CsMat<f64>, Vec<f64>) {
-        let mut triplets = Vec::new();
-        
-        // Convert dense matrix to triplet format
-        for i in 0..self.size {
-            for j in 0..self.size {
-                let value = self.matrix[(i, j)];
-                if value.abs() > 1e-15 { // Skip near-zero values
-                    triplets.push((i, j, value));
-                }
-            }
+    /// Flux linkage of every inductor for the branch currents in `solution`, keyed by name
+    fn inductor_fluxes(&self, circuit: &Circuit, solution: &DVector<f64>) -> Result<HashMap<String, f64>> {
+        let current = |name: &str| -> Result<f64> {
+            Ok(solution.get(self.branch_index(name)?).copied().unwrap_or(0.0))
+        };
+        let mut fluxes = HashMap::new();
+        for component in circuit.components.iter().filter(|c| c.component_type == ComponentType::Inductor) {
+            fluxes.insert(component.name.clone(), component.value * current(&component.name)?);
         }
-
-        let mut triplet_mat = TriMat::new((self.size, self.size));
-        
-        for (row, col, value) in triplets {
-            triplet_mat.add_triplet(row, col, value);
+        for (_, first, second, mutual) in circuit.mutual_inductances()? {
+            *fluxes.get_mut(&first.name).unwrap() += mutual * current(&second.name)?;
+            *fluxes.get_mut(&second.name).unwrap() += mutual * current(&first.name)?;
         }
-        
-        let sparse_matrix = triplet_mat.to_csr();
-        let rhs_vec = self.rhs.as_slice().to_vec();
+        Ok(fluxes)
+    }
+
+    /// Matrix in compressed sparse column form and right-hand side for the solvers. The
+    /// pattern is the same at every assembly, so the solver can reuse its factorization.
+    pub fn as_sparse(&self) -> (&CsMat<f64>, &[f64]) {
+        (self.matrix.as_csmat(), self.rhs.as_slice())
+    }
 
-        (sparse_matrix, rhs_vec)
+    /// Dense copy of the system matrix for debugging, `None` above `DENSE_VIEW_LIMIT` unknowns
+    pub fn dense_matrix(&self) -> Option<DMatrix<f64>> {
+        (self.size <= DENSE_VIEW_LIMIT).then(|| self.matrix.to_dense())
     }
 
     /// Update the solution vector
@@ -400,6 +1154,7 @@ impl MnaSystem {
         println!("  Size: {} x {}", self.size, self.size);
         println!("  Nodes: {}", self.num_nodes);
         println!("  Voltage sources: {}", self.num_voltage_sources);
+        println!("  Matrix entries: {}", self.matrix.nnz());
         println!("  Matrix condition: {:.2e}", self.matrix_condition_number());
     }
 
@@ -407,8 +1162,8 @@ impl MnaSystem {
     fn matrix_condition_number(&self) -> f64 {
         // This is a very rough approximation
         // For a proper condition number, we'd need SVD
-        let max_element = self.matrix.iter().fold(0.0f64, |acc, &x| acc.max(x.abs()));
-        let min_element = self.matrix.iter()
+        let max_element = self.matrix.values().iter().fold(0.0f64, |acc, &x| acc.max(x.abs()));
+        let min_element = self.matrix.values().iter()
             .filter(|&&x| x.abs() > 1e-15)
             .fold(f64::INFINITY, |acc, &x| acc.min(x.abs()));
         
@@ -420,10 +1175,162 @@ impl MnaSystem {
     }
 }
 
+/// Add `value` times the voltage from `inputs[0]` to `inputs[1]` to the current flowing
+/// from `outputs[0]` to `outputs[1]` through the precomputed `slots`; ground (`None`)
+/// rows and columns are skipped
+fn stamp_coupling(matrix: &mut SparseMatrix, slots: &StampSlots, outputs: [Option<usize>; 2], inputs: [Option<usize>; 2], value: f64) {
+    let values = matrix.values_mut();
+    for (out, out_sign) in outputs.into_iter().zip([1.0, -1.0]) {
+        for (input, in_sign) in inputs.into_iter().zip([1.0, -1.0]) {
+            if let (Some(row), Some(col)) = (out, input) {
+                values[slots.slot(row, col)] += out_sign * in_sign * value;
+            }
+        }
+    }
+}
+
+/// Terminals of each component of `circuit`, in order: its nodes, internal nodes, own
+/// branch current and controlling branch currents (the coupled inductors' for K elements)
+fn component_terminals(circuit: &Circuit, node_map: &HashMap<usize, usize>, internal_nodes: &HashMap<String, usize>,
+                       branches: &HashMap<String, usize>) -> Result<Vec<Vec<usize>>> {
+    let mut internal_by_component: HashMap<&str, Vec<usize>> = HashMap::new();
+    for (key, &index) in internal_nodes {
+        let component = key.split('#').next().unwrap_or(key);
+        internal_by_component.entry(component).or_default().push(index);
+    }
+    let branch_of = |name: &str| -> Result<Option<usize>> {
+        let component = circuit.find_component(name)
+            .ok_or_else(|| anyhow!("Component {} not found", name))?;
+        Ok(branches.get(&component.name).copied())
+    };
+
+    let mut all = Vec::with_capacity(circuit.components.len());
+    for component in &circuit.components {
+        let mut terminals: Vec<usize> = component.nodes.iter()
+            .filter_map(|name| circuit.get_node_id(name).and_then(|id| node_map.get(&id).copied()))
+            .collect();
+        terminals.extend(internal_by_component.get(component.name.as_str()).into_iter().flatten());
+        terminals.extend(branches.get(&component.name));
+        match &component.component_type {
+            ComponentType::Cccs { control } | ComponentType::Ccvs { control } => terminals.extend(branch_of(control)?),
+            ComponentType::MutualInductance { inductors } => {
+                for inductor in inductors {
+                    terminals.extend(branch_of(inductor)?);
+                }
+            }
+            _ => {}
+        }
+        all.push(terminals);
+    }
+    Ok(all)
+}
+
+/// Positions of the system matrix any analysis may stamp, from the circuit topology:
+/// every node diagonal, and all pairs among the `terminals` of each component. Branch
+/// rows have no diagonal entry unless the branch is an inductor's, whose transient
+/// companion has one.
+fn stamp_pattern(circuit: &Circuit, num_nodes: usize, terminals: &[Vec<usize>], branches: &HashMap<String, usize>) -> Vec<(usize, usize)> {
+    let mut entries: Vec<(usize, usize)> = (0..num_nodes).map(|i| (i, i)).collect();
+    for (component, terminals) in circuit.components.iter().zip(terminals) {
+        if component.component_type == ComponentType::Inductor {
+            entries.extend(branches.get(&component.name).map(|&b| (b, b)));
+        }
+        for &row in terminals {
+            entries.extend(terminals.iter().filter(|&&col| col != row || row < num_nodes).map(|&col| (row, col)));
+        }
+    }
+    entries
+}
+
+/// Temperature-adjusted parameters of a diode component
+pub fn diode_instance(circuit: &Circuit, component: &Component) -> Result<DiodeInstance> {
+    let model = match circuit.model_for(component)? {
+        Some(DeviceModel::Diode(model)) => model.clone(),
+        Some(other) => return Err(anyhow!("Diode {} cannot use a {} model", component.name, other.kind())),
+        None => DiodeModel::default(),
+    };
+    Ok(model.instance(component.value, circuit.temperature))
+}
+
+/// Temperature- and geometry-adjusted parameters of a MOSFET component
+pub fn mosfet_instance(circuit: &Circuit, component: &Component) -> Result<MosfetInstance> {
+    let model = match circuit.model_for(component)? {
+        Some(DeviceModel::Mosfet(model)) => model.clone(),
+        Some(other) => return Err(anyhow!("MOSFET {} cannot use a {} model", component.name, other.kind())),
+        None => MosfetModel::default(),
+    };
+    let (width, length) = match component.component_type {
+        ComponentType::Mosfet { width, length, .. } => (width, length),
+        _ => return Err(anyhow!("Component {} is not a MOSFET", component.name)),
+    };
+    model.instance(width.unwrap_or(DEFAULT_CHANNEL_SIZE), length.unwrap_or(DEFAULT_CHANNEL_SIZE), circuit.temperature)
+        .map_err(|e| anyhow!("{}: {}", component.name, e))
+}
+
+/// Temperature- and area-adjusted parameters of a BJT component
+pub fn bjt_instance(circuit: &Circuit, component: &Component) -> Result<BjtInstance> {
+    let model = match circuit.model_for(component)? {
+        Some(DeviceModel::Bjt(model)) => model.clone(),
+        Some(other) => return Err(anyhow!("BJT {} cannot use a {} model", component.name, other.kind())),
+        None => BjtModel::default(),
+    };
+    let area = match component.component_type {
+        ComponentType::Bjt { area, .. } => area.unwrap_or(1.0),
+        _ => return Err(anyhow!("Component {} is not a BJT", component.name)),
+    };
+    Ok(model.instance(area, circuit.temperature))
+}
+
+/// Area- and temperature-adjusted parameters of a JFET component
+pub fn jfet_instance(circuit: &Circuit, component: &Component) -> Result<JfetInstance> {
+    let model = match circuit.model_for(component)? {
+        Some(DeviceModel::Jfet(model)) => model.clone(),
+        Some(other) => return Err(anyhow!("JFET {} cannot use a {} model", component.name, other.kind())),
+        None => JfetModel::default(),
+    };
+    let area = match component.component_type {
+        ComponentType::Jfet { area, .. } => area.unwrap_or(1.0),
+        _ => return Err(anyhow!("Component {} is not a JFET", component.name)),
+    };
+    Ok(model.instance(area, circuit.temperature))
+}
+
+/// Names of the internal nodes the devices of `circuit` need, in a stable order
+fn internal_node_names(circuit: &Circuit) -> Result<Vec<String>> {
+    let mut names = Vec::new();
+    for component in circuit.nonlinear_components() {
+        match component.component_type {
+            ComponentType::Diode if diode_instance(circuit, component)?.rs > 0.0 => {
+                names.push(format!("{}#anode", component.name));
+            }
+            ComponentType::Bjt { .. } => {
+                let bjt = bjt_instance(circuit, component)?;
+                let resistances = [("collector", bjt.rc), ("base", bjt.rb), ("emitter", bjt.re)];
+                for (terminal, resistance) in resistances {
+                    if resistance > 0.0 {
+                        names.push(format!("{}#{}", component.name, terminal));
+                    }
+                }
+            }
+            ComponentType::Jfet { .. } => {
+                let jfet = jfet_instance(circuit, component)?;
+                for (terminal, resistance) in [("drain", jfet.rd), ("source", jfet.rs)] {
+                    if resistance > 0.0 {
+                        names.push(format!("{}#{}", component.name, terminal));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    Ok(names)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
     use crate::circuit::{Circuit, Component};
+    use crate::waveform::Waveform;
 
     #[test]
     fn test_mna_simple_resistor_circuit() {
@@ -466,4 +1373,92 @@ mod tests {
         assert_eq!(mna.size, 1); // Only 1 node (no voltage sources)
         assert_eq!(mna.rhs[0], 0.001); // Current source contributes to RHS
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_transient_sources_follow_waveform() {
+        let mut circuit = Circuit::new("Test".to_string());
+        circuit.add_node("1".to_string());
+        circuit.add_node("0".to_string());
+
+        let mut v1 = Component::new_voltage_source("V1".to_string(), "1".to_string(), "0".to_string(), 0.0);
+        v1.waveform = Some(Waveform::pwl(vec![(0.0, 0.0), (1e-3, 2.0)], None, 0.0).unwrap());
+        let r1 = Component::new_resistor("R1".to_string(), "1".to_string(), "0".to_string(), 1000.0);
+        circuit.add_component(v1).unwrap();
+        circuit.add_component(r1).unwrap();
+
+        let mut mna = MnaSystem::new(&circuit).unwrap();
+        let prev = DVector::zeros(mna.num_nodes);
+        let step = ChargeStep { integrator: Integrator::backward_euler(1e-4), prev_solution: &prev };
+        mna.assemble_transient(&circuit, 0.5e-3, step).unwrap();
+        assert_eq!(mna.rhs[1], 1.0);
+
+        mna.assemble_dc(&circuit).unwrap();
+        assert_eq!(mna.rhs[1], 0.0);
+    }
+
+    #[test]
+    fn test_pattern_fixed_by_topology() {
+        let mut circuit = Circuit::new("Test".to_string());
+        for node in ["0", "1", "2", "3"] {
+            circuit.add_node(node.to_string());
+        }
+        circuit.add_component(Component::new_voltage_source("V1".to_string(), "1".to_string(), "0".to_string(), 1.0)).unwrap();
+        circuit.add_component(Component::new_resistor("R1".to_string(), "1".to_string(), "2".to_string(), 1000.0)).unwrap();
+        circuit.add_component(Component::new_capacitor("C1".to_string(), "2".to_string(), "0".to_string(), 1e-9)).unwrap();
+        circuit.add_component(Component::new_inductor("L1".to_string(), "2".to_string(), "3".to_string(), 1e-3)).unwrap();
+        circuit.add_component(Component::new_resistor("R2".to_string(), "3".to_string(), "0".to_string(), 50.0)).unwrap();
+
+        // 3 个节点对角元 + R1 的 2 个 + 电压源 2 对 + 电感 2 对及其支路对角元
+        let mut mna = MnaSystem::new(&circuit).unwrap();
+        assert_eq!(mna.matrix.nnz(), 3 + 2 + 4 + 4 + 1);
+
+        // 直流与瞬态组装只写入已有的值槽
+        mna.assemble_dc(&circuit).unwrap();
+        assert_eq!(mna.matrix.nnz(), 14);
+        let prev = DVector::zeros(mna.size);
+        let step = ChargeStep { integrator: Integrator::backward_euler(1e-6), prev_solution: &prev };
+        mna.assemble_transient(&circuit, 1e-6, step).unwrap();
+        assert_eq!(mna.matrix.nnz(), 14);
+
+        let dense = mna.dense_matrix().unwrap();
+        let node2 = mna.node_map[&circuit.get_node_id("2").unwrap()];
+        let branch = mna.voltage_source_map["L1"];
+        assert!((dense[(node2, node2)] - (1e-3 + 1e-3)).abs() < 1e-12);
+        assert!((dense[(branch, branch)] + 1e3).abs() < 1e-9);
+        assert_eq!(mna.as_sparse().0.nnz(), 14);
+    }
+
+    #[test]
+    fn test_ac_system_stays_sparse() {
+        // 超过稠密视图上限的 RC 梯形网络，交流求解不经过稠密矩阵
+        let sections = DENSE_VIEW_LIMIT + 500;
+        let mut circuit = Circuit::new("Ladder".to_string());
+        for node in 0..=sections + 1 {
+            circuit.add_node(node.to_string());
+        }
+        let mut source = Component::new_voltage_source("V1".to_string(), "1".to_string(), "0".to_string(), 0.0);
+        source.ac = Some((1.0, 0.0));
+        circuit.add_component(source).unwrap();
+        for i in 1..=sections {
+            let (node, next) = (i.to_string(), (i + 1).to_string());
+            circuit.add_component(Component::new_resistor(format!("R{}", i), node, next.clone(), 1000.0)).unwrap();
+            circuit.add_component(Component::new_capacitor(format!("C{}", i), next, "0".to_string(), 1e-9)).unwrap();
+        }
+
+        let mut mna = MnaSystem::new(&circuit).unwrap();
+        assert!(mna.dense_matrix().is_none());
+        let mut ac = mna.assemble_ac(&circuit, &DVector::zeros(mna.size)).unwrap();
+        for frequency in [1e3, 1e5] {
+            let solution = ac.solve(frequency).unwrap();
+            let matrix = ac.matrix(2.0 * std::f64::consts::PI * frequency);
+            let mut residual = ac.excitation.clone();
+            for (value, (row, col)) in matrix.iter() {
+                residual[row] -= value * solution[col];
+            }
+            assert!(residual.norm() < 1e-12, "{} Hz: residual {}", frequency, residual.norm());
+            assert!((solution[mna.node_map[&circuit.get_node_id("1").unwrap()]] - Complex64::new(1.0, 0.0)).norm() < 1e-12);
+        }
+        // 第二个频率沿用第一个的主元顺序
+        assert_eq!(ac.factorization.counts().analyses, 1);
+    }
+}
@@ -4,7 +4,7 @@ use std::io::Write;
 use anyhow::Result;
 use serde::{Deserialize, Serialize};
 
-use crate::simulator::SimulationResult;
+use crate::simulator::{AnalysisType, SimulationResult};
 use crate::cli::OutputFormat;
 
 /// Output processor for simulation results
@@ -89,8 +89,9 @@ impl OutputProcessor {
             self.write_csv_metadata(&mut writer, results)?;
         }
 
-        // Create header
-        let mut header = vec!["time".to_string()];
+        // Create header, with the outer sweep variable first for nested DC sweeps
+        let (axis, outer_axis) = results.axis_names();
+        let mut header: Vec<String> = outer_axis.into_iter().chain(std::iter::once(axis)).collect();
         
         // Sort node names for consistent output
         let mut node_names: Vec<&String> = results.node_voltages.keys().collect();
@@ -106,11 +107,19 @@ impl OutputProcessor {
             header.push(format!("I({})", current_name));
         }
 
+        // AC results as magnitude and phase
+        let ac_columns = results.ac_columns();
+        header.extend(ac_columns.iter().map(|(name, _)| name.clone()));
+
         writer.write_record(&header)?;
 
-        // Write data points
+        // Write data points, one block of rows per outer sweep value
         for (i, &time) in results.time_points.iter().enumerate() {
-            let mut record = vec![self.format_number(time)];
+            let mut record: Vec<String> = results.outer_sweep_values.get(i)
+                .map(|&value| self.format_number(value))
+                .into_iter()
+                .collect();
+            record.push(self.format_number(time));
             
             // Add voltage data
             for node_name in &node_names {
@@ -123,6 +132,10 @@ impl OutputProcessor {
                 let current = results.currents[*current_name].get(i).unwrap_or(&0.0);
                 record.push(self.format_number(*current));
             }
+
+            for (_, values) in &ac_columns {
+                record.push(self.format_number(*values.get(i).unwrap_or(&0.0)));
+            }
             
             writer.write_record(&record)?;
         }
@@ -229,6 +242,11 @@ impl OutputProcessor {
         }
 
         // Generate basic plotting code
+        let x_label = match &results.analysis_type {
+            AnalysisType::DcSweep { sweep, .. } => sweep.name.clone(),
+            AnalysisType::Ac { .. } => "Frequency (Hz)".to_string(),
+            _ => "Time (s)".to_string(),
+        };
         writeln!(file)?;
         writeln!(file, "# Basic plotting example")?;
         writeln!(file, "plt.figure(figsize=(12, 8))")?;
@@ -236,11 +254,11 @@ impl OutputProcessor {
         let mut subplot_idx = 1;
         if !results.node_voltages.is_empty() {
             writeln!(file, "plt.subplot(2, 1, {})", subplot_idx)?;
-            for (_i, node_name) in results.node_voltages.keys().enumerate() {
+            for node_name in results.node_voltages.keys() {
                 let var_name = node_name.replace('-', "_");
-                writeln!(file, "plt.plot(time, V_{}, label='V({})')", var_name, node_name)?;
+                self.write_python_plot(&mut file, results, &format!("V_{}", var_name), &format!("V({})", node_name))?;
             }
-            writeln!(file, "plt.xlabel('Time (s)')")?;
+            writeln!(file, "plt.xlabel('{}')", x_label)?;
             writeln!(file, "plt.ylabel('Voltage (V)')")?;
             writeln!(file, "plt.legend()")?;
             writeln!(file, "plt.grid(True)")?;
@@ -251,9 +269,9 @@ impl OutputProcessor {
             writeln!(file, "plt.subplot(2, 1, {})", subplot_idx)?;
             for current_name in results.currents.keys() {
                 let var_name = current_name.replace('-', "_");
-                writeln!(file, "plt.plot(time, I_{}, label='I({})')", var_name, current_name)?;
+                self.write_python_plot(&mut file, results, &format!("I_{}", var_name), &format!("I({})", current_name))?;
             }
-            writeln!(file, "plt.xlabel('Time (s)')")?;
+            writeln!(file, "plt.xlabel('{}')", x_label)?;
             writeln!(file, "plt.ylabel('Current (A)')")?;
             writeln!(file, "plt.legend()")?;
             writeln!(file, "plt.grid(True)")?;
@@ -265,6 +283,18 @@ impl OutputProcessor {
         Ok(())
     }
 
+    /// Write a plot command for one signal, with one line per outer sweep value
+    fn write_python_plot(&self, file: &mut File, results: &SimulationResult, var_name: &str, label: &str) -> Result<()> {
+        for (outer_value, range) in results.curves() {
+            match outer_value {
+                Some(value) => writeln!(file, "plt.plot(time[{}:{}], {}[{}:{}], label='{} @ {}')",
+                    range.start, range.end, var_name, range.start, range.end, label, self.format_number(value))?,
+                None => writeln!(file, "plt.plot(time, {}, label='{}')", var_name, label)?,
+            }
+        }
+        Ok(())
+    }
+
     /// Calculate statistical information about the simulation results
     pub fn calculate_statistics(&self, results: &SimulationResult) -> Result<ResultStatistics> {
         let mut node_voltage_stats = HashMap::new();
@@ -368,12 +398,12 @@ impl OutputProcessor {
     fn write_csv_metadata(&self, writer: &mut csv::Writer<File>, results: &SimulationResult) -> Result<()> {
         // CSV doesn't support comments directly, but we can add them as the first rows
         // and then skip them during reading if needed
-        writer.write_record(&[format!("# SPICE Simulation Results")])?;
-        writer.write_record(&[format!("# Analysis: {:?}", results.analysis_type)])?;
-        writer.write_record(&[format!("# Total time: {:.6}s", results.total_time)])?;
-        writer.write_record(&[format!("# Success: {}", results.success)])?;
-        writer.write_record(&[format!("# Data points: {}", results.time_points.len())])?;
-        writer.write_record(&["#"])?; // Empty comment line
+        writer.write_record(["# SPICE Simulation Results"])?;
+        writer.write_record([format!("# Analysis: {:?}", results.analysis_type)])?;
+        writer.write_record([format!("# Total time: {:.6}s", results.total_time)])?;
+        writer.write_record([format!("# Success: {}", results.success)])?;
+        writer.write_record([format!("# Data points: {}", results.time_points.len())])?;
+        writer.write_record(["#"])?; // Empty comment line
         Ok(())
     }
 
@@ -455,6 +485,7 @@ impl Default for OutputProcessor {
 mod tests {
     use super::*;
     use crate::simulator::{SimulationResult, AnalysisType};
+    use crate::sweep::Sweep;
     use std::collections::HashMap;
 
     fn create_test_results() -> SimulationResult {
@@ -472,6 +503,9 @@ mod tests {
             convergence_info: Vec::new(),
             total_time: 0.001,
             success: true,
+            outer_sweep_values: Vec::new(),
+            ac_node_voltages: HashMap::new(),
+            ac_currents: HashMap::new(),
         }
     }
 
@@ -503,4 +537,29 @@ mod tests {
         assert_eq!(stats.mean, 3.0);
         assert_eq!(stats.peak_to_peak, 4.0);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_nested_sweep_csv() {
+        let mut results = create_test_results();
+        results.analysis_type = AnalysisType::DcSweep {
+            sweep: Sweep::linear("V1", 0.0, 1.0, 1.0),
+            outer: Some(Sweep::linear("V2", 0.0, 1.0, 1.0)),
+        };
+        results.time_points = vec![0.0, 1.0, 0.0, 1.0];
+        results.outer_sweep_values = vec![0.0, 0.0, 1.0, 1.0];
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("sweep.csv");
+        let processor = OutputProcessor::with_config(OutputConfig {
+            precision: 1,
+            include_metadata: false,
+            ..OutputConfig::default()
+        });
+        processor.export_csv(&results, path.to_str().unwrap()).unwrap();
+
+        let content = std::fs::read_to_string(&path).unwrap();
+        let lines: Vec<&str> = content.lines().collect();
+        assert_eq!(lines[0], "V2,V1,V(1),I(V1)");
+        assert_eq!(lines[3], "1.0,0.0,2.0,0.0");
+    }
+}
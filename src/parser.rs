@@ -1,38 +1,22 @@
-use nom::{
-    branch::alt,
-    bytes::complete::{tag, tag_no_case, take_while1},
-    character::complete::{alphanumeric1, char, line_ending, multispace0, multispace1, not_line_ending, space0, space1},
-    combinator::{map, opt, recognize},
-    multi::{many0, separated_list1},
-    number::complete::double,
-    sequence::{pair, preceded, terminated},
-    IResult,
-};
 use regex::Regex;
 use lazy_static::lazy_static;
 use std::collections::HashMap;
 use std::fs;
+use std::path::{Path, PathBuf};
 use anyhow::{anyhow, Result};
 
 use crate::circuit::{Component, ComponentType, Node};
+use crate::devices::DeviceModel;
+use crate::expression;
+use crate::sweep::{Sweep, SweepKind};
+use crate::waveform::{self, Waveform};
 
 // 正则表达式模式
 lazy_static! {
-    static ref COMPONENT_PATTERN: Regex = Regex::new(
-        r"^([RVCLID])(\w+)\s+(\w+)\s+(\w+)\s+(.+)$"
-    ).unwrap();
-    
-    static ref VOLTAGE_SOURCE_PATTERN: Regex = Regex::new(
-        r"^([RVCLID])(\w+)\s+(\w+)\s+(\w+)\s+(DC|AC|PULSE)\s+(.+)$"
-    ).unwrap();
-    
-    static ref VALUE_PATTERN: Regex = Regex::new(
-        r"^([0-9]+\.?[0-9]*)\s*([a-zA-Z]*)$"
-    ).unwrap();
-    
     static ref ANALYSIS_PATTERN: Regex = Regex::new(
-        r"^\.(op|tran|dc|ac)\s+(.+)$"
+        r"(?i)^\.(op|tran|dc|ac)\b\s*(.*)$"
     ).unwrap();
+    static ref ASSIGNMENT_PATTERN: Regex = Regex::new(r"\s*=\s*").unwrap();
 }
 
 #[derive(Debug, Clone)]
@@ -43,24 +27,106 @@ pub struct SpiceNetlist {
     pub subcircuits: Vec<Subcircuit>,
     pub parameters: HashMap<String, f64>,
     pub analyses: Vec<Analysis>,
+    /// Device models from `.MODEL` cards, keyed by lowercase name
+    pub models: HashMap<String, DeviceModel>,
+    /// Circuit temperature from `.TEMP`, in °C
+    pub temperature: Option<f64>,
+    /// Settings from `.OPTIONS` cards in order, names lowercased; flags have no value
+    pub options: Vec<(String, Option<String>)>,
+    /// Node voltages from `.IC V(node)=value` cards
+    pub initial_conditions: Vec<(String, f64)>,
+    /// Initial Newton guesses from `.NODESET V(node)=value` cards
+    pub nodesets: Vec<(String, f64)>,
 }
 
+/// Subcircuit definition (.SUBCKT ... .ENDS)
 #[derive(Debug, Clone)]
 pub struct Subcircuit {
     pub name: String,
+    /// Port nodes, in the order used by X instances
     pub nodes: Vec<String>,
-    pub components: Vec<Component>,
+    /// Default parameter values from the `PARAMS:` section, unevaluated
+    pub parameters: Vec<(String, String)>,
+    /// Element and instance lines of the body, expanded per instance
+    pub lines: Vec<SourceLine>,
+    /// Subcircuits defined inside this one, only visible to its body
+    pub subcircuits: Vec<Subcircuit>,
+}
+
+impl Subcircuit {
+    fn find<'a>(subcircuits: &'a [Subcircuit], name: &str) -> Option<&'a Subcircuit> {
+        subcircuits.iter().find(|sub| sub.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// A logical netlist line and the file/line it started on
+#[derive(Debug, Clone)]
+pub struct SourceLine {
+    pub text: String,
+    pub file: Option<PathBuf>,
+    pub line: usize,
+}
+
+impl SourceLine {
+    /// `file:line` (or `line N` for in-memory netlists) for error messages
+    pub fn location(&self) -> String {
+        match &self.file {
+            Some(file) => format!("{}:{}", file.display(), self.line),
+            None => format!("line {}", self.line),
+        }
+    }
+}
+
+/// A parsed X instance line
+#[derive(Debug, Clone)]
+struct SubcircuitInstance {
+    name: String,
+    nodes: Vec<String>,
+    subcircuit: String,
+    parameters: Vec<(String, String)>,
+}
+
+/// Name prefix, port mapping and parameter scope of the subcircuit being expanded
+struct ExpansionScope<'a> {
+    prefix: String,
+    port_map: HashMap<String, String>,
+    parameters: HashMap<String, f64>,
+    subcircuits: Vec<&'a [Subcircuit]>,
+    active: Vec<String>,
+}
+
+impl ExpansionScope<'_> {
+    /// Map a node name used inside the subcircuit body to its flattened name.
+    /// Ports and local nodes of a body are matched without regard to case, as SPICE does.
+    fn map_node(&self, node: &str) -> String {
+        if let Some(actual) = self.port_map.get(&node.to_lowercase()) {
+            actual.clone()
+        } else if self.prefix.is_empty() || Node::new(node.to_string()).is_ground() {
+            node.to_string()
+        } else {
+            format!("{}.{}", self.prefix, node.to_lowercase())
+        }
+    }
+
+    /// Map an element name used inside the subcircuit body to its hierarchical name
+    fn map_name(&self, name: &str) -> String {
+        if self.prefix.is_empty() {
+            name.to_string()
+        } else {
+            format!("{}.{}", self.prefix, name)
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub enum Analysis {
     Operating,
-    Transient { tstep: f64, tstop: f64, tstart: Option<f64> },
+    /// `.TRAN tstep tstop [tstart [tmax]] [UIC]`
+    Transient { tstep: f64, tstop: f64, tstart: Option<f64>, tmax: Option<f64>, uic: bool },
+    /// DC sweep of `sweep`, optionally nested inside a sweep of `outer`
     DcSweep { 
-        source: String, 
-        start: f64, 
-        stop: f64, 
-        step: f64 
+        sweep: Sweep, 
+        outer: Option<Sweep>, 
     },
     Ac { 
         points: usize, 
@@ -70,9 +136,12 @@ pub enum Analysis {
     },
 }
 
-#[allow(dead_code)]
+/// DC value, transient waveform and AC magnitude/phase of an independent source
+type SourceSpec = (f64, Option<Waveform>, Option<(f64, f64)>);
+
 pub struct SpiceParser {
-    line_regex: Regex,
+    /// Directories searched for .INCLUDE/.LIB files not found next to the including file
+    search_paths: Vec<PathBuf>,
 }
 
 impl Default for SpiceParser {
@@ -84,48 +153,154 @@ impl Default for SpiceParser {
 impl SpiceParser {
     pub fn new() -> Self {
         SpiceParser {
-            line_regex: Regex::new(r"^\s*([+].*|[*;].*|\..*|\w+.*)$").unwrap(),
+            search_paths: Vec::new(),
         }
     }
 
+    /// Create a parser that also looks for included files in `search_paths`
+    pub fn with_search_paths(search_paths: Vec<PathBuf>) -> Self {
+        SpiceParser { search_paths }
+    }
+
+    /// Add a library search directory
+    pub fn add_search_path<P: Into<PathBuf>>(&mut self, path: P) {
+        self.search_paths.push(path.into());
+    }
+
     pub fn parse_file(&self, filename: &str) -> Result<SpiceNetlist> {
-        let content = fs::read_to_string(filename)
+        let path = Path::new(filename);
+        let content = fs::read_to_string(path)
             .map_err(|e| anyhow!("Failed to read file '{}': {}", filename, e))?;
         
-        self.parse_netlist(&content)
+        let lines = self.preprocess_source(&content, Some(path));
+        let mut stack = vec![include_key(path, None)];
+        self.parse_source(lines, &mut stack)
     }
 
     pub fn parse_netlist(&self, content: &str) -> Result<SpiceNetlist> {
-        let lines = self.preprocess_lines(content);
+        let lines = self.preprocess_source(content, None);
+        self.parse_source(lines, &mut Vec::new())
+    }
+
+    fn parse_source(&self, mut lines: Vec<SourceLine>, include_stack: &mut Vec<(PathBuf, Option<String>)>) -> Result<SpiceNetlist> {
         let mut components = Vec::new();
         let mut analyses = Vec::new();
-        let mut title = String::new();
+        let mut subcircuits = Vec::new();
+        let mut statements = Vec::new();
+        let mut parameter_definitions = Vec::new();
+        // 尚未结束的子电路定义及其 .SUBCKT 所在行
+        let mut open_subcircuits: Vec<(Subcircuit, &SourceLine)> = Vec::new();
+
+        // 解析标题（主文件第一行非控制语句），包含文件中的行不能作为标题
+        let title = match lines.iter().position(|line| !line.text.starts_with('.')) {
+            Some(index) => lines.remove(index).text,
+            None => String::new(),
+        };
+        let lines = self.expand_includes(lines, include_stack)?;
         
-        for (_line_num, line) in lines.iter().enumerate() {
-            let line = line.trim();
+        for source_line in lines.iter() {
+            let line = source_line.text.trim();
             
             // 跳过空行和注释
             if line.is_empty() || line.starts_with('*') || line.starts_with(';') {
                 continue;
             }
-            
-            // 解析标题（第一行非注释行）
-            if title.is_empty() && !line.starts_with('.') {
-                title = line.to_string();
+
+            let keyword = line.split_whitespace().next().unwrap_or("").to_lowercase();
+
+            // 子电路定义，可以嵌套
+            if keyword == ".subckt" {
+                let subcircuit = self.parse_subckt_header(line)
+                    .map_err(|e| anyhow!("{}: {}", source_line.location(), e))?;
+                open_subcircuits.push((subcircuit, source_line));
                 continue;
             }
-            
-            // 解析分析指令
-            if line.starts_with('.') {
-                if let Some(analysis) = self.parse_analysis_line(line)? {
-                    analyses.push(analysis);
+            if keyword == ".ends" {
+                let (subcircuit, _) = open_subcircuits.pop()
+                    .ok_or_else(|| anyhow!("{}: .ENDS without matching .SUBCKT", source_line.location()))?;
+                match open_subcircuits.last_mut() {
+                    Some((parent, _)) => parent.subcircuits.push(subcircuit),
+                    None => subcircuits.push(subcircuit),
                 }
                 continue;
             }
-            
-            // 解析组件
-            if let Some(component) = self.parse_component_line(line)? {
-                components.push(component);
+            if keyword == ".end" {
+                break;
+            }
+
+            // 子电路内部的元件行在实例化时才展开；.model 总是全局的
+            if let Some((subcircuit, _)) = open_subcircuits.last_mut().filter(|_| keyword != ".model") {
+                if !line.starts_with('.') || keyword == ".param" {
+                    subcircuit.lines.push(source_line.clone());
+                }
+                continue;
+            }
+
+            // 参数可以在使用之后定义，元件和分析指令在所有参数求值后再解析
+            if keyword == ".param" {
+                let definitions = self.parse_param_line(line)
+                    .map_err(|e| anyhow!("{}: {}", source_line.location(), e))?;
+                parameter_definitions.extend(definitions);
+            } else {
+                statements.push(source_line);
+            }
+        }
+
+        if let Some((subcircuit, header)) = open_subcircuits.last() {
+            return Err(anyhow!("{}: Subcircuit '{}' is missing .ENDS", header.location(), subcircuit.name));
+        }
+
+        let parameters = expression::evaluate_parameters(&parameter_definitions, &HashMap::new())?;
+        let scope = ExpansionScope {
+            prefix: String::new(),
+            port_map: HashMap::new(),
+            parameters: parameters.clone(),
+            subcircuits: vec![&subcircuits],
+            active: Vec::new(),
+        };
+
+        let mut models = HashMap::new();
+        let mut temperature = None;
+        let mut options = Vec::new();
+        let mut initial_conditions = Vec::new();
+        let mut nodesets = Vec::new();
+        for source_line in statements {
+            let line = source_line.text.as_str();
+            let keyword = line.split_whitespace().next().unwrap_or("").to_lowercase();
+            if keyword == ".model" {
+                let (name, model) = self.parse_model_line(line, &parameters)
+                    .map_err(|e| anyhow!("{}: {}", source_line.location(), e))?;
+                models.insert(name.to_lowercase(), model);
+            } else if keyword == ".temp" {
+                let value = tokenize_line(line).get(1).cloned()
+                    .ok_or_else(|| anyhow!("{}: .TEMP needs a temperature", source_line.location()))?;
+                temperature = Some(self.parse_value(&value, &parameters)
+                    .map_err(|e| anyhow!("{}: {}", source_line.location(), e))?);
+            } else if matches!(keyword.as_str(), ".options" | ".option" | ".opt") {
+                options.extend(tokenize_line(line)[1..].iter().map(|token| match split_assignment(token) {
+                    Some((name, value)) => (name, Some(value)),
+                    None => (token.to_lowercase(), None),
+                }));
+            } else if keyword == ".ic" || keyword == ".nodeset" {
+                let voltages = self.parse_node_voltages(line, &parameters)
+                    .map_err(|e| anyhow!("{}: {}", source_line.location(), e))?;
+                if keyword == ".ic" {
+                    initial_conditions.extend(voltages);
+                } else {
+                    nodesets.extend(voltages);
+                }
+            } else if line.starts_with('.') {
+                // 解析分析指令
+                let analysis = self.parse_analysis_line(line, &parameters)
+                    .map_err(|e| anyhow!("{}: {}", source_line.location(), e))?;
+                analyses.extend(analysis);
+            } else if line.starts_with(['X', 'x']) {
+                self.expand_instance(source_line, &scope, &mut components)?;
+            } else {
+                // 解析组件
+                let component = self.parse_component_line(source_line, &parameters)
+                    .map_err(|e| anyhow!("{}: {}", source_line.location(), e))?;
+                components.extend(component);
             }
         }
         
@@ -133,113 +308,599 @@ impl SpiceParser {
             title,
             components,
             nodes: Vec::new(), // 节点将在电路构建时创建
-            subcircuits: Vec::new(),
-            parameters: HashMap::new(),
+            subcircuits,
+            parameters,
             analyses,
+            models,
+            temperature,
+            options,
+            initial_conditions,
+            nodesets,
         })
     }
-    
-    fn parse_component_line(&self, line: &str) -> Result<Option<Component>> {
-        // 尝试匹配电压源模式（支持DC/AC/PULSE）
-        if let Some(captures) = VOLTAGE_SOURCE_PATTERN.captures(line) {
-            let component_type = captures.get(1).unwrap().as_str();
-            let name = captures.get(2).unwrap().as_str().to_string();
-            let node1 = captures.get(3).unwrap().as_str().to_string();
-            let node2 = captures.get(4).unwrap().as_str().to_string();
-            let source_type = captures.get(5).unwrap().as_str();
-            let value_str = captures.get(6).unwrap().as_str();
-            
-            if component_type == "V" {
-                // 对于PULSE等复杂语法，我们只取第一个数值作为初始值
-                let value = if source_type == "PULSE" {
-                    // 解析PULSE(0V 5V 0s 1ns 1ns 500ns 1us)格式
-                    if let Some(pulse_captures) = Regex::new(r"PULSE\(([^)]+)\)").unwrap().captures(value_str) {
-                        let pulse_params = pulse_captures.get(1).unwrap().as_str();
-                        let params: Vec<&str> = pulse_params.split_whitespace().collect();
-                        if params.len() >= 2 {
-                            // 使用第二个参数（高电平）作为电压值
-                            self.parse_value_with_unit(params[1])?
-                        } else {
-                            self.parse_value_with_unit(params[0])?
+
+    /// Parse the `V(node)=value` assignments of an `.IC` or `.NODESET` card
+    fn parse_node_voltages(&self, line: &str, parameters: &HashMap<String, f64>) -> Result<Vec<(String, f64)>> {
+        // 允许等号两侧有空格
+        let line = ASSIGNMENT_PATTERN.replace_all(line, "=");
+        tokenize_line(&line)[1..].iter()
+            .map(|token| {
+                let (target, value) = token.split_once('=')
+                    .ok_or_else(|| anyhow!("Expected V(node)=value, found '{}'", token))?;
+                let node = target.strip_prefix(['V', 'v'])
+                    .and_then(|rest| rest.strip_prefix('('))
+                    .and_then(|rest| rest.strip_suffix(')'))
+                    .map(str::trim)
+                    .filter(|node| !node.is_empty())
+                    .ok_or_else(|| anyhow!("Expected V(node)=value, found '{}'", token))?;
+                Ok((node.to_string(), self.parse_value(value, parameters)?))
+            })
+            .collect()
+    }
+
+    /// Parse `.PARAM name=value...`; values stay unevaluated until all definitions are known
+    fn parse_param_line(&self, line: &str) -> Result<Vec<(String, String)>> {
+        tokenize_line(line)[1..].iter()
+            .map(|token| split_assignment(token)
+                .ok_or_else(|| anyhow!("Invalid .PARAM assignment '{}'", token)))
+            .collect()
+    }
+
+    /// Parse `.SUBCKT name ports... [PARAMS:] name=value...`
+    fn parse_subckt_header(&self, line: &str) -> Result<Subcircuit> {
+        let tokens = tokenize_line(line);
+        let name = tokens.get(1)
+            .ok_or_else(|| anyhow!("Missing subcircuit name: {}", line))?
+            .clone();
+
+        let mut nodes = Vec::new();
+        let mut parameters = Vec::new();
+        for token in &tokens[2..] {
+            if token.eq_ignore_ascii_case("params:") {
+                continue;
+            }
+            match split_assignment(token) {
+                Some(assignment) => parameters.push(assignment),
+                None if parameters.is_empty() => nodes.push(token.clone()),
+                None => return Err(anyhow!("Unexpected token '{}' in .SUBCKT {}", token, name)),
+            }
+        }
+
+        Ok(Subcircuit {
+            name,
+            nodes,
+            parameters,
+            lines: Vec::new(),
+            subcircuits: Vec::new(),
+        })
+    }
+
+    /// Parse `Xname nodes... subckt [PARAMS:] name=value...`
+    fn parse_instance_line(&self, line: &str) -> Result<SubcircuitInstance> {
+        let tokens = tokenize_line(line);
+        let mut positional = Vec::new();
+        let mut parameters = Vec::new();
+        for token in &tokens[1..] {
+            if token.eq_ignore_ascii_case("params:") {
+                continue;
+            }
+            match split_assignment(token) {
+                Some(assignment) => parameters.push(assignment),
+                None if parameters.is_empty() => positional.push(token.clone()),
+                None => return Err(anyhow!("Unexpected token '{}' in instance {}", token, tokens[0])),
+            }
+        }
+
+        let subcircuit = positional.pop()
+            .ok_or_else(|| anyhow!("Instance {} does not name a subcircuit", tokens[0]))?;
+
+        Ok(SubcircuitInstance {
+            name: tokens[0].clone(),
+            nodes: positional,
+            subcircuit,
+            parameters,
+        })
+    }
+
+    /// Flatten one X instance into `components`, recursing into nested instances
+    fn expand_instance(&self, source_line: &SourceLine, scope: &ExpansionScope, components: &mut Vec<Component>) -> Result<()> {
+        let (definition, inner) = self.enter_instance(&source_line.text, scope)
+            .map_err(|e| anyhow!("{}: {}", source_line.location(), e))?;
+
+        for body_line in &definition.lines {
+            if body_line.text.starts_with('.') {
+                continue;
+            } else if body_line.text.starts_with(['X', 'x']) {
+                self.expand_instance(body_line, &inner, components)?;
+            } else {
+                let component = self.parse_component_line(body_line, &inner.parameters)
+                    .map_err(|e| anyhow!("{} (in {}): {}", body_line.location(), inner.prefix, e))?;
+                if let Some(mut component) = component {
+                    component.name = inner.map_name(&component.name);
+                    component.nodes = component.nodes.iter().map(|node| inner.map_node(node)).collect();
+                    match &mut component.component_type {
+                        ComponentType::Cccs { control } | ComponentType::Ccvs { control } => {
+                            *control = inner.map_name(control);
                         }
-                    } else {
-                        // 如果不是PULSE格式，尝试直接解析
-                        self.parse_value_with_unit(value_str)?
+                        ComponentType::MutualInductance { inductors } => {
+                            for inductor in inductors.iter_mut() {
+                                *inductor = inner.map_name(inductor);
+                            }
+                        }
+                        _ => {}
                     }
-                } else {
-                    self.parse_value_with_unit(value_str)?
-                };
-                
-                return Ok(Some(Component {
-                    name,
-                    component_type: ComponentType::VoltageSource,
-                    nodes: vec![node1, node2],
-                    value,
-                    model: None,
-                }));
+                    components.push(component);
+                }
             }
         }
-        
-        // 尝试匹配普通组件模式
-        if let Some(captures) = COMPONENT_PATTERN.captures(line) {
-            let component_type = captures.get(1).unwrap().as_str();
-            let name = captures.get(2).unwrap().as_str().to_string();
-            let node1 = captures.get(3).unwrap().as_str().to_string();
-            let node2 = captures.get(4).unwrap().as_str().to_string();
-            let value_str = captures.get(5).unwrap().as_str();
-            
-            let value = self.parse_value_with_unit(value_str)?;
-            
-            let comp_type = match component_type {
-                "R" => ComponentType::Resistor,
-                "C" => ComponentType::Capacitor,
-                "L" => ComponentType::Inductor,
-                "V" => ComponentType::VoltageSource,
-                "I" => ComponentType::CurrentSource,
-                "D" => ComponentType::Diode,
-                _ => return Err(anyhow!("Unknown component type: {}", component_type)),
+
+        Ok(())
+    }
+
+    /// Resolve an X line against the visible definitions and build the scope of its body
+    fn enter_instance<'a>(&self, line: &str, scope: &ExpansionScope<'a>) -> Result<(&'a Subcircuit, ExpansionScope<'a>)> {
+        let instance = self.parse_instance_line(line)?;
+        let definition = scope.subcircuits.iter()
+            .rev()
+            .find_map(|subcircuits| Subcircuit::find(subcircuits, &instance.subcircuit))
+            .ok_or_else(|| anyhow!("Unknown subcircuit '{}' in instance {}", instance.subcircuit, instance.name))?;
+
+        if definition.nodes.len() != instance.nodes.len() {
+            return Err(anyhow!(
+                "Instance {} connects {} nodes, but subcircuit {} has {} ports",
+                instance.name,
+                instance.nodes.len(),
+                definition.name,
+                definition.nodes.len()
+            ));
+        }
+        if scope.active.iter().any(|name| name.eq_ignore_ascii_case(&definition.name)) {
+            return Err(anyhow!("Recursive instantiation of subcircuit '{}'", definition.name));
+        }
+
+        // 实例参数在外层作用域求值，未覆盖的默认值可以引用其他子电路参数
+        let mut parameters = scope.parameters.clone();
+        for (name, value) in &instance.parameters {
+            if !definition.parameters.iter().any(|(param, _)| param == name) {
+                return Err(anyhow!("Subcircuit {} has no parameter '{}'", definition.name, name));
+            }
+            let value = expression::evaluate(value, &scope.parameters)
+                .map_err(|e| anyhow!("Instance {} parameter '{}': {}", instance.name, name, e))?;
+            parameters.insert(name.clone(), value);
+        }
+        let defaults: Vec<(String, String)> = definition.parameters.iter()
+            .filter(|(name, _)| !instance.parameters.iter().any(|(param, _)| param == name))
+            .cloned()
+            .collect();
+        let mut parameters = expression::evaluate_parameters(&defaults, &parameters)
+            .map_err(|e| anyhow!("Subcircuit {}: {}", definition.name, e))?;
+
+        // 子电路内部的 .PARAM 定义只在本实例内可见
+        let mut local_definitions = Vec::new();
+        for body_line in definition.lines.iter().filter(|line| line.text.starts_with('.')) {
+            let definitions = self.parse_param_line(&body_line.text)
+                .map_err(|e| anyhow!("{}: {}", body_line.location(), e))?;
+            local_definitions.extend(definitions);
+        }
+        if !local_definitions.is_empty() {
+            parameters = expression::evaluate_parameters(&local_definitions, &parameters)
+                .map_err(|e| anyhow!("Subcircuit {}: {}", definition.name, e))?;
+        }
+
+        let port_map = definition.nodes.iter()
+            .map(|port| port.to_lowercase())
+            .zip(instance.nodes.iter().map(|node| scope.map_node(node)))
+            .collect();
+
+        let mut subcircuits = scope.subcircuits.clone();
+        subcircuits.push(&definition.subcircuits);
+        let mut active = scope.active.clone();
+        active.push(definition.name.clone());
+
+        let inner = ExpansionScope {
+            prefix: scope.map_name(&instance.name),
+            port_map,
+            parameters,
+            subcircuits,
+            active,
+        };
+
+        Ok((definition, inner))
+    }
+    
+    fn parse_component_line(&self, source_line: &SourceLine, parameters: &HashMap<String, f64>) -> Result<Option<Component>> {
+        let tokens = tokenize_line(&source_line.text);
+        let name = match tokens.first() {
+            Some(name) => name.clone(),
+            None => return Ok(None),
+        };
+        if name.starts_with(['M', 'm']) {
+            return self.parse_mosfet(name, &tokens[1..], parameters).map(Some);
+        }
+        if name.starts_with(['Q', 'q']) {
+            return self.parse_bjt(name, &tokens[1..], parameters).map(Some);
+        }
+        if name.starts_with(['J', 'j']) {
+            return self.parse_jfet(name, &tokens[1..], parameters).map(Some);
+        }
+        if name.starts_with(['E', 'e', 'F', 'f', 'G', 'g', 'H', 'h']) {
+            return self.parse_controlled_source(name, &tokens[1..], parameters).map(Some);
+        }
+        if name.starts_with(['K', 'k']) {
+            return self.parse_mutual_inductance(name, &tokens[1..], parameters).map(Some);
+        }
+        if tokens.len() < 4 {
+            return Ok(None);
+        }
+
+        let nodes = vec![tokens[1].clone(), tokens[2].clone()];
+        let component_type = match name.chars().next().unwrap().to_ascii_uppercase() {
+            'R' => ComponentType::Resistor,
+            'C' => ComponentType::Capacitor,
+            'L' => ComponentType::Inductor,
+            'V' => ComponentType::VoltageSource,
+            'I' => ComponentType::CurrentSource,
+            'D' => ComponentType::Diode,
+            _ => return Ok(None),
+        };
+
+        if component_type == ComponentType::Diode {
+            return self.parse_diode(name, nodes, &tokens[3..], parameters).map(Some);
+        }
+
+        let (value, waveform, ac) = if component_type.is_source() {
+            self.parse_source_spec(&tokens[3..], parameters, source_line)?
+        } else {
+            (self.parse_value(&tokens[3], parameters)?, None, None)
+        };
+
+        // 电容的初始电压、电感的初始电流，仅在 UIC 瞬态分析中使用
+        let mut initial_condition = None;
+        if matches!(component_type, ComponentType::Capacitor | ComponentType::Inductor) {
+            for token in &tokens[4..] {
+                match split_assignment(token) {
+                    Some((key, value)) if key == "ic" => initial_condition = Some(self.parse_value(&value, parameters)?),
+                    _ => return Err(anyhow!("Unexpected '{}' on {}", token, name)),
+                }
+            }
+        }
+
+        Ok(Some(Component {
+            name,
+            component_type,
+            nodes,
+            value,
+            model: None,
+            waveform,
+            ac,
+            initial_condition,
+        }))
+    }
+
+    /// Parse the tail of `Dname anode cathode model [area | AREA=area]`
+    fn parse_diode(&self, name: String, nodes: Vec<String>, tail: &[String], parameters: &HashMap<String, f64>) -> Result<Component> {
+        let mut diode = Component::new_diode(name, nodes[0].clone(), nodes[1].clone(), Some(tail[0].clone()));
+        for (index, token) in tail.iter().enumerate().skip(1) {
+            diode.value = match split_assignment(token) {
+                Some((key, value)) if key == "area" => self.parse_value(&value, parameters)?,
+                None if index == 1 => self.parse_value(token, parameters)?,
+                _ => return Err(anyhow!("Unexpected '{}' on diode {}", token, diode.name)),
             };
-            
-            return Ok(Some(Component {
-                name,
-                component_type: comp_type,
-                nodes: vec![node1, node2],
-                value,
-                model: None,
-            }));
         }
-        
-        Ok(None)
+        Ok(diode)
+    }
+
+    /// Parse the tail of `Mname drain gate source bulk model [W=width] [L=length]`
+    fn parse_mosfet(&self, name: String, tail: &[String], parameters: &HashMap<String, f64>) -> Result<Component> {
+        if tail.len() < 5 {
+            return Err(anyhow!("MOSFET {} needs drain, gate, source and bulk nodes and a model", name));
+        }
+        let (mut width, mut length) = (None, None);
+        for token in &tail[5..] {
+            match split_assignment(token) {
+                Some((key, value)) if key == "w" => width = Some(self.parse_value(&value, parameters)?),
+                Some((key, value)) if key == "l" => length = Some(self.parse_value(&value, parameters)?),
+                _ => return Err(anyhow!("Unexpected '{}' on MOSFET {}", token, name)),
+            }
+        }
+        Ok(Component::new_mosfet(name, tail[..4].to_vec(), tail[4].clone(), width, length))
+    }
+
+    /// Parse the tail of `Qname collector base emitter [substrate] model [area | AREA=area]`.
+    /// With two positional tokens after the emitter, the second is taken as the area
+    /// if it is a number and as the model otherwise.
+    fn parse_bjt(&self, name: String, tail: &[String], parameters: &HashMap<String, f64>) -> Result<Component> {
+        let missing = || anyhow!("BJT {} needs collector, base and emitter nodes and a model", name);
+        if tail.len() < 4 {
+            return Err(missing());
+        }
+        let mut positional = Vec::new();
+        let mut area = None;
+        for token in tail.iter().skip(3) {
+            match split_assignment(token) {
+                Some((key, value)) if key == "area" => area = Some(self.parse_value(&value, parameters)?),
+                Some(_) => return Err(anyhow!("Unexpected '{}' on BJT {}", token, name)),
+                None => positional.push(token.clone()),
+            }
+        }
+
+        let mut nodes = tail[..3].to_vec();
+        let model = match positional.as_slice() {
+            [model] => model.clone(),
+            [model, value] if self.parse_value(value, parameters).is_ok() => {
+                area = Some(self.parse_value(value, parameters)?);
+                model.clone()
+            }
+            [substrate, model] => {
+                nodes.push(substrate.clone());
+                model.clone()
+            }
+            [substrate, model, value] => {
+                nodes.push(substrate.clone());
+                area = Some(self.parse_value(value, parameters)?);
+                model.clone()
+            }
+            _ => return Err(missing()),
+        };
+        Ok(Component::new_bjt(name, nodes, model, area))
+    }
+
+    /// Parse the tail of `Jname drain gate source model [area | AREA=area]`
+    fn parse_jfet(&self, name: String, tail: &[String], parameters: &HashMap<String, f64>) -> Result<Component> {
+        if tail.len() < 4 {
+            return Err(anyhow!("JFET {} needs drain, gate and source nodes and a model", name));
+        }
+        let mut area = None;
+        for (index, token) in tail.iter().enumerate().skip(4) {
+            area = match split_assignment(token) {
+                Some((key, value)) if key == "area" => Some(self.parse_value(&value, parameters)?),
+                None if index == 4 => Some(self.parse_value(token, parameters)?),
+                _ => return Err(anyhow!("Unexpected '{}' on JFET {}", token, name)),
+            };
+        }
+        Ok(Component::new_jfet(name, tail[..3].to_vec(), tail[3].clone(), area))
+    }
+
+    /// Parse the tail of a linear controlled source:
+    /// `Ename|Gname n+ n- nc+ nc- gain` or `Fname|Hname n+ n- Vcontrol gain`
+    fn parse_controlled_source(&self, name: String, tail: &[String], parameters: &HashMap<String, f64>) -> Result<Component> {
+        let letter = name.chars().next().unwrap().to_ascii_uppercase();
+        let (component_type, node_count) = match letter {
+            'E' => (ComponentType::Vcvs, 4),
+            'G' => (ComponentType::Vccs, 4),
+            'F' => (ComponentType::Cccs { control: tail.get(2).cloned().unwrap_or_default() }, 2),
+            _ => (ComponentType::Ccvs { control: tail.get(2).cloned().unwrap_or_default() }, 2),
+        };
+        // F、H 的第三个字段是控制电压源的名字
+        let gain_index = if node_count == 4 { 4 } else { 3 };
+        if tail.len() != gain_index + 1 {
+            return Err(anyhow!("{} expects {} followed by a gain", name,
+                if node_count == 4 { "output and controlling nodes" } else { "output nodes and a controlling voltage source" }));
+        }
+        let gain = self.parse_value(&tail[gain_index], parameters)?;
+        Ok(Component::new_controlled_source(name, component_type, tail[..node_count].to_vec(), gain))
+    }
+
+    /// Parse the tail of `Kname L1 L2 [L3 ...] coupling`; every pair of the listed inductors
+    /// is coupled with the same coefficient
+    fn parse_mutual_inductance(&self, name: String, tail: &[String], parameters: &HashMap<String, f64>) -> Result<Component> {
+        let Some((coupling, inductors)) = tail.split_last().filter(|(_, inductors)| inductors.len() >= 2) else {
+            return Err(anyhow!("{} expects at least two inductors followed by a coupling coefficient", name));
+        };
+        let coupling = self.parse_value(coupling, parameters)?;
+        Ok(Component::new_mutual_inductance(name, inductors.to_vec(), coupling))
+    }
+
+    /// Parse `.MODEL name type(param=value ...)`; the parentheses are optional
+    fn parse_model_line(&self, line: &str, parameters: &HashMap<String, f64>) -> Result<(String, DeviceModel)> {
+        let tokens = tokenize_line(line);
+        let name = tokens.get(1)
+            .ok_or_else(|| anyhow!("Missing model name: {}", line))?;
+        let type_token = tokens.get(2)
+            .ok_or_else(|| anyhow!("Missing type for model {}", name))?;
+
+        let kind = type_token.split('(').next().unwrap_or("");
+        let mut assignments = function_arguments(type_token).unwrap_or_default();
+        assignments.extend(tokens[3..].iter().cloned());
+
+        let params = assignments.iter()
+            .map(|token| {
+                let (key, value) = split_assignment(token)
+                    .ok_or_else(|| anyhow!("Invalid model parameter '{}' in model {}", token, name))?;
+                Ok((key, self.parse_value(&value, parameters)?))
+            })
+            .collect::<Result<Vec<(String, f64)>>>()?;
+
+        let model = DeviceModel::from_card(kind, &params)
+            .map_err(|e| anyhow!("Model {}: {}", name, e))?;
+        Ok((name.clone(), model))
+    }
+
+    /// Parse an independent source specification:
+    /// `[DC] value [AC mag [phase]] [PULSE(...)|SIN(...)|EXP(...)|PWL(...)|SFFM(...)]`.
+    /// Returns the DC value (the waveform's t=0 value if no DC value is given), the waveform
+    /// and the AC magnitude and phase in degrees (magnitude 1 and phase 0 if omitted).
+    fn parse_source_spec(&self, spec: &[String], parameters: &HashMap<String, f64>, origin: &SourceLine) -> Result<SourceSpec> {
+        let mut dc = None;
+        let mut waveform = None;
+        let mut ac = None;
+        let mut index = 0;
+
+        while index < spec.len() {
+            let token = &spec[index];
+            let kind = token.split('(').next().unwrap_or("").to_uppercase();
+            index += 1;
+
+            match kind.as_str() {
+                "DC" => {
+                    let value = spec.get(index)
+                        .ok_or_else(|| anyhow!("Missing value after {}", token))?;
+                    dc = Some(self.parse_value(value, parameters)?);
+                    index += 1;
+                }
+                "AC" => {
+                    // 小信号激励，只在交流分析中使用
+                    let mut values = Vec::new();
+                    while values.len() < 2 {
+                        match spec.get(index).map(|v| self.parse_value(v, parameters)) {
+                            Some(Ok(value)) => values.push(value),
+                            _ => break,
+                        }
+                        index += 1;
+                    }
+                    ac = Some((values.first().copied().unwrap_or(1.0), values.get(1).copied().unwrap_or(0.0)));
+                }
+                "PULSE" | "SIN" | "EXP" | "SFFM" => {
+                    let args = function_arguments(token)
+                        .ok_or_else(|| anyhow!("Invalid {} specification: {}", kind, token))?;
+                    let values = args.iter()
+                        .map(|arg| self.parse_value(arg, parameters))
+                        .collect::<Result<Vec<f64>>>()?;
+                    waveform = Some(Waveform::from_values(&kind, &values)?);
+                }
+                "PWL" => {
+                    let (pwl, consumed) = self.parse_pwl(token, &spec[index..], parameters, origin)?;
+                    waveform = Some(pwl);
+                    index += consumed;
+                }
+                _ if dc.is_none() && waveform.is_none() => {
+                    dc = Some(self.parse_value(token, parameters)?);
+                }
+                _ => return Err(anyhow!("Unexpected token '{}' in source specification", token)),
+            }
+        }
+
+        // 只有 AC 激励的源直流值为零
+        let value = dc
+            .or_else(|| waveform.as_ref().map(Waveform::initial_value))
+            .or(ac.map(|_| 0.0))
+            .ok_or_else(|| anyhow!("Missing source value"))?;
+        Ok((value, waveform, ac))
+    }
+
+    /// Parse `PWL(t1 v1 t2 v2 ... [R=t] [TD=t])` or `PWL FILE=name [R=t] [TD=t]`.
+    /// Options may also follow the closing parenthesis; returns how many of `rest` were used.
+    fn parse_pwl(&self, token: &str, rest: &[String], parameters: &HashMap<String, f64>, origin: &SourceLine) -> Result<(Waveform, usize)> {
+        let mut args = function_arguments(token).unwrap_or_default();
+        let mut consumed = 0;
+        while let Some(extra) = rest.get(consumed) {
+            if extra.eq_ignore_ascii_case("file") && consumed + 1 < rest.len() {
+                args.extend_from_slice(&rest[consumed..consumed + 2]);
+                consumed += 2;
+            } else if split_assignment(extra).is_some() {
+                args.push(extra.clone());
+                consumed += 1;
+            } else {
+                break;
+            }
+        }
+
+        let mut values = Vec::new();
+        let mut repeat = None;
+        let mut delay = 0.0;
+        let mut file = None;
+        let mut args = args.iter();
+        while let Some(arg) = args.next() {
+            if arg.eq_ignore_ascii_case("file") {
+                file = Some(args.next().ok_or_else(|| anyhow!("Missing PWL file name"))?.clone());
+                continue;
+            }
+            match split_assignment(arg) {
+                Some((name, value)) => match name.as_str() {
+                    "r" => repeat = Some(self.parse_value(&value, parameters)?),
+                    "td" => delay = self.parse_value(&value, parameters)?,
+                    "file" => file = Some(value),
+                    _ => return Err(anyhow!("Unknown PWL option '{}'", name)),
+                },
+                None => values.push(self.parse_value(arg, parameters)?),
+            }
+        }
+
+        let points = match file {
+            Some(name) => {
+                if !values.is_empty() {
+                    return Err(anyhow!("PWL cannot combine FILE with inline points"));
+                }
+                let path = self.resolve_include(unquote(&name), origin)?;
+                waveform::read_pwl_file(&path)?
+            }
+            None => {
+                if values.len() % 2 != 0 {
+                    return Err(anyhow!("PWL needs time/value pairs, got {} values", values.len()));
+                }
+                values.chunks(2).map(|pair| (pair[0], pair[1])).collect()
+            }
+        };
+
+        Ok((Waveform::pwl(points, repeat, delay)?, consumed))
+    }
+
+    /// Parse a numeric value: a literal with unit, a `{expr}` / `'expr'` expression
+    /// or the bare name of a parameter
+    fn parse_value(&self, value_str: &str, parameters: &HashMap<String, f64>) -> Result<f64> {
+        if value_str.starts_with(['{', '\'']) {
+            return expression::evaluate(value_str, parameters)
+                .map_err(|e| anyhow!("Cannot evaluate '{}': {}", value_str, e));
+        }
+        if let Some(&value) = parameters.get(&value_str.to_lowercase()) {
+            return Ok(value);
+        }
+        self.parse_value_with_unit(value_str)
     }
     
-    fn parse_analysis_line(&self, line: &str) -> Result<Option<Analysis>> {
+    fn parse_analysis_line(&self, line: &str, parameters: &HashMap<String, f64>) -> Result<Option<Analysis>> {
         if let Some(captures) = ANALYSIS_PATTERN.captures(line) {
-            let analysis_type = captures.get(1).unwrap().as_str();
+            let analysis_type = captures.get(1).unwrap().as_str().to_lowercase();
             let params = captures.get(2).unwrap().as_str();
             
-            match analysis_type {
+            match analysis_type.as_str() {
                 "op" => Ok(Some(Analysis::Operating)),
                 "tran" => {
-                    let parts: Vec<&str> = params.split_whitespace().collect();
+                    let mut parts = tokenize_line(params);
+                    let uic = parts.last().is_some_and(|part| part.eq_ignore_ascii_case("uic"));
+                    if uic {
+                        parts.pop();
+                    }
                     if parts.len() >= 2 {
-                        let tstep = self.parse_time_with_unit(parts[0])?;
-                        let tstop = self.parse_time_with_unit(parts[1])?;
-                        Ok(Some(Analysis::Transient { tstep, tstop, tstart: None }))
+                        let tstep = self.parse_value(&parts[0], parameters)?;
+                        let tstop = self.parse_value(&parts[1], parameters)?;
+                        let optional = |index: usize| parts.get(index)
+                            .map(|part| self.parse_value(part, parameters))
+                            .transpose();
+                        let (tstart, tmax) = (optional(2)?, optional(3)?);
+                        Ok(Some(Analysis::Transient { tstep, tstop, tstart, tmax, uic }))
                     } else {
                         Err(anyhow!("Invalid transient analysis parameters"))
                     }
                 }
                 "dc" => {
-                    let parts: Vec<&str> = params.split_whitespace().collect();
-                    if parts.len() >= 4 {
-                        let source = parts[0].to_string();
-                        let start = parts[1].parse::<f64>()?;
-                        let stop = parts[2].parse::<f64>()?;
-                        let step = parts[3].parse::<f64>()?;
-                        Ok(Some(Analysis::DcSweep { source, start, stop, step }))
+                    let parts = tokenize_line(params);
+                    let (sweep, used) = self.parse_sweep(&parts, parameters)?;
+                    let outer = if used < parts.len() {
+                        let (outer, outer_used) = self.parse_sweep(&parts[used..], parameters)?;
+                        if used + outer_used < parts.len() {
+                            return Err(anyhow!("Unexpected '{}' after second DC sweep", parts[used + outer_used]));
+                        }
+                        Some(outer)
                     } else {
-                        Err(anyhow!("Invalid DC sweep parameters"))
+                        None
+                    };
+                    Ok(Some(Analysis::DcSweep { sweep, outer }))
+                }
+                "ac" => {
+                    // .ac dec|oct|lin 点数 起始频率 终止频率
+                    let parts = tokenize_line(params);
+                    if parts.len() != 4 {
+                        return Err(anyhow!("Invalid AC analysis parameters"));
+                    }
+                    let points = self.parse_value(&parts[1], parameters)?;
+                    if points < 1.0 || points.fract() != 0.0 {
+                        return Err(anyhow!("Invalid number of AC points: {}", points));
                     }
+                    let fstart = self.parse_value(&parts[2], parameters)?;
+                    let fstop = self.parse_value(&parts[3], parameters)?;
+                    Sweep::frequency(&parts[0], points as usize, fstart, fstop)?;
+                    Ok(Some(Analysis::Ac { points: points as usize, fstart, fstop, sweep_type: parts[0].to_lowercase() }))
                 }
                 _ => Ok(None),
             }
@@ -248,62 +909,68 @@ impl SpiceParser {
         }
     }
     
-    fn parse_value_with_unit(&self, value_str: &str) -> Result<f64> {
-        if let Some(captures) = VALUE_PATTERN.captures(value_str) {
-            let value = captures.get(1).unwrap().as_str().parse::<f64>()?;
-            let unit = captures.get(2).unwrap().as_str().to_lowercase();
-            
-            let multiplier = match unit.as_str() {
-                "f" | "femto" => 1e-15,
-                "p" | "pico" => 1e-12,
-                "n" | "nano" => 1e-9,
-                "u" | "micro" => 1e-6,
-                "m" | "milli" => 1e-3,
-                "k" | "kilo" => 1e3,
-                "meg" | "mega" => 1e6,
-                "g" | "giga" => 1e9,
-                "t" | "tera" => 1e12,
-                "v" => 1.0, // 电压单位
-                "" => 1.0,   // 无单位
-                _ => return Err(anyhow!("Unknown unit: {}", unit)),
-            };
-            
-            Ok(value * multiplier)
-        } else {
-            // 尝试直接解析数值
-            value_str.parse::<f64>().map_err(|e| anyhow!("Invalid value: {}", e))
+    /// Parse one DC sweep specification and return it with the number of tokens used:
+    /// `name start stop step`, `name LIN|DEC|OCT points start stop` or `name LIST v1 v2 ...`
+    fn parse_sweep(&self, tokens: &[String], parameters: &HashMap<String, f64>) -> Result<(Sweep, usize)> {
+        let name = tokens.first()
+            .ok_or_else(|| anyhow!("Invalid DC sweep parameters"))?;
+        let keyword = tokens.get(1).map(|t| t.to_uppercase()).unwrap_or_default();
+        let values = |range: std::ops::Range<usize>| -> Result<Vec<f64>> {
+            let args = tokens.get(range)
+                .ok_or_else(|| anyhow!("Invalid DC sweep parameters for {}", name))?;
+            args.iter().map(|arg| self.parse_value(arg, parameters)).collect()
+        };
+        let point_count = |value: f64| -> Result<usize> {
+            if value < 1.0 || value.fract() != 0.0 {
+                return Err(anyhow!("Invalid number of sweep points for {}: {}", name, value));
+            }
+            Ok(value as usize)
+        };
+
+        match keyword.as_str() {
+            "LIN" => {
+                let v = values(2..5)?;
+                Ok((Sweep::linear_points(name, point_count(v[0])?, v[1], v[2])?, 5))
+            }
+            "DEC" | "OCT" => {
+                let v = values(2..5)?;
+                let (start, stop, points) = (v[1], v[2], point_count(v[0])?);
+                let kind = if keyword == "DEC" {
+                    SweepKind::Decade { start, stop, points }
+                } else {
+                    SweepKind::Octave { start, stop, points }
+                };
+                Ok((Sweep::new(name, kind), 5))
+            }
+            "LIST" => {
+                // 列表一直延续到第一个不是数值的记号（下一个扫描变量名）
+                let list: Vec<f64> = tokens[2..].iter()
+                    .map_while(|arg| self.parse_value(arg, parameters).ok())
+                    .collect();
+                if list.is_empty() {
+                    return Err(anyhow!("LIST sweep of {} has no values", name));
+                }
+                let used = 2 + list.len();
+                Ok((Sweep::new(name, SweepKind::List(list)), used))
+            }
+            _ => {
+                let v = values(1..4)?;
+                Ok((Sweep::linear(name, v[0], v[1], v[2]), 4))
+            }
         }
     }
-    
-    fn parse_time_with_unit(&self, time_str: &str) -> Result<f64> {
-        if let Some(captures) = VALUE_PATTERN.captures(time_str) {
-            let value = captures.get(1).unwrap().as_str().parse::<f64>()?;
-            let unit = captures.get(2).unwrap().as_str().to_lowercase();
-            
-            let multiplier = match unit.as_str() {
-                "fs" => 1e-15,
-                "ps" => 1e-12,
-                "ns" => 1e-9,
-                "us" => 1e-6,
-                "ms" => 1e-3,
-                "s" => 1.0,
-                "" => 1.0,   // 无单位，假设秒
-                _ => return Err(anyhow!("Unknown time unit: {}", unit)),
-            };
-            
-            Ok(value * multiplier)
-        } else {
-            // 尝试直接解析数值
-            time_str.parse::<f64>().map_err(|e| anyhow!("Invalid time value: {}", e))
-        }
+
+    fn parse_value_with_unit(&self, value_str: &str) -> Result<f64> {
+        expression::parse_number(value_str)
     }
 
     /// Preprocess SPICE netlist lines - handle line continuations, comments, etc.
-    fn preprocess_lines(&self, content: &str) -> Vec<String> {
-        let mut processed_lines = Vec::new();
-        let mut current_line = String::new();
+    /// Each logical line remembers the file and line number it starts on.
+    fn preprocess_source(&self, content: &str, file: Option<&Path>) -> Vec<SourceLine> {
+        let mut processed_lines: Vec<SourceLine> = Vec::new();
+        let mut current_line: Option<SourceLine> = None;
         
-        for line in content.lines() {
+        for (index, line) in content.lines().enumerate() {
             let line = line.trim();
             
             // Skip empty lines
@@ -317,300 +984,208 @@ impl SpiceParser {
             }
             
             // Handle line continuation
-            if line.starts_with('+') {
-                current_line.push(' ');
-                current_line.push_str(&line[1..].trim());
-            } else {
-                if !current_line.is_empty() {
-                    processed_lines.push(current_line.clone());
+            if let Some(continuation) = line.strip_prefix('+') {
+                if let Some(current) = current_line.as_mut() {
+                    current.text.push(' ');
+                    current.text.push_str(continuation.trim());
                 }
-                current_line = line.to_string();
+            } else {
+                processed_lines.extend(current_line.take());
+                current_line = Some(SourceLine {
+                    text: line.to_string(),
+                    file: file.map(Path::to_path_buf),
+                    line: index + 1,
+                });
             }
         }
         
         // Don't forget the last line
-        if !current_line.is_empty() {
-            processed_lines.push(current_line);
-        }
+        processed_lines.extend(current_line);
         
         processed_lines
     }
-}
 
-// Parser functions using nom
-fn parse_spice_netlist(input: &str) -> IResult<&str, SpiceNetlist> {
-    let (input, title) = parse_title(input)?;
-    let (input, lines) = many0(parse_netlist_line)(input)?;
-    
-    let mut components = Vec::new();
-    let mut subcircuits = Vec::new();
-    let mut parameters = HashMap::new();
-    let mut analyses = Vec::new();
-    
-    for line in lines {
-        match line {
-            NetlistLine::Component(comp) => components.push(comp),
-            NetlistLine::Subcircuit(sub) => subcircuits.push(sub),
-            NetlistLine::Parameter(name, value) => { parameters.insert(name, value); },
-            NetlistLine::Analysis(analysis) => analyses.push(analysis),
-            NetlistLine::End => break,
-        }
-    }
-    
-    // Extract unique nodes
-    let mut node_names = std::collections::HashSet::new();
-    for comp in &components {
-        node_names.insert(comp.nodes[0].clone());
-        node_names.insert(comp.nodes[1].clone());
-        if comp.nodes.len() > 2 {
-            for node in &comp.nodes[2..] {
-                node_names.insert(node.clone());
+    /// Replace .INCLUDE and .LIB references with the lines they refer to
+    fn expand_includes(&self, lines: Vec<SourceLine>, stack: &mut Vec<(PathBuf, Option<String>)>) -> Result<Vec<SourceLine>> {
+        let mut expanded = Vec::new();
+        let mut lines = lines.into_iter();
+
+        while let Some(line) = lines.next() {
+            let tokens = tokenize_line(&line.text);
+            let keyword = tokens[0].to_lowercase();
+            match keyword.as_str() {
+                ".include" | ".inc" => {
+                    let name = tokens.get(1)
+                        .ok_or_else(|| anyhow!("{}: Missing file name in .INCLUDE", line.location()))?;
+                    let path = self.resolve_include(unquote(name), &line)?;
+                    expanded.extend(self.read_included(&path, None, &line, stack)?);
+                }
+                ".lib" if tokens.len() >= 3 => {
+                    let path = self.resolve_include(unquote(&tokens[1]), &line)?;
+                    let section = unquote(&tokens[2]).to_string();
+                    expanded.extend(self.read_included(&path, Some(section), &line, stack)?);
+                }
+                ".lib" => {
+                    // 未被引用的库段定义，跳到 .ENDL
+                    lines.by_ref()
+                        .find(|line| line.text.split_whitespace().next()
+                            .is_some_and(|keyword| keyword.eq_ignore_ascii_case(".endl")))
+                        .ok_or_else(|| anyhow!("{}: .LIB section is missing .ENDL", line.location()))?;
+                }
+                ".endl" => {}
+                _ => expanded.push(line),
             }
         }
-    }
-    
-    let nodes: Vec<Node> = node_names.into_iter()
-        .map(|name| Node::new(name))
-        .collect();
-    
-    Ok((input, SpiceNetlist {
-        title,
-        components,
-        nodes,
-        subcircuits,
-        parameters,
-        analyses,
-    }))
-}
 
-#[derive(Debug)]
-enum NetlistLine {
-    Component(Component),
-    Subcircuit(Subcircuit),
-    Parameter(String, f64),
-    Analysis(Analysis),
-    End,
-}
-
-fn parse_title(input: &str) -> IResult<&str, String> {
-    let (input, title) = terminated(not_line_ending, line_ending)(input)?;
-    Ok((input, title.trim().to_string()))
-}
-
-fn parse_netlist_line(input: &str) -> IResult<&str, NetlistLine> {
-    let (input, _) = multispace0(input)?;
-    
-    // Try parsing .end first
-    if let Ok((remaining, _)) = tag_no_case::<&str, &str, nom::error::Error<&str>>(".end")(input) {
-        return Ok((remaining, NetlistLine::End));
+        Ok(expanded)
     }
-    
-    alt((
-        map(parse_component, NetlistLine::Component),
-        map(parse_analysis, NetlistLine::Analysis),
-        map(parse_parameter, |(name, value)| NetlistLine::Parameter(name, value)),
-        map(preceded(char('*'), not_line_ending), |_| NetlistLine::End), // Skip comments
-    ))(input)
-}
 
-fn parse_component(input: &str) -> IResult<&str, Component> {
-    let (input, _) = multispace0(input)?; // Skip leading whitespace
-    let (input, name) = parse_component_name(input)?;
-    let (input, _) = multispace1(input)?;
-    let (input, nodes) = separated_list1(multispace1, parse_node_name)(input)?;
-    let (input, _) = multispace1(input)?;
-    let (input, value) = parse_component_value(input)?;
-    let (input, _) = multispace0(input)?; // Allow trailing whitespace or newlines
-    
-    let component_type = match name.chars().next().unwrap().to_ascii_uppercase() {
-        'R' => ComponentType::Resistor,
-        'C' => ComponentType::Capacitor,
-        'L' => ComponentType::Inductor,
-        'V' => ComponentType::VoltageSource,
-        'I' => ComponentType::CurrentSource,
-        'D' => ComponentType::Diode,
-        _ => return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Alt))),
-    };
-    
-    if nodes.len() < 2 {
-        return Err(nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Count)));
-    }
-    
-    Ok((input, Component {
-        name,
-        component_type,
-        nodes,
-        value,
-        model: None,
-    }))
-}
+    /// Read an included file (or one section of a library file) and expand it recursively
+    fn read_included(&self, path: &Path, section: Option<String>, origin: &SourceLine, stack: &mut Vec<(PathBuf, Option<String>)>) -> Result<Vec<SourceLine>> {
+        let key = include_key(path, section.clone());
+        if stack.contains(&key) {
+            return Err(anyhow!("{}: Circular include of '{}'", origin.location(), path.display()));
+        }
 
-fn parse_component_name(input: &str) -> IResult<&str, String> {
-    map(
-        recognize(pair(
-            take_while1(|c: char| c.is_alphabetic()),
-            opt(take_while1(|c: char| c.is_alphanumeric() || c == '_'))
-        )),
-        |s: &str| s.to_string()
-    )(input)
-}
+        let content = fs::read_to_string(path)
+            .map_err(|e| anyhow!("{}: Failed to read file '{}': {}", origin.location(), path.display(), e))?;
+        let mut lines = self.preprocess_source(&content, Some(path));
 
-fn parse_node_name(input: &str) -> IResult<&str, String> {
-    map(
-        alt((
-            tag("0"),  // Ground node
-            alphanumeric1,
-        )),
-        |s: &str| s.to_string()
-    )(input)
-}
+        if let Some(section) = &section {
+            let is_keyword = |line: &SourceLine, keyword: &str| {
+                line.text.split_whitespace().next().is_some_and(|k| k.eq_ignore_ascii_case(keyword))
+            };
+            let start = lines.iter()
+                .position(|line| {
+                    let tokens = tokenize_line(&line.text);
+                    tokens.len() == 2 && tokens[0].eq_ignore_ascii_case(".lib")
+                        && unquote(&tokens[1]).eq_ignore_ascii_case(section)
+                })
+                .ok_or_else(|| anyhow!("{}: Section '{}' not found in library '{}'", origin.location(), section, path.display()))?;
+            let end = lines[start..].iter()
+                .position(|line| is_keyword(line, ".endl"))
+                .map(|offset| start + offset)
+                .ok_or_else(|| anyhow!("{}: Section '{}' in '{}' is missing .ENDL", origin.location(), section, path.display()))?;
+            lines = lines.drain(start + 1..end).collect();
+        }
 
-fn parse_component_value(input: &str) -> IResult<&str, f64> {
-    let (input, value_str) = take_while1(|c: char| 
-        c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '+'
-    )(input)?;
-    
-    // Parse value with unit suffix
-    let value = parse_value_with_unit(value_str)
-        .map_err(|_| nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Float)))?;
-    
-    Ok((input, value))
-}
+        stack.push(key);
+        let expanded = self.expand_includes(lines, stack);
+        stack.pop();
+        expanded
+    }
 
-fn parse_analysis(input: &str) -> IResult<&str, Analysis> {
-    let (input, _) = char('.')(input)?;
-    
-    alt((
-        parse_tran_analysis,
-        parse_dc_analysis,
-        parse_op_analysis,
-        parse_ac_analysis,
-    ))(input)
-}
+    /// Find an included file: relative to the including file, then in the search paths
+    fn resolve_include(&self, name: &str, origin: &SourceLine) -> Result<PathBuf> {
+        let path = Path::new(name);
+        if path.is_absolute() {
+            return Ok(path.to_path_buf());
+        }
 
-fn parse_tran_analysis(input: &str) -> IResult<&str, Analysis> {
-    let (input, _) = tag_no_case("tran")(input)?;
-    let (input, _) = space1(input)?;
-    let (input, tstep) = parse_time_value(input)?;
-    let (input, _) = space1(input)?;
-    let (input, tstop) = parse_time_value(input)?;
-    let (input, tstart) = opt(preceded(space1, parse_time_value))(input)?;
-    let (input, _) = opt(line_ending)(input)?;
-    
-    Ok((input, Analysis::Transient { tstep, tstop, tstart }))
+        let base = origin.file.as_deref()
+            .and_then(Path::parent)
+            .unwrap_or_else(|| Path::new(""));
+        std::iter::once(base.join(path))
+            .chain(self.search_paths.iter().map(|dir| dir.join(path)))
+            .find(|candidate| candidate.is_file())
+            .ok_or_else(|| anyhow!("{}: Cannot find included file '{}'", origin.location(), name))
+    }
 }
 
-fn parse_dc_analysis(input: &str) -> IResult<&str, Analysis> {
-    let (input, _) = tag_no_case("dc")(input)?;
-    let (input, _) = space1(input)?;
-    let (input, source) = parse_component_name(input)?;
-    let (input, _) = space1(input)?;
-    let (input, start) = double(input)?;
-    let (input, _) = space1(input)?;
-    let (input, stop) = double(input)?;
-    let (input, _) = space1(input)?;
-    let (input, step) = double(input)?;
-    let (input, _) = opt(line_ending)(input)?;
-    
-    Ok((input, Analysis::DcSweep { source, start, stop, step }))
+/// Identity of an included file/section for cycle detection
+fn include_key(path: &Path, section: Option<String>) -> (PathBuf, Option<String>) {
+    let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    (path, section.map(|s| s.to_lowercase()))
 }
 
-fn parse_op_analysis(input: &str) -> IResult<&str, Analysis> {
-    let (input, _) = tag_no_case("op")(input)?;
-    let (input, _) = opt(line_ending)(input)?;
-    Ok((input, Analysis::Operating))
+/// Strip surrounding single or double quotes from a file name
+fn unquote(text: &str) -> &str {
+    text.trim_matches(|c| c == '\'' || c == '"')
 }
 
-fn parse_ac_analysis(input: &str) -> IResult<&str, Analysis> {
-    let (input, _) = tag_no_case("ac")(input)?;
-    let (input, _) = space1(input)?;
-    let (input, sweep_type) = alt((
-        tag_no_case("lin"),
-        tag_no_case("oct"),
-        tag_no_case("dec"),
-    ))(input)?;
-    let (input, _) = space1(input)?;
-    let (input, points) = map(double, |x| x as usize)(input)?;
-    let (input, _) = space1(input)?;
-    let (input, fstart) = double(input)?;
-    let (input, _) = space1(input)?;
-    let (input, fstop) = double(input)?;
-    let (input, _) = opt(line_ending)(input)?;
-    
-    Ok((input, Analysis::Ac { 
-        points, 
-        fstart, 
-        fstop, 
-        sweep_type: sweep_type.to_string() 
-    }))
-}
+/// Split a netlist line into tokens, keeping `(...)`, `{...}` and `'...'` groups together
+/// and joining `name = value` into a single `name=value` token
+fn tokenize_line(line: &str) -> Vec<String> {
+    let mut tokens: Vec<String> = Vec::new();
+    let mut current = String::new();
+    let mut depth = 0usize;
+    let mut quoted = false;
 
-fn parse_parameter(input: &str) -> IResult<&str, (String, f64)> {
-    let (input, _) = tag_no_case(".param")(input)?;
-    let (input, _) = space1(input)?;
-    let (input, name) = parse_component_name(input)?;
-    let (input, _) = space0(input)?;
-    let (input, _) = char('=')(input)?;
-    let (input, _) = space0(input)?;
-    let (input, value) = double(input)?;
-    let (input, _) = opt(line_ending)(input)?;
-    
-    Ok((input, (name, value)))
-}
+    for c in line.chars() {
+        match c {
+            '\'' => {
+                quoted = !quoted;
+                current.push(c);
+            }
+            _ if quoted => current.push(c),
+            '(' | '{' => {
+                // `PULSE (0 5 ...)` 与 `PULSE(0 5 ...)` 等价
+                if depth == 0 && current.is_empty() && c == '(' {
+                    if let Some(last) = tokens.pop() {
+                        current = last;
+                    }
+                }
+                depth += 1;
+                current.push(c);
+            }
+            ')' | '}' => {
+                depth = depth.saturating_sub(1);
+                current.push(c);
+            }
+            c if depth == 0 && (c.is_whitespace() || c == ',') => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
 
-fn parse_time_value(input: &str) -> IResult<&str, f64> {
-    let (input, value_str) = take_while1(|c: char| 
-        c.is_ascii_alphanumeric() || c == '.' || c == '-' || c == '+'
-    )(input)?;
-    
-    let value = parse_time_with_unit(value_str)
-        .map_err(|_| nom::Err::Error(nom::error::Error::new(input, nom::error::ErrorKind::Float)))?;
-    
-    Ok((input, value))
+    // 合并 `name = value` 以及 `name= value` 形式的赋值
+    let mut merged: Vec<String> = Vec::new();
+    let mut iter = tokens.into_iter().peekable();
+    while let Some(token) = iter.next() {
+        if token == "=" || token.starts_with('=') {
+            if let Some(last) = merged.last_mut() {
+                last.push_str(&token);
+                if token == "=" {
+                    if let Some(next) = iter.next() {
+                        last.push_str(&next);
+                    }
+                }
+                continue;
+            }
+        }
+        if token.ends_with('=') {
+            let mut token = token;
+            if let Some(next) = iter.next() {
+                token.push_str(&next);
+            }
+            merged.push(token);
+            continue;
+        }
+        merged.push(token);
+    }
+    merged
 }
 
-/// Parse value with unit suffix (e.g., 1k, 1meg, 1m, 1u, 1n, 1p)
-fn parse_value_with_unit(value_str: &str) -> Result<f64> {
-    let value_str = value_str.trim().to_lowercase();
-    
-    if let Some(num_str) = value_str.strip_suffix("meg") {
-        Ok(num_str.parse::<f64>()? * 1e6)
-    } else if let Some(num_str) = value_str.strip_suffix("k") {
-        Ok(num_str.parse::<f64>()? * 1e3)
-    } else if let Some(num_str) = value_str.strip_suffix("m") {
-        Ok(num_str.parse::<f64>()? * 1e-3)
-    } else if let Some(num_str) = value_str.strip_suffix("u") {
-        Ok(num_str.parse::<f64>()? * 1e-6)
-    } else if let Some(num_str) = value_str.strip_suffix("n") {
-        Ok(num_str.parse::<f64>()? * 1e-9)
-    } else if let Some(num_str) = value_str.strip_suffix("p") {
-        Ok(num_str.parse::<f64>()? * 1e-12)
-    } else if let Some(num_str) = value_str.strip_suffix("f") {
-        Ok(num_str.parse::<f64>()? * 1e-15)
-    } else {
-        Ok(value_str.parse::<f64>()?)
+/// Split a `name=value` token, lowercasing the name
+fn split_assignment(token: &str) -> Option<(String, String)> {
+    let (name, value) = token.split_once('=')?;
+    if name.is_empty() || token.starts_with('{') {
+        return None;
     }
+    Some((name.trim().to_lowercase(), value.trim().to_string()))
 }
 
-/// Parse time value with unit (fs, ps, ns, us, ms, s)
-fn parse_time_with_unit(value_str: &str) -> Result<f64> {
-    let value_str = value_str.trim().to_lowercase();
-    
-    if let Some(num_str) = value_str.strip_suffix("fs") {
-        Ok(num_str.parse::<f64>()? * 1e-15)
-    } else if let Some(num_str) = value_str.strip_suffix("ps") {
-        Ok(num_str.parse::<f64>()? * 1e-12)
-    } else if let Some(num_str) = value_str.strip_suffix("ns") {
-        Ok(num_str.parse::<f64>()? * 1e-9)
-    } else if let Some(num_str) = value_str.strip_suffix("us") {
-        Ok(num_str.parse::<f64>()? * 1e-6)
-    } else if let Some(num_str) = value_str.strip_suffix("ms") {
-        Ok(num_str.parse::<f64>()? * 1e-3)
-    } else if let Some(num_str) = value_str.strip_suffix("s") {
-        Ok(num_str.parse::<f64>()?)
-    } else {
-        Ok(value_str.parse::<f64>()?)
-    }
+/// Arguments of a `NAME(a b c)` token
+fn function_arguments(token: &str) -> Option<Vec<String>> {
+    let open = token.find('(')?;
+    let inner = token[open + 1..].strip_suffix(')')?;
+    Some(tokenize_line(inner))
 }
 
 #[cfg(test)]
@@ -624,28 +1199,496 @@ mod tests {
         let content = "Test Circuit\nR1 1 2 1k\n.end";
         
         // Check the preprocessing first
-        let lines = parser.preprocess_lines(content);
+        let lines = parser.preprocess_source(content, None);
         assert_eq!(lines.len(), 3);
-        assert_eq!(lines[0], "Test Circuit");
-        assert_eq!(lines[1], "R1 1 2 1k");
-        assert_eq!(lines[2], ".end");
+        assert_eq!(lines[0].text, "Test Circuit");
+        assert_eq!(lines[1].text, "R1 1 2 1k");
+        assert_eq!(lines[2].text, ".end");
+        assert_eq!(lines[2].line, 3);
         
         // TODO: Fix netlist parsing - it works in actual files but not in unit tests
         // The program successfully compiles and runs with real SPICE files
     }
 
+    #[test]
+    fn test_subcircuit_expansion() {
+        let parser = SpiceParser::new();
+        let content = "\
+Divider chain
+.subckt half in out gnd PARAMS: r=1k
+R1 in mid {r}
+R2 mid out {r}
+.ends
+.subckt stage a b
+X1 a tap 0 half r=2k
+X2 tap b 0 half
+.ends
+V1 1 0 DC 5
+X1 1 2 stage
+X2 2 0 stage
+.end";
+        let netlist = parser.parse_netlist(content).unwrap();
+        assert_eq!(netlist.subcircuits.len(), 2);
+        assert_eq!(netlist.components.len(), 9);
+
+        let find = |name: &str| netlist.components.iter().find(|c| c.name == name).unwrap();
+        let r = find("X1.X1.R1");
+        assert_eq!(r.nodes, vec!["1".to_string(), "X1.X1.mid".to_string()]);
+        assert_eq!(r.value, 2000.0);
+        let r = find("X1.X1.R2");
+        assert_eq!(r.nodes, vec!["X1.X1.mid".to_string(), "X1.tap".to_string()]);
+        let r = find("X1.X2.R2");
+        assert_eq!(r.nodes, vec!["X1.X2.mid".to_string(), "2".to_string()]);
+        assert_eq!(r.value, 1000.0);
+        let r = find("X2.X2.R2");
+        assert_eq!(r.nodes, vec!["X2.X2.mid".to_string(), "0".to_string()]);
+    }
+
+    #[test]
+    fn test_subcircuit_node_case() {
+        // 端口与内部节点名不区分大小写
+        let parser = SpiceParser::new();
+        let content = "\
+Buffer
+.subckt Buf IN Out
+R1 in out 1k
+R2 OUT Mid 1k
+R3 mid 0 1k
+.ends
+V1 a 0 DC 1
+X1 a b buf
+.end";
+        let netlist = parser.parse_netlist(content).unwrap();
+        let find = |name: &str| netlist.components.iter().find(|c| c.name == name).unwrap();
+        assert_eq!(find("X1.R1").nodes, vec!["a".to_string(), "b".to_string()]);
+        assert_eq!(find("X1.R2").nodes, vec!["b".to_string(), "X1.mid".to_string()]);
+        assert_eq!(find("X1.R3").nodes, vec!["X1.mid".to_string(), "0".to_string()]);
+    }
+
+    #[test]
+    fn test_nested_subcircuit_scope() {
+        let parser = SpiceParser::new();
+        let content = "\
+Nested definitions
+.subckt outer a
+.subckt inner p
+R1 p 0 1k
+.ends
+X1 a inner
+.ends
+X1 1 outer
+V1 1 0 5
+.end";
+        let netlist = parser.parse_netlist(content).unwrap();
+        assert_eq!(netlist.subcircuits[0].subcircuits[0].name, "inner");
+        assert!(netlist.components.iter().any(|c| c.name == "X1.X1.R1"));
+
+        // 嵌套定义在外部不可见
+        let content = "Scope\n.subckt outer a\n.subckt inner p\nR1 p 0 1k\n.ends\n.ends\nX1 1 inner\n.end";
+        assert!(parser.parse_netlist(content).is_err());
+
+        // 子电路递归实例化自身
+        let content = "Recursion\n.subckt loop a\nX1 a loop\n.ends\nX1 1 loop\n.end";
+        let error = parser.parse_netlist(content).unwrap_err().to_string();
+        assert!(error.contains("Recursive instantiation of subcircuit 'loop'"), "{}", error);
+
+        // 缺少 .ends 的定义
+        let content = "Missing ends\n.subckt open a\nR1 a 0 1k\nX1 1 open\n.end";
+        let error = parser.parse_netlist(content).unwrap_err().to_string();
+        assert!(error.contains("line 2: Subcircuit 'open' is missing .ENDS"), "{}", error);
+    }
+
+    #[test]
+    fn test_param_expressions() {
+        let parser = SpiceParser::new();
+        let content = "\
+Parameterized filter
+.param Rload=2k Cval='1n * scale'
+.param scale=2 tau={Rload*Cval}
+.subckt rc in out PARAMS: r=1k c={cval/2}
+R1 in out {r}
+C1 out 0 {c}
+.param rtotal={2*r}
+R2 out 0 rtotal
+.ends
+V1 1 0 DC {sqrt(16)}
+R1 1 2 {2*Rload}
+X1 2 3 rc r={Rload/4}
+.tran {tau/100} {5*tau} 0 {tau/10}
+.end";
+        let netlist = parser.parse_netlist(content).unwrap();
+        assert_eq!(netlist.parameters["rload"], 2000.0);
+        assert!((netlist.parameters["tau"] - 4e-6).abs() < 1e-18);
+
+        let value = |name: &str| netlist.components.iter().find(|c| c.name == name).unwrap().value;
+        assert_eq!(value("V1"), 4.0);
+        assert_eq!(value("R1"), 4000.0);
+        assert_eq!(value("X1.R1"), 500.0);
+        assert!((value("X1.C1") - 1e-9).abs() < 1e-21);
+        assert_eq!(value("X1.R2"), 1000.0);
+
+        match &netlist.analyses[0] {
+            Analysis::Transient { tstep, tstop, tstart, tmax, uic } => {
+                assert!((tstep - 4e-8).abs() < 1e-20);
+                assert!((tstop - 2e-5).abs() < 1e-18);
+                assert_eq!(*tstart, Some(0.0));
+                assert!((tmax.unwrap() - 4e-7).abs() < 1e-19);
+                assert!(!uic);
+            }
+            other => panic!("unexpected analysis {:?}", other),
+        }
+
+        let cyclic = "Cycle\n.param a={b+1} b={a*2}\nR1 1 0 {a}\n.end";
+        assert!(parser.parse_netlist(cyclic).is_err());
+    }
+
+    #[test]
+    fn test_include_and_lib() {
+        let dir = tempfile::tempdir().unwrap();
+        let models = dir.path().join("models");
+        fs::create_dir(&models).unwrap();
+        fs::write(models.join("cells.lib"), "\
+* shared cells
+.lib typ
+.param rval=2k
+.include load.inc
+.endl
+.lib slow
+.param rval=3k
+.endl
+").unwrap();
+        fs::write(models.join("load.inc"), "Rload out 0 {rval}\n").unwrap();
+        fs::write(dir.path().join("top.sp"), "\
+Top level
+.lib 'models/cells.lib' typ
+V1 in 0 DC 1
+R1 in out 1k
+.end
+").unwrap();
+
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_file(dir.path().join("top.sp").to_str().unwrap()).unwrap();
+        assert_eq!(netlist.title, "Top level");
+        assert_eq!(netlist.parameters["rval"], 2000.0);
+        assert!(netlist.components.iter().any(|c| c.name == "Rload" && c.value == 2000.0));
+
+        // 通过搜索路径找到库文件
+        fs::write(dir.path().join("search.sp"), "Search\n.lib cells.lib slow\nR1 1 0 {rval}\n.end\n").unwrap();
+        let mut parser = SpiceParser::new();
+        assert!(parser.parse_file(dir.path().join("search.sp").to_str().unwrap()).is_err());
+        parser.add_search_path(&models);
+        let netlist = parser.parse_file(dir.path().join("search.sp").to_str().unwrap()).unwrap();
+        assert_eq!(netlist.components[0].value, 3000.0);
+    }
+
+    #[test]
+    fn test_include_errors_carry_location() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("a.inc"), ".include b.inc\n").unwrap();
+        fs::write(dir.path().join("b.inc"), "R1 1 0 1k\n.include a.inc\n").unwrap();
+        fs::write(dir.path().join("bad.inc"), "R1 1 0 1k\nR2 1 0 {undefined}\n").unwrap();
+        fs::write(dir.path().join("cycle.sp"), "Cycle\n.include a.inc\n.end\n").unwrap();
+        fs::write(dir.path().join("bad.sp"), "Bad\nV1 1 0 1\n.include bad.inc\n.end\n").unwrap();
+
+        let parser = SpiceParser::new();
+        let err = parser.parse_file(dir.path().join("cycle.sp").to_str().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("Circular include"));
+        assert!(err.to_string().contains("b.inc:2"));
+
+        let err = parser.parse_file(dir.path().join("bad.sp").to_str().unwrap()).unwrap_err();
+        assert!(err.to_string().contains("bad.inc:2"), "{}", err);
+    }
+
+    #[test]
+    fn test_source_waveforms() {
+        let dir = tempfile::tempdir().unwrap();
+        fs::write(dir.path().join("ramp.txt"), "* time value\n0 0\n1u 2\n2u 2\n").unwrap();
+        fs::write(dir.path().join("sources.sp"), "\
+Sources
+.param vhigh=5
+V1 1 0 DC 1 PULSE(0 {vhigh} 1n 1n 1n 5n 10n)
+V2 2 0 SIN(0.5 1 1k)
+V3 3 0 PWL(0 0 1u 1 2u 0) r=0 td=1u
+I1 4 0 PWL FILE=ramp.txt
+V4 5 0 EXP (0 1 0 1u)
+V5 6 0 AC 0.5 90
+V6 7 0 DC 2 AC SIN(2 1 1k)
+.end
+").unwrap();
+
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_file(dir.path().join("sources.sp").to_str().unwrap()).unwrap();
+        let find = |name: &str| netlist.components.iter().find(|c| c.name == name).unwrap();
+
+        let v1 = find("V1");
+        assert_eq!(v1.value, 1.0);
+        assert_eq!(v1.value_at(4e-9), 5.0);
+        assert_eq!(find("V2").value, 0.5);
+        assert_eq!(find("V3").waveform, Some(Waveform::Pwl {
+            points: vec![(0.0, 0.0), (1e-6, 1.0), (2e-6, 0.0)],
+            repeat: Some(0.0),
+            delay: 1e-6,
+        }));
+        assert!((find("I1").value_at(0.5e-6) - 1.0).abs() < 1e-9);
+        assert!(find("V4").waveform.is_some());
+        assert_eq!((find("V5").value, find("V5").ac), (0.0, Some((0.5, 90.0))));
+        assert_eq!(find("V6").ac, Some((1.0, 0.0)));
+        assert!(find("V6").waveform.is_some());
+        assert_eq!(find("V1").ac, None);
+
+        assert!(parser.parse_netlist("Bad\nV1 1 0 PWL(0 0 1u)\n.end").is_err());
+        assert!(parser.parse_netlist("Bad\nV1 1 0 PULSE(0 1 0 -1n)\n.end").is_err());
+    }
+
+    #[test]
+    fn test_dc_sweep_specs() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("\
+Sweeps
+.param vmax=5
+.dc V1 0 {vmax} 0.1 V2 0 3 1
+.dc I1 DEC 10 1u 1m
+.dc V1 LIST 0 1.5 3 R1 LIN 3 1k 2k
+.end").unwrap();
+
+        assert_eq!(netlist.analyses.len(), 3);
+        match &netlist.analyses[0] {
+            Analysis::DcSweep { sweep, outer: Some(outer) } => {
+                assert_eq!(sweep, &Sweep::linear("V1", 0.0, 5.0, 0.1));
+                assert_eq!(outer, &Sweep::linear("V2", 0.0, 3.0, 1.0));
+            }
+            other => panic!("Expected nested DC sweep, got {:?}", other),
+        }
+        match &netlist.analyses[1] {
+            Analysis::DcSweep { sweep, outer: None } => {
+                assert_eq!(sweep.kind, SweepKind::Decade { start: 1e-6, stop: 1e-3, points: 10 });
+            }
+            other => panic!("Expected DC sweep, got {:?}", other),
+        }
+        match &netlist.analyses[2] {
+            Analysis::DcSweep { sweep, outer: Some(outer) } => {
+                assert_eq!(sweep.kind, SweepKind::List(vec![0.0, 1.5, 3.0]));
+                assert_eq!(outer.values().unwrap(), vec![1000.0, 1500.0, 2000.0]);
+            }
+            other => panic!("Expected nested DC sweep, got {:?}", other),
+        }
+
+        assert!(parser.parse_netlist("Bad\n.dc V1 0 1\n.end").is_err());
+        assert!(parser.parse_netlist("Bad\n.dc V1 DEC 2.5 1 10\n.end").is_err());
+    }
+
+    #[test]
+    fn test_model_cards() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("\
+Diodes
+.param isat=2.52n
+.temp 50
+.options method=Gear NOPAGE
+D1 a 0 d1n4148
+D2 a b DMOD 2
+.subckt clamp in
+Dc in 0 dlocal area=3
+.model dlocal D
+.ends
+.model D1N4148 D(IS={isat} RS=0.568 N=1.752
++ CJO=4p M=0.4 TT=20n)
+.model dmod d is=1e-15 bv=10
+.end").unwrap();
+
+        assert_eq!(netlist.temperature, Some(50.0));
+        assert_eq!(netlist.options, vec![("method".to_string(), Some("Gear".to_string())), ("nopage".to_string(), None)]);
+        assert_eq!(netlist.models.len(), 3);
+        match &netlist.models["d1n4148"] {
+            DeviceModel::Diode(model) => {
+                assert_eq!(model.is, 2.52e-9);
+                assert_eq!(model.n, 1.752);
+                assert_eq!(model.m, 0.4);
+            }
+            other => panic!("unexpected model {:?}", other),
+        }
+        let d2 = &netlist.components[1];
+        assert_eq!(d2.component_type, ComponentType::Diode);
+        assert_eq!(d2.nodes, vec!["a", "b"]);
+        assert_eq!(d2.model.as_deref(), Some("DMOD"));
+        assert_eq!(d2.value, 2.0);
+
+        assert!(parser.parse_netlist("Bad\n.model x D(IS=1 foo=2)\n.end").is_err());
+        assert!(parser.parse_netlist("Bad\n.model x Q(IS=1)\n.end").is_err());
+    }
+
+    #[test]
+    fn test_mosfet_lines() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("\
+CMOS inverter
+.param wn=2u
+M1 out in 0 0 nch W={wn} L=1u
+M2 out in vdd vdd pch
+.model nch NMOS(VTO=0.7 KP=110u GAMMA=0.4 LAMBDA=0.04)
+.model pch PMOS(VTO=-0.7 KP=50u TOX=9n CGSO=0.3n)
+.end").unwrap();
+
+        let m1 = &netlist.components[0];
+        assert_eq!(m1.nodes, vec!["out", "in", "0", "0"]);
+        assert_eq!(m1.model.as_deref(), Some("nch"));
+        assert_eq!(m1.component_type, ComponentType::Mosfet {
+            model_type: "nch".to_string(),
+            width: Some(2e-6),
+            length: Some(1e-6),
+        });
+        assert!(matches!(netlist.components[1].component_type, ComponentType::Mosfet { width: None, .. }));
+        assert_eq!(netlist.models["pch"].kind(), "PMOS");
+
+        assert!(parser.parse_netlist("Bad\nM1 d g s nch\n.end").is_err());
+        assert!(parser.parse_netlist("Bad\nM1 d g s b nch AD=1p\n.end").is_err());
+    }
+
+    #[test]
+    fn test_bjt_lines() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("\
+Bipolar
+Q1 c b e qnpn
+Q2 c b e sub qnpn
+Q3 c b e qnpn 2
+Q4 c b e sub qpnp area=0.5
+.model qnpn NPN(IS=1e-16 BF=120 VAF=80 RB=100 CJE=1p TF=0.3n)
+.model qpnp PNP(BF=50)
+.end").unwrap();
+
+        let nodes: Vec<usize> = netlist.components.iter().map(|c| c.nodes.len()).collect();
+        assert_eq!(nodes, vec![3, 4, 3, 4]);
+        assert_eq!(netlist.components[1].nodes[3], "sub");
+        assert_eq!(netlist.components[2].component_type, ComponentType::Bjt { model_type: "qnpn".to_string(), area: Some(2.0) });
+        assert_eq!(netlist.components[3].model.as_deref(), Some("qpnp"));
+        assert!(matches!(netlist.components[3].component_type, ComponentType::Bjt { area: Some(a), .. } if a == 0.5));
+        assert_eq!(netlist.models["qpnp"].kind(), "PNP");
+
+        assert!(parser.parse_netlist("Bad\nQ1 c b\n.end").is_err());
+        assert!(parser.parse_netlist("Bad\nQ1 c b e qnpn m=2\n.end").is_err());
+    }
+
+    #[test]
+    fn test_jfet_lines() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("\
+JFET preamp
+J1 d g s jn
+J2 d g s jp 2
+.model jn NJF(VTO=-1.5 BETA=2m LAMBDA=0.01 RD=5 RS=5 CGS=3p CGD=1p)
+.model jp PJF
+.end").unwrap();
+
+        assert_eq!(netlist.components[0].nodes, vec!["d", "g", "s"]);
+        assert_eq!(netlist.components[0].component_type, ComponentType::Jfet { model_type: "jn".to_string(), area: None });
+        assert!(matches!(netlist.components[1].component_type, ComponentType::Jfet { area: Some(a), .. } if a == 2.0));
+        assert_eq!(netlist.models["jn"].kind(), "NJF");
+        assert_eq!(netlist.models["jp"].kind(), "PJF");
+
+        assert!(parser.parse_netlist("Bad\nJ1 d g jn\n.end").is_err());
+    }
+
+    #[test]
+    fn test_controlled_sources() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("\
+Dependent sources
+.param av=1e5
+E1 out 0 inp inn {av}
+G1 a 0 c 0 1m
+X1 a b sense
+.subckt sense p n
+Vs p m 0
+H1 n 0 Vs 1k
+Rm m 0 1k
+.ends
+.end").unwrap();
+
+        let e1 = &netlist.components[0];
+        assert_eq!(e1.component_type, ComponentType::Vcvs);
+        assert_eq!(e1.nodes, vec!["out", "0", "inp", "inn"]);
+        assert_eq!(e1.value, 1e5);
+        assert_eq!(netlist.components[1].component_type, ComponentType::Vccs);
+
+        // 子电路内的控制源名字与元件名一起加上层次前缀
+        let h1 = netlist.components.iter().find(|c| c.name == "X1.H1").unwrap();
+        assert_eq!(h1.component_type, ComponentType::Ccvs { control: "X1.Vs".to_string() });
+        assert_eq!(h1.nodes, vec!["b", "0"]);
+
+        assert!(parser.parse_netlist("Bad\nF1 a 0 V1\n.end").is_err());
+        assert!(parser.parse_netlist("Bad\nE1 a 0 b 1\n.end").is_err());
+    }
+
+    #[test]
+    fn test_mutual_inductance() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("\
+Coupled inductors
+L1 a 0 1m
+L2 b 0 1m
+K1 L1 L2 0.9
+X1 c d xfmr
+.subckt xfmr p s
+Lp p 0 1m
+Ls1 s 0 1m
+Ls2 s 0 1m
+Kx Lp Ls1 Ls2 {0.5}
+.ends
+.end").unwrap();
+
+        let k1 = netlist.components.iter().find(|c| c.name == "K1").unwrap();
+        assert_eq!(k1.component_type, ComponentType::MutualInductance { inductors: vec!["L1".to_string(), "L2".to_string()] });
+        assert!(k1.nodes.is_empty());
+        assert_eq!(k1.value, 0.9);
+
+        // 子电路内的电感名加上层次前缀
+        let kx = netlist.components.iter().find(|c| c.name == "X1.Kx").unwrap();
+        assert_eq!(kx.component_type, ComponentType::MutualInductance {
+            inductors: vec!["X1.Lp".to_string(), "X1.Ls1".to_string(), "X1.Ls2".to_string()],
+        });
+
+        assert!(parser.parse_netlist("Bad\nL1 a 0 1m\nK1 L1 0.5\n.end").is_err());
+    }
+
+    #[test]
+    fn test_initial_conditions() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("\
+Charged tank
+.param v0=2
+C1 out 0 1u IC={v0}
+L1 out x 1m ic=10m
+R1 x 0 1
+.ic V(out)=1 v( x ) = {v0/4}
+.nodeset V(out)=0.5
+.tran 1u 1m UIC
+.end").unwrap();
+
+        assert_eq!(netlist.components[0].initial_condition, Some(2.0));
+        assert!((netlist.components[1].initial_condition.unwrap() - 0.01).abs() < 1e-15);
+        assert_eq!(netlist.components[2].initial_condition, None);
+        assert_eq!(netlist.initial_conditions, vec![("out".to_string(), 1.0), ("x".to_string(), 0.5)]);
+        assert_eq!(netlist.nodesets, vec![("out".to_string(), 0.5)]);
+        assert!(matches!(netlist.analyses[0], Analysis::Transient { tstart: None, uic: true, .. }));
+
+        assert!(parser.parse_netlist("Bad\nC1 1 0 1u 2\n.end").is_err());
+        assert!(parser.parse_netlist("Bad\n.ic out=1\n.end").is_err());
+    }
+
+    // 单位后缀由 expression::parse_number 统一解析
     #[test]
     fn test_parse_value_with_unit() {
-        assert_eq!(parse_value_with_unit("1k").unwrap(), 1000.0);
-        assert_eq!(parse_value_with_unit("1.5meg").unwrap(), 1.5e6);
-        assert_eq!(parse_value_with_unit("10m").unwrap(), 10e-3);
-        assert_eq!(parse_value_with_unit("1u").unwrap(), 1e-6);
+        assert_eq!(expression::parse_number("1k").unwrap(), 1000.0);
+        assert_eq!(expression::parse_number("1.5meg").unwrap(), 1.5e6);
+        assert_eq!(expression::parse_number("10m").unwrap(), 10e-3);
+        assert_eq!(expression::parse_number("1u").unwrap(), 1e-6);
     }
 
     #[test]
     fn test_parse_time_with_unit() {
-        assert_eq!(parse_time_with_unit("1ns").unwrap(), 1e-9);
-        assert_eq!(parse_time_with_unit("1.5us").unwrap(), 1.5e-6);
-        assert_eq!(parse_time_with_unit("10ms").unwrap(), 10e-3);
+        assert_eq!(expression::parse_number("1ns").unwrap(), 1e-9);
+        assert_eq!(expression::parse_number("1.5us").unwrap(), 1.5e-6);
+        assert_eq!(expression::parse_number("10ms").unwrap(), 10e-3);
     }
 } 
\ No newline at end of file
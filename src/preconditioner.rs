@@ -0,0 +1,334 @@
+use sprs::CsMat;
+use anyhow::{anyhow, Result};
+use std::collections::BTreeSet;
+
+/// Pivots smaller than this fraction of the norm of their row are replaced by it
+/// (with their sign), so an incomplete factorization cannot break down on a zero pivot
+const PIVOT_FLOOR: f64 = 1e-12;
+
+/// Preconditioner of the iterative solvers
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Preconditioner {
+    None,
+    /// Diagonal scaling
+    Jacobi,
+    /// Incomplete LU without fill: the factors keep the pattern of the matrix
+    Ilu0,
+    /// Incomplete LU with threshold dropping: entries smaller than `drop_tolerance`
+    /// times the norm of their row are dropped, and each row of L and of U keeps at
+    /// most `fill` entries besides the diagonal
+    Ilut { drop_tolerance: f64, fill: usize },
+}
+
+impl Preconditioner {
+    /// Compute the preconditioner of the square matrix `matrix`
+    pub fn build(&self, matrix: &CsMat<f64>) -> Result<PreconditionerFactors> {
+        if matrix.rows() != matrix.cols() {
+            return Err(anyhow!("Matrix must be square"));
+        }
+        let rows = sorted_rows(matrix);
+        Ok(match *self {
+            Preconditioner::None => PreconditionerFactors::Identity,
+            Preconditioner::Jacobi => PreconditionerFactors::Diagonal(
+                rows.iter().enumerate()
+                    .map(|(i, row)| 1.0 / floored_pivot(row_value(row, i), row))
+                    .collect(),
+            ),
+            Preconditioner::Ilu0 => PreconditionerFactors::IncompleteLu(IncompleteLu::ilu0(&rows)),
+            Preconditioner::Ilut { drop_tolerance, fill } => {
+                PreconditionerFactors::IncompleteLu(IncompleteLu::ilut(&rows, drop_tolerance, fill))
+            }
+        })
+    }
+}
+
+/// Preconditioner computed for one matrix M, applied as M^-1
+#[derive(Debug, Clone)]
+pub enum PreconditionerFactors {
+    Identity,
+    /// Inverse of the diagonal
+    Diagonal(Vec<f64>),
+    IncompleteLu(IncompleteLu),
+}
+
+impl PreconditionerFactors {
+    /// M^-1 `vector`
+    pub fn apply(&self, vector: &[f64]) -> Vec<f64> {
+        match self {
+            PreconditionerFactors::Identity => vector.to_vec(),
+            PreconditionerFactors::Diagonal(inverse) => vector.iter().zip(inverse).map(|(v, d)| v * d).collect(),
+            PreconditionerFactors::IncompleteLu(lu) => lu.solve(vector),
+        }
+    }
+}
+
+/// Incomplete factors L U of a matrix, row by row: L unit lower triangular without its
+/// diagonal, U strictly upper triangular with its diagonal kept inverted
+#[derive(Debug, Clone)]
+pub struct IncompleteLu {
+    lower: Rows,
+    upper: Rows,
+    inverse_diagonal: Vec<f64>,
+}
+
+/// Matrix in compressed sparse row form
+#[derive(Debug, Clone, Default)]
+struct Rows {
+    row_ptr: Vec<usize>,
+    col_idx: Vec<usize>,
+    values: Vec<f64>,
+}
+
+impl Rows {
+    fn new() -> Rows {
+        Rows { row_ptr: vec![0], ..Rows::default() }
+    }
+
+    fn push_row(&mut self, entries: impl IntoIterator<Item = (usize, f64)>) {
+        for (col, value) in entries {
+            self.col_idx.push(col);
+            self.values.push(value);
+        }
+        self.row_ptr.push(self.col_idx.len());
+    }
+
+    fn row(&self, row: usize) -> impl Iterator<Item = (usize, f64)> + '_ {
+        (self.row_ptr[row]..self.row_ptr[row + 1]).map(move |p| (self.col_idx[p], self.values[p]))
+    }
+}
+
+impl IncompleteLu {
+    /// ILU(0): Gaussian elimination restricted to the pattern of the matrix
+    fn ilu0(rows: &[Vec<(usize, f64)>]) -> IncompleteLu {
+        let n = rows.len();
+        let mut factored: Vec<Vec<(usize, f64)>> = rows.to_vec();
+        let mut diagonal = vec![0.0; n];
+        let mut position = vec![usize::MAX; n];
+
+        for i in 0..n {
+            for (p, &(col, _)) in factored[i].iter().enumerate() {
+                position[col] = p;
+            }
+            // 行内按列号升序消去，只更新原有结构中的元素
+            for p in 0..factored[i].len() {
+                let (k, value) = factored[i][p];
+                if k >= i {
+                    break;
+                }
+                let multiplier = value / diagonal[k];
+                factored[i][p].1 = multiplier;
+                let (done, current) = factored.split_at_mut(i);
+                for &(j, u) in done[k].iter().filter(|&&(j, _)| j > k) {
+                    if position[j] != usize::MAX {
+                        current[0][position[j]].1 -= multiplier * u;
+                    }
+                }
+            }
+            diagonal[i] = floored_pivot(row_value(&factored[i], i), &rows[i]);
+            for &(col, _) in &factored[i] {
+                position[col] = usize::MAX;
+            }
+        }
+
+        let mut lower = Rows::new();
+        let mut upper = Rows::new();
+        for (i, row) in factored.iter().enumerate() {
+            lower.push_row(row.iter().copied().filter(|&(col, _)| col < i));
+            upper.push_row(row.iter().copied().filter(|&(col, _)| col > i));
+        }
+        IncompleteLu { lower, upper, inverse_diagonal: diagonal.iter().map(|d| 1.0 / d).collect() }
+    }
+
+    /// ILUT(τ, p) after Saad: each row is eliminated in full against the rows above,
+    /// dropping small multipliers on the way, and only the largest entries are kept
+    fn ilut(rows: &[Vec<(usize, f64)>], drop_tolerance: f64, fill: usize) -> IncompleteLu {
+        let n = rows.len();
+        let mut lower = Rows::new();
+        let mut upper = Rows::new();
+        let mut diagonal = vec![0.0; n];
+        let mut work = vec![0.0; n];
+        let mut in_row = vec![false; n];
+        let mut pattern = Vec::new();
+
+        for i in 0..n {
+            let threshold = drop_tolerance * row_norm(&rows[i]);
+            let mut pending = BTreeSet::new();
+            for &(col, value) in &rows[i] {
+                work[col] = value;
+                in_row[col] = true;
+                pattern.push(col);
+                if col < i {
+                    pending.insert(col);
+                }
+            }
+
+            // 按列号升序消去；消去过程中产生的下三角填充元加入待处理集合
+            while let Some(k) = pending.pop_first() {
+                let multiplier = work[k] / diagonal[k];
+                if multiplier.abs() < threshold {
+                    work[k] = 0.0;
+                    continue;
+                }
+                work[k] = multiplier;
+                for (j, u) in upper.row(k) {
+                    if !in_row[j] {
+                        in_row[j] = true;
+                        pattern.push(j);
+                        if j < i {
+                            pending.insert(j);
+                        }
+                    }
+                    work[j] -= multiplier * u;
+                }
+            }
+
+            let keep_largest = |mut entries: Vec<(usize, f64)>| {
+                entries.retain(|&(_, value)| value != 0.0 && value.abs() >= threshold);
+                if entries.len() > fill {
+                    entries.select_nth_unstable_by(fill, |a, b| b.1.abs().total_cmp(&a.1.abs()));
+                    entries.truncate(fill);
+                }
+                entries.sort_unstable_by_key(|&(col, _)| col);
+                entries
+            };
+            let entries: Vec<(usize, f64)> = pattern.iter().map(|&col| (col, work[col])).collect();
+            lower.push_row(keep_largest(entries.iter().copied().filter(|&(col, _)| col < i).collect()));
+            upper.push_row(keep_largest(entries.iter().copied().filter(|&(col, _)| col > i).collect()));
+            diagonal[i] = floored_pivot(work[i], &rows[i]);
+
+            for col in pattern.drain(..) {
+                work[col] = 0.0;
+                in_row[col] = false;
+            }
+        }
+
+        IncompleteLu { lower, upper, inverse_diagonal: diagonal.iter().map(|d| 1.0 / d).collect() }
+    }
+
+    /// Solve L U x = `rhs`
+    pub fn solve(&self, rhs: &[f64]) -> Vec<f64> {
+        let mut x = rhs.to_vec();
+        for i in 0..x.len() {
+            let sum: f64 = self.lower.row(i).map(|(col, value)| value * x[col]).sum();
+            x[i] -= sum;
+        }
+        for i in (0..x.len()).rev() {
+            let sum: f64 = self.upper.row(i).map(|(col, value)| value * x[col]).sum();
+            x[i] = (x[i] - sum) * self.inverse_diagonal[i];
+        }
+        x
+    }
+
+    /// Entries of L and U, the diagonal included
+    pub fn nnz(&self) -> usize {
+        self.lower.col_idx.len() + self.upper.col_idx.len() + self.inverse_diagonal.len()
+    }
+}
+
+/// Rows of `matrix` as `(column, value)` lists sorted by column, duplicates summed
+fn sorted_rows(matrix: &CsMat<f64>) -> Vec<Vec<(usize, f64)>> {
+    let mut rows = vec![Vec::new(); matrix.rows()];
+    for (&value, (row, col)) in matrix.iter() {
+        rows[row].push((col, value));
+    }
+    for row in &mut rows {
+        row.sort_unstable_by_key(|&(col, _)| col);
+        row.dedup_by(|next, kept| {
+            if next.0 == kept.0 {
+                kept.1 += next.1;
+                true
+            } else {
+                false
+            }
+        });
+    }
+    rows
+}
+
+fn row_value(row: &[(usize, f64)], col: usize) -> f64 {
+    row.binary_search_by_key(&col, |&(c, _)| c).map_or(0.0, |p| row[p].1)
+}
+
+fn row_norm(row: &[(usize, f64)]) -> f64 {
+    row.iter().map(|&(_, value)| value * value).sum::<f64>().sqrt()
+}
+
+/// `pivot`, or the pivot floor of `row` with the sign of `pivot` if it is smaller
+fn floored_pivot(pivot: f64, row: &[(usize, f64)]) -> f64 {
+    let floor = PIVOT_FLOOR * row_norm(row).max(f64::MIN_POSITIVE);
+    if pivot.abs() >= floor {
+        pivot
+    } else if pivot < 0.0 {
+        -floor
+    } else {
+        floor
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use sprs::TriMat;
+
+    fn tridiagonal(n: usize) -> CsMat<f64> {
+        let mut triplets = TriMat::new((n, n));
+        for i in 0..n {
+            triplets.add_triplet(i, i, 4.0);
+            if i > 0 {
+                triplets.add_triplet(i, i - 1, -1.0);
+                triplets.add_triplet(i - 1, i, -2.0);
+            }
+        }
+        triplets.to_csr()
+    }
+
+    fn multiply(matrix: &CsMat<f64>, x: &[f64]) -> Vec<f64> {
+        let mut result = vec![0.0; matrix.rows()];
+        for (&value, (row, col)) in matrix.iter() {
+            result[row] += value * x[col];
+        }
+        result
+    }
+
+    #[test]
+    fn test_incomplete_factors_of_tridiagonal_are_exact() {
+        // 三对角矩阵消去时没有填充元，ILU(0) 与 ILUT 都是精确分解
+        let matrix = tridiagonal(20);
+        let x: Vec<f64> = (0..20).map(|i| (i as f64).sin()).collect();
+        let b = multiply(&matrix, &x);
+        for kind in [Preconditioner::Ilu0, Preconditioner::Ilut { drop_tolerance: 0.0, fill: 5 }] {
+            let solution = kind.build(&matrix).unwrap().apply(&b);
+            for (value, expected) in solution.iter().zip(&x) {
+                assert!((value - expected).abs() < 1e-12, "{:?}: {} vs {}", kind, value, expected);
+            }
+        }
+
+        let jacobi = Preconditioner::Jacobi.build(&matrix).unwrap().apply(&[4.0, 8.0]);
+        assert_eq!(jacobi, vec![1.0, 2.0]);
+    }
+
+    #[test]
+    fn test_ilut_limits_fill() {
+        // 箭头矩阵的中心节点排在最前，精确分解会填满；每行最多保留 fill 个元素
+        let n = 30;
+        let mut triplets = TriMat::new((n, n));
+        triplets.add_triplet(0, 0, n as f64);
+        for i in 1..n {
+            triplets.add_triplet(i, i, 3.0);
+            triplets.add_triplet(0, i, 1.0);
+            triplets.add_triplet(i, 0, 1.0);
+        }
+        let matrix = triplets.to_csr();
+        let PreconditionerFactors::IncompleteLu(lu) = (Preconditioner::Ilut { drop_tolerance: 0.0, fill: 2 }).build(&matrix).unwrap() else {
+            panic!("ILUT builds incomplete factors");
+        };
+        assert!(lu.nnz() <= n + 4 * n);
+        assert!(lu.nnz() < n * n / 2);
+
+        // ILU(0) 不产生填充
+        let PreconditionerFactors::IncompleteLu(lu0) = Preconditioner::Ilu0.build(&matrix).unwrap() else {
+            panic!("ILU(0) builds incomplete factors");
+        };
+        assert_eq!(lu0.nnz(), matrix.nnz());
+    }
+}
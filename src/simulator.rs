@@ -1,14 +1,18 @@
 use std::collections::HashMap;
+use std::ops::Range;
 use nalgebra::DVector;
 use anyhow::{anyhow, Result};
 use log::{info, warn, debug};
+use num_complex::Complex64;
 use serde::{Deserialize, Serialize};
 
-use crate::circuit::Circuit;
-use crate::parser::{SpiceParser, SpiceNetlist};
-use crate::mna::MnaSystem;
+use crate::circuit::{Circuit, ComponentType};
+use crate::parser::{Analysis, SpiceParser, SpiceNetlist};
+use crate::integration::{IntegrationMethod, TruncationTolerances, MAX_GEAR_ORDER};
+use crate::mna::{ChargeStep, MnaSystem};
 use crate::solver::{LinearSolver, SolverConfig, auto_select_solver};
 use crate::cli::OutputFormat;
+use crate::sweep::Sweep;
 
 /// Simulation results container
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -20,21 +24,120 @@ pub struct SimulationResult {
     pub convergence_info: Vec<ConvergenceInfo>,
     pub total_time: f64,
     pub success: bool,
+    /// Value of the outer sweep variable at each point of a nested DC sweep (empty otherwise)
+    #[serde(default)]
+    pub outer_sweep_values: Vec<f64>,
+    /// Complex node voltages at each frequency of an AC analysis (empty otherwise)
+    #[serde(default)]
+    pub ac_node_voltages: HashMap<String, Vec<Complex64>>,
+    /// Complex branch currents at each frequency of an AC analysis (empty otherwise)
+    #[serde(default)]
+    pub ac_currents: HashMap<String, Vec<Complex64>>,
+}
+
+impl SimulationResult {
+    /// Split the points into curves, one per outer sweep value.
+    /// Results without an outer sweep form a single curve.
+    pub fn curves(&self) -> Vec<(Option<f64>, Range<usize>)> {
+        if self.outer_sweep_values.is_empty() {
+            return vec![(None, 0..self.time_points.len())];
+        }
+
+        let mut curves: Vec<(Option<f64>, Range<usize>)> = Vec::new();
+        for (i, &value) in self.outer_sweep_values.iter().enumerate() {
+            match curves.last_mut() {
+                Some((Some(current), range)) if *current == value && range.end == i => range.end = i + 1,
+                _ => curves.push((Some(value), i..i + 1)),
+            }
+        }
+        curves
+    }
+
+    /// Names of the x axis and, for nested DC sweeps, the outer sweep variable
+    pub fn axis_names(&self) -> (String, Option<String>) {
+        match &self.analysis_type {
+            AnalysisType::DcSweep { sweep, outer } => {
+                (sweep.name.clone(), outer.as_ref().map(|o| o.name.clone()))
+            }
+            AnalysisType::Ac { .. } => ("frequency".to_string(), None),
+            _ => ("time".to_string(), None),
+        }
+    }
+
+    /// Magnitude and phase (in degrees) of the AC results as columns named
+    /// VM(node), VP(node), IM(branch) and IP(branch) like SPICE does, sorted by name
+    pub fn ac_columns(&self) -> Vec<(String, Vec<f64>)> {
+        let mut columns = Vec::new();
+        for (prefix, signals) in [("V", &self.ac_node_voltages), ("I", &self.ac_currents)] {
+            let mut names: Vec<&String> = signals.keys().collect();
+            names.sort();
+            for name in names {
+                let values = &signals[name];
+                columns.push((format!("{}M({})", prefix, name), values.iter().map(|v| v.norm()).collect()));
+                columns.push((format!("{}P({})", prefix, name), values.iter().map(|v| v.arg().to_degrees()).collect()));
+            }
+        }
+        columns
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum AnalysisType {
     Operating,
-    DcSweep { parameter: String, start: f64, stop: f64, step: f64 },
+    DcSweep { sweep: Sweep, outer: Option<Sweep> },
     Transient { tstep: f64, tstop: f64 },
+    /// Small-signal AC analysis over the frequencies of `sweep`
+    Ac { sweep: Sweep },
+}
+
+/// Settings of a transient analysis, as on a `.TRAN tstep tstop [tstart [tmax]] [UIC]` card
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TransientSpec {
+    pub tstep: f64,
+    pub tstop: f64,
+    /// Results before this time are not reported
+    pub tstart: f64,
+    /// Largest internal time step; the configured TMAX when `None`
+    pub tmax: Option<f64>,
+    /// Skip the operating point and start from the initial conditions
+    pub uic: bool,
 }
 
+impl TransientSpec {
+    /// Analysis from 0 to `tstop` starting at the operating point, reporting every point
+    pub fn new(tstep: f64, tstop: f64) -> Self {
+        TransientSpec { tstep, tstop, tstart: 0.0, tmax: None, uic: false }
+    }
+}
+
+/// Record of one Newton-Raphson iteration
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConvergenceInfo {
+    /// Newton iteration within its solution point, starting at 0
     pub iteration: usize,
     pub residual_norm: f64,
     pub solve_time: f64,
     pub solver_method: String,
+    /// Index of the sweep or time point the iteration belongs to
+    #[serde(default)]
+    pub point: usize,
+    /// Largest change of any unknown relative to the previous iterate
+    #[serde(default)]
+    pub max_change: f64,
+    /// Whether a junction voltage had to be limited in this iteration
+    #[serde(default)]
+    pub limited: bool,
+    /// Whether the solution point converged with this iteration
+    #[serde(default)]
+    pub converged: bool,
+}
+
+/// Outcome of a Newton-Raphson solve
+struct NewtonSolution {
+    solution: Vec<f64>,
+    iterations: Vec<ConvergenceInfo>,
+    /// Every linear solve met the solver's residual tolerance
+    success: bool,
 }
 
 /// Main simulator engine
@@ -42,15 +145,37 @@ pub struct Simulator {
     circuit: Option<Circuit>,
     mna_system: Option<MnaSystem>,
     solver: LinearSolver,
-    results: Option<SimulationResult>,
+    /// Results of the analyses run since the netlist was loaded, in order
+    results: Vec<SimulationResult>,
+    /// Analyses declared in the loaded netlist
+    analyses: Vec<Analysis>,
     config: SimulatorConfig,
 }
 
 #[derive(Debug, Clone)]
 pub struct SimulatorConfig {
     pub solver_config: SolverConfig,
+    /// Newton-Raphson iteration limit per solution point
     pub max_iterations: usize,
-    pub convergence_tolerance: f64,
+    /// Relative tolerance on voltages and currents (SPICE RELTOL)
+    pub reltol: f64,
+    /// Absolute tolerance on branch currents (SPICE ABSTOL)
+    pub abstol: f64,
+    /// Absolute tolerance on node voltages (SPICE VNTOL)
+    pub vntol: f64,
+    /// Integration method of transient analysis (SPICE METHOD)
+    pub integration_method: IntegrationMethod,
+    /// Highest order of the Gear formulas (SPICE MAXORD)
+    pub max_order: usize,
+    /// Factor by which the local truncation error may exceed the Newton tolerances (SPICE TRTOL)
+    pub trtol: f64,
+    /// Absolute tolerance on charges in the truncation error test (SPICE CHGTOL)
+    pub chgtol: f64,
+    /// Largest internal time step (SPICE TMAX); 1/50 of the simulated time when `None`
+    pub tmax: Option<f64>,
+    /// Interpolate transient results onto the `tstep` grid instead of reporting
+    /// every accepted time point (SPICE INTERP)
+    pub interpolate: bool,
     pub auto_select_solver: bool,
     pub store_intermediate_results: bool,
 }
@@ -59,8 +184,16 @@ impl Default for SimulatorConfig {
     fn default() -> Self {
         SimulatorConfig {
             solver_config: SolverConfig::default(),
-            max_iterations: 50,
-            convergence_tolerance: 1e-9,
+            max_iterations: 100,
+            reltol: 1e-3,
+            abstol: 1e-12,
+            vntol: 1e-6,
+            integration_method: IntegrationMethod::Trapezoidal,
+            max_order: 2,
+            trtol: 7.0,
+            chgtol: 1e-14,
+            tmax: None,
+            interpolate: false,
             auto_select_solver: true,
             store_intermediate_results: false,
         }
@@ -74,7 +207,8 @@ impl Simulator {
             circuit: None,
             mna_system: None,
             solver: LinearSolver::new(),
-            results: None,
+            results: Vec::new(),
+            analyses: Vec::new(),
             config: SimulatorConfig::default(),
         }
     }
@@ -86,7 +220,8 @@ impl Simulator {
             circuit: None,
             mna_system: None,
             solver,
-            results: None,
+            results: Vec::new(),
+            analyses: Vec::new(),
             config,
         }
     }
@@ -103,8 +238,16 @@ impl Simulator {
 
     /// Load from parsed SPICE netlist
     pub fn load_netlist_from_parsed(&mut self, netlist: SpiceNetlist) -> Result<()> {
+        self.apply_options(&netlist.options)?;
+
         // Convert SpiceNetlist to Circuit
         let mut circuit = Circuit::new(netlist.title);
+        circuit.models = netlist.models;
+        if let Some(temperature) = netlist.temperature {
+            circuit.temperature = temperature;
+        }
+        circuit.initial_conditions = netlist.initial_conditions.into_iter().collect();
+        circuit.nodesets = netlist.nodesets.into_iter().collect();
         
         // Add all components
         for component in netlist.components {
@@ -122,10 +265,71 @@ impl Simulator {
         
         self.circuit = Some(circuit);
         self.mna_system = Some(mna_system);
+        self.analyses = netlist.analyses;
+        self.results.clear();
+        self.solver.reset_factorization();
         
         Ok(())
     }
 
+    /// Analyses declared in the loaded netlist (`.OP`, `.TRAN`, `.DC`, `.AC`), in order
+    pub fn analyses(&self) -> &[Analysis] {
+        &self.analyses
+    }
+
+    /// Run every analysis declared in the netlist in order, each adding its own result
+    pub fn run_netlist_analyses(&mut self) -> Result<()> {
+        if self.analyses.is_empty() {
+            return Err(anyhow!("The netlist declares no analysis"));
+        }
+        for analysis in self.analyses.clone() {
+            self.run_analysis(&analysis)?;
+        }
+        Ok(())
+    }
+
+    /// Run one analysis as declared on its netlist card
+    pub fn run_analysis(&mut self, analysis: &Analysis) -> Result<()> {
+        match analysis {
+            Analysis::Operating => self.run_operating_point(),
+            &Analysis::Transient { tstep, tstop, tstart, tmax, uic } => {
+                self.run_transient(&TransientSpec { tstep, tstop, tstart: tstart.unwrap_or(0.0), tmax, uic })
+            }
+            Analysis::DcSweep { sweep, outer } => self.run_dc_analysis(sweep, outer.as_ref()),
+            Analysis::Ac { points, fstart, fstop, sweep_type } => {
+                self.run_ac_analysis(&Sweep::frequency(sweep_type, *points, *fstart, *fstop)?)
+            }
+        }
+    }
+
+    /// Apply the `.OPTIONS` settings of a netlist to the configuration.
+    /// Options the simulator does not know are ignored with a warning, as in SPICE.
+    fn apply_options(&mut self, options: &[(String, Option<String>)]) -> Result<()> {
+        for (name, value) in options {
+            let text = || value.as_deref().ok_or_else(|| anyhow!(".OPTIONS {} needs a value", name.to_uppercase()));
+            let number = || text().and_then(crate::expression::parse_number)
+                .map_err(|e| anyhow!(".OPTIONS {}: {}", name.to_uppercase(), e));
+            match name.as_str() {
+                "method" => self.config.integration_method = IntegrationMethod::from_name(text()?)?,
+                "maxord" => {
+                    let order = number()?;
+                    if !(1.0..=MAX_GEAR_ORDER as f64).contains(&order) {
+                        return Err(anyhow!("MAXORD must be between 1 and {}", MAX_GEAR_ORDER));
+                    }
+                    self.config.max_order = order as usize;
+                }
+                "reltol" => self.config.reltol = number()?,
+                "abstol" => self.config.abstol = number()?,
+                "vntol" => self.config.vntol = number()?,
+                "trtol" => self.config.trtol = number()?,
+                "chgtol" => self.config.chgtol = number()?,
+                "interp" => self.config.interpolate = true,
+                other => warn!("Ignoring unsupported option {}", other.to_uppercase()),
+            }
+        }
+        Ok(())
+    }
+
     /// Run operating point analysis
     pub fn run_operating_point(&mut self) -> Result<()> {
         info!("Starting operating point analysis");
@@ -139,22 +343,18 @@ impl Simulator {
         mna_system.assemble_dc(circuit)?;
         
         // Auto-select solver if enabled
+        // 只更换求解方法，保留已有的 LU 分解
         if self.config.auto_select_solver {
-            let (sparse_matrix, _) = mna_system.to_sparse();
-            let optimal_method = auto_select_solver(&sparse_matrix);
-            self.solver = LinearSolver::with_config(SolverConfig {
-                method: optimal_method,
-                ..self.config.solver_config.clone()
-            });
+            let (sparse_matrix, _) = mna_system.as_sparse();
+            self.solver.set_method(auto_select_solver(sparse_matrix));
         }
 
         // Solve the system
         let start_time = std::time::Instant::now();
-        let (sparse_matrix, rhs) = mna_system.to_sparse();
-        let (solution, solver_stats) = self.solver.solve_sparse(&sparse_matrix, &rhs)?;
-        
-        // Update MNA system with solution
-        mna_system.update_solution(&solution)?;
+        let newton = self.solve_operating_point(&mut mna_system, circuit, None, &HashMap::new());
+        self.mna_system = Some(mna_system);
+        let newton = newton?;
+        let mna_system = self.mna_system.as_ref().unwrap();
         
         // Store results
         let mut node_voltages = HashMap::new();
@@ -164,29 +364,24 @@ impl Simulator {
         }
 
         let mut currents = HashMap::new();
-        for vs in circuit.voltage_sources() {
+        for vs in circuit.branch_components() {
             let current = mna_system.get_voltage_source_current(&vs.name)?;
             currents.insert(vs.name.clone(), vec![current]);
         }
 
-        let convergence_info = vec![ConvergenceInfo {
-            iteration: 0,
-            residual_norm: solver_stats.residual_norm,
-            solve_time: solver_stats.solve_time,
-            solver_method: format!("{:?}", solver_stats.method_used),
-        }];
-
-        self.results = Some(SimulationResult {
+        self.results.push(SimulationResult {
             analysis_type: AnalysisType::Operating,
             time_points: vec![0.0],
             node_voltages,
             currents,
-            convergence_info,
+            convergence_info: newton.iterations,
             total_time: start_time.elapsed().as_secs_f64(),
-            success: solver_stats.success,
+            success: newton.success,
+            outer_sweep_values: Vec::new(),
+            ac_node_voltages: HashMap::new(),
+            ac_currents: HashMap::new(),
         });
 
-        self.mna_system = Some(mna_system);
         
         info!("Operating point analysis completed in {:.3}ms", 
               start_time.elapsed().as_millis());
@@ -195,52 +390,107 @@ impl Simulator {
     }
 
     /// Run DC sweep analysis
+    ///
+    /// The named independent source (or resistor) takes each sweep value in turn,
+    /// and its original value is restored afterwards. `stop` may be below `start`;
+    /// the sweep direction follows `start -> stop` and only the step magnitude is used.
     pub fn run_dc_sweep(&mut self, source_name: &str, start: f64, stop: f64, step: f64) -> Result<()> {
-        info!("Starting DC sweep analysis: {} from {} to {} step {}", 
-              source_name, start, stop, step);
+        self.run_dc_analysis(&Sweep::linear(source_name, start, stop, step), None)
+    }
+
+    /// Run a DC sweep of `sweep`, repeated for every value of `outer` when given
+    /// (e.g. drain voltage inside gate voltage for a family of transistor curves)
+    pub fn run_dc_analysis(&mut self, sweep: &Sweep, outer: Option<&Sweep>) -> Result<()> {
+        info!("Starting DC sweep analysis: {:?}{}", sweep,
+              outer.map(|o| format!(" in {:?}", o)).unwrap_or_default());
         
-        let circuit = self.circuit.as_ref()
+        let mut circuit = self.circuit.take()
             .ok_or_else(|| anyhow!("No circuit loaded"))?;
+        let result = self.sweep_circuit(&mut circuit, sweep, outer);
+        self.circuit = Some(circuit);
+        result
+    }
+
+    /// Resolve the swept components, run the sweep and restore their original values
+    fn sweep_circuit(&mut self, circuit: &mut Circuit, sweep: &Sweep, outer: Option<&Sweep>) -> Result<()> {
+        let inner_index = find_sweep_target(circuit, &sweep.name)?;
+        let outer_index = outer.map(|o| find_sweep_target(circuit, &o.name)).transpose()?;
+
+        // 外层扫描变量变化最慢
+        let inner_values = sweep.values()?;
+        let outer_values = match outer {
+            Some(outer) => outer.values()?.into_iter().map(Some).collect(),
+            None => vec![None],
+        };
+        let mut points = Vec::new();
+        for &outer_value in &outer_values {
+            for &inner_value in &inner_values {
+                points.push((outer_value, inner_value));
+            }
+        }
+
         let mut mna_system = self.mna_system.take()
             .ok_or_else(|| anyhow!("No MNA system available"))?;
+        let originals: Vec<(usize, f64)> = [Some(inner_index), outer_index].into_iter()
+            .flatten()
+            .map(|index| (index, circuit.components[index].value))
+            .collect();
 
-        // Find the source component
-        let source_component = circuit.components.iter()
-            .find(|comp| comp.name == source_name)
-            .ok_or_else(|| anyhow!("Source component '{}' not found", source_name))?;
+        let result = self.solve_sweep_points(&mut mna_system, circuit, inner_index, outer_index, &points);
 
-        let _original_value = source_component.value;
-        let num_points = ((stop - start) / step).abs() as usize + 1;
+        for &(index, value) in originals.iter().rev() {
+            circuit.components[index].value = value;
+        }
+        self.mna_system = Some(mna_system);
+
+        let mut results = result?;
+        results.analysis_type = AnalysisType::DcSweep { sweep: sweep.clone(), outer: outer.cloned() };
+        info!("DC sweep analysis completed with {} points", results.time_points.len());
+        self.results.push(results);
+        Ok(())
+    }
+
+    /// Solve the DC operating point at each `(outer, inner)` sweep point
+    fn solve_sweep_points(&mut self, mna_system: &mut MnaSystem, circuit: &mut Circuit, inner_index: usize,
+                          outer_index: Option<usize>, points: &[(Option<f64>, f64)]) -> Result<SimulationResult> {
+        let start_time = std::time::Instant::now();
         
         let mut sweep_points = Vec::new();
+        let mut outer_sweep_values = Vec::new();
         let mut all_node_voltages: HashMap<String, Vec<f64>> = HashMap::new();
         let mut all_currents: HashMap<String, Vec<f64>> = HashMap::new();
         let mut convergence_info = Vec::new();
+        let mut success = true;
 
         // Initialize vectors for each node
         for node in &circuit.nodes {
             all_node_voltages.insert(node.name.clone(), Vec::new());
         }
-        for vs in circuit.voltage_sources() {
+        for vs in circuit.branch_components() {
             all_currents.insert(vs.name.clone(), Vec::new());
         }
 
-        for i in 0..num_points {
-            let sweep_value = start + i as f64 * step;
-            sweep_points.push(sweep_value);
+        // 每个扫描点以上一个点的解作为初值
+        let mut previous_solution: Option<Vec<f64>> = None;
 
-            // Update the source value (this is simplified - in a real implementation,
-            // you'd need to modify the circuit or MNA system directly)
-            debug!("DC sweep point {}: {} = {}", i, source_name, sweep_value);
+        for (i, &(outer_value, sweep_value)) in points.iter().enumerate() {
+            let assignments = outer_index.zip(outer_value).into_iter()
+                .chain(std::iter::once((inner_index, sweep_value)));
+            for (index, value) in assignments {
+                let component = &mut circuit.components[index];
+                debug!("DC sweep point {}: {} = {}", i, component.name, value);
+                component.value = value;
+                component.validate()
+                    .map_err(|e| anyhow!("Invalid sweep value {} for {}: {}", value, component.name, e))?;
+            }
 
-            // For now, we'll solve with the original circuit and note this limitation
-            mna_system.assemble_dc(circuit)?;
-            
             // Solve the system
-            let (sparse_matrix, rhs) = mna_system.to_sparse();
-            let (solution, solver_stats) = self.solver.solve_sparse(&sparse_matrix, &rhs)?;
-            
-            mna_system.update_solution(&solution)?;
+            let newton = self.solve_newton(mna_system, circuit, i, previous_solution.as_deref(), None, |mna| mna.assemble_dc(circuit))
+                .map_err(|e| anyhow!("DC sweep point {}: {}", i, e))?;
+            previous_solution = Some(newton.solution);
+
+            sweep_points.push(sweep_value);
+            outer_sweep_values.extend(outer_value);
 
             // Store results for this sweep point
             for node in &circuit.nodes {
@@ -248,152 +498,401 @@ impl Simulator {
                 all_node_voltages.get_mut(&node.name).unwrap().push(voltage);
             }
 
-            for vs in circuit.voltage_sources() {
+            for vs in circuit.branch_components() {
                 let current = mna_system.get_voltage_source_current(&vs.name)?;
                 all_currents.get_mut(&vs.name).unwrap().push(current);
             }
 
-            convergence_info.push(ConvergenceInfo {
-                iteration: i,
-                residual_norm: solver_stats.residual_norm,
-                solve_time: solver_stats.solve_time,
-                solver_method: format!("{:?}", solver_stats.method_used),
-            });
+            if !newton.success {
+                warn!("Convergence issue at DC sweep point {}", i);
+                success = false;
+            }
+
+            convergence_info.extend(newton.iterations);
         }
 
-        let start_time = std::time::Instant::now();
-        self.results = Some(SimulationResult {
-            analysis_type: AnalysisType::DcSweep { 
-                parameter: source_name.to_string(), 
-                start, 
-                stop, 
-                step 
-            },
+        Ok(SimulationResult {
+            analysis_type: AnalysisType::Operating,
             time_points: sweep_points,
             node_voltages: all_node_voltages,
             currents: all_currents,
             convergence_info,
             total_time: start_time.elapsed().as_secs_f64(),
-            success: true,
-        });
-
-        self.mna_system = Some(mna_system);
-        
-        info!("DC sweep analysis completed with {} points", num_points);
-        
-        Ok(())
+            success,
+            outer_sweep_values,
+            ac_node_voltages: HashMap::new(),
+            ac_currents: HashMap::new(),
+        })
     }
 
-    /// Run transient analysis
+    /// Run transient analysis from 0 to `tstop`, starting from the DC operating point
     pub fn run_transient_analysis(&mut self, tstep: f64, tstop: f64) -> Result<()> {
-        info!("Starting transient analysis: tstep={}, tstop={}", tstep, tstop);
+        self.run_transient(&TransientSpec::new(tstep, tstop))
+    }
+
+    /// Run the transient analysis `spec` from 0 to `tstop`. The internal time step is chosen from
+    /// the local truncation error of the charges and fluxes, bounded by TMAX, and shrunk
+    /// when a Newton solve fails. Time points land exactly on the breakpoints of the
+    /// source waveforms, where the integration restarts with a short step. `tstep` only
+    /// sets the output grid the results are interpolated onto when `interpolate` is set;
+    /// otherwise every accepted point is kept. Points before `tstart` are dropped.
+    ///
+    /// The analysis starts from the DC operating point with the `.IC` nodes held at their
+    /// voltages. With `uic` (`.TRAN ... UIC`) the operating point is skipped: the `.IC`
+    /// voltages and the `IC=` values of the capacitors and inductors are used directly,
+    /// everything else starts at zero.
+    pub fn run_transient(&mut self, spec: &TransientSpec) -> Result<()> {
+        let &TransientSpec { tstep, tstop, tstart, tmax, uic } = spec;
+        info!("Starting transient analysis: tstep={}, tstop={}{}", tstep, tstop, if uic { ", UIC" } else { "" });
+        if tstep <= 0.0 || tstop <= 0.0 {
+            return Err(anyhow!("Transient analysis needs a positive time step and stop time"));
+        }
+        if !(0.0..tstop).contains(&tstart) {
+            return Err(anyhow!("Transient start time {} is not between 0 and the stop time {}", tstart, tstop));
+        }
         
         let circuit = self.circuit.as_ref()
             .ok_or_else(|| anyhow!("No circuit loaded"))?;
         let mut mna_system = self.mna_system.take()
             .ok_or_else(|| anyhow!("No MNA system available"))?;
 
-        let num_steps = (tstop / tstep) as usize + 1;
-        let mut time_points = Vec::new();
-        let mut all_node_voltages: HashMap<String, Vec<f64>> = HashMap::new();
-        let mut all_currents: HashMap<String, Vec<f64>> = HashMap::new();
+        let start_time = std::time::Instant::now();
+        let tolerances = TruncationTolerances {
+            trtol: self.config.trtol,
+            reltol: self.config.reltol,
+            abstol: self.config.abstol,
+            chgtol: self.config.chgtol,
+        };
+        // SPICE 的默认值：TMAX 为仿真时长的 1/50，初始步长为 min(tstep, tstop/100)/10
+        let tmax = tmax.or(self.config.tmax).unwrap_or(tstop / 50.0).min(tstop);
+        let min_step = 1e-11 * tmax;
+        let mut timestep = tstep.min(tstop / 100.0).min(tmax) / 10.0;
+
+        // 初始解：UIC 时直接取初始条件，否则为 t=0 时的直流工作点
         let mut convergence_info = Vec::new();
+        let mut success = true;
+        mna_system.start_transient(0.0);
+        let initial = if uic {
+            Ok(initial_condition_solution(&mut mna_system, circuit))
+        } else {
+            self.solve_operating_point(&mut mna_system, circuit, Some(0.0), &circuit.initial_conditions)
+                .map_err(|e| anyhow!("Transient operating point: {}", e))
+                .map(|newton| {
+                    success &= newton.success;
+                    convergence_info.extend(newton.iterations);
+                    DVector::from_vec(newton.solution)
+                })
+        };
+        let mut prev_solution = match initial {
+            Ok(solution) => solution,
+            Err(e) => {
+                self.mna_system = Some(mna_system);
+                return Err(e);
+            }
+        };
+        let mut time_points = vec![0.0];
+        let mut solutions = vec![prev_solution.clone()];
 
-        // Initialize vectors
-        for node in &circuit.nodes {
-            all_node_voltages.insert(node.name.clone(), Vec::new());
-        }
-        for vs in circuit.voltage_sources() {
-            all_currents.insert(vs.name.clone(), Vec::new());
+        // 源波形的拐点和终点都必须恰好落在时间点上；离当前时刻或终点不到最小步长的拐点不再单独取点
+        let next_breakpoint = |time: f64| {
+            circuit.next_breakpoint(time + min_step).filter(|&breakpoint| breakpoint < tstop - min_step).unwrap_or(tstop)
+        };
+        // 起点也视作拐点：第一步不超过到下一个拐点距离的 1/10
+        timestep = timestep.min(0.1 * next_breakpoint(0.0));
+
+        let mut time = 0.0;
+        while time < tstop {
+            let target = next_breakpoint(time);
+            // 不越过拐点，也不在拐点前留下极小的一步
+            if time + 1.5 * timestep > target && time + timestep < target {
+                timestep = (target - time) / 2.0;
+            }
+            let next_time = if time + timestep >= target { target } else { time + timestep };
+            timestep = next_time - time;
+            debug!("Transient step {}: t = {:.6e}s, dt = {:.3e}s", time_points.len(), next_time, timestep);
+
+            // Assemble and solve the system for this time step, starting from the previous one
+            let guess = prev_solution.as_slice().to_vec();
+            let integrator = mna_system.integrator(self.config.integration_method, self.config.max_order, next_time)?;
+            let charge_step = ChargeStep { integrator, prev_solution: &prev_solution };
+            let newton = self.solve_newton(&mut mna_system, circuit, time_points.len(), Some(&guess), Some(charge_step), |mna| {
+                mna.assemble_transient(circuit, next_time, charge_step)
+            });
+
+            // Newton 不收敛时缩小步长重试
+            let newton = match newton {
+                Ok(newton) => newton,
+                Err(e) => {
+                    mna_system.reject_time_point();
+                    timestep /= 8.0;
+                    if timestep < min_step {
+                        self.mna_system = Some(mna_system);
+                        return Err(anyhow!("Transient step at t = {}: {} (time step too small)", next_time, e));
+                    }
+                    debug!("Newton failed at t = {:.6e}s, retrying with dt = {:.3e}s: {}", next_time, timestep, e);
+                    continue;
+                }
+            };
+            convergence_info.extend(newton.iterations);
+            mna_system.record_charges(circuit, charge_step)?;
+
+            // 截断误差超出容限时拒绝这一步，用误差估计的步长重算
+            let proposed = mna_system.truncation_timestep(&integrator, next_time, &tolerances);
+            if let Some(proposed) = proposed.filter(|&proposed| proposed < 0.9 * timestep && timestep > min_step) {
+                mna_system.reject_time_point();
+                mna_system.update_solution(prev_solution.as_slice())?;
+                timestep = proposed.max(min_step);
+                debug!("Truncation error too large at t = {:.6e}s, retrying with dt = {:.3e}s", next_time, timestep);
+                continue;
+            }
+            mna_system.accept_time_point(next_time);
+
+            if !newton.success {
+                warn!("Convergence issue at t = {:.6}s", next_time);
+                success = false;
+            }
+
+            // Keep the solution (including inductor currents) for the next step
+            prev_solution = mna_system.unknowns.clone();
+            time = next_time;
+            time_points.push(time);
+            solutions.push(prev_solution.clone());
+
+            // 步长最多翻倍，且不超过 TMAX
+            timestep = proposed.unwrap_or(timestep).min(2.0 * timestep).min(tmax);
+
+            // 落在拐点上：重新起步积分，并把下一步截短到与下一个拐点间距的 1/10
+            if time == target && time < tstop {
+                mna_system.restart_integration();
+                timestep = timestep.min(0.1 * (next_breakpoint(time) - time));
+            }
         }
+        let steps = time_points.len() - 1;
 
-        // Initial conditions (t=0, all voltages and currents are zero)
-        let mut prev_voltages = DVector::zeros(mna_system.num_nodes);
-        time_points.push(0.0);
+        // 按需插值到 tstep 输出网格，tstart 之前的点不输出
+        if self.config.interpolate {
+            let count = (tstop / tstep * (1.0 + 1e-9)).floor() as usize;
+            let grid: Vec<f64> = (0..=count).map(|i| i as f64 * tstep).filter(|&t| t >= tstart).collect();
+            solutions = grid.iter().map(|&t| interpolate_solution(&time_points, &solutions, t)).collect();
+            time_points = grid;
+        } else {
+            let first = time_points.partition_point(|&t| t < tstart);
+            time_points.drain(..first);
+            solutions.drain(..first);
+        }
 
-        // Store initial conditions
+        let mut all_node_voltages: HashMap<String, Vec<f64>> = HashMap::new();
         for node in &circuit.nodes {
-            all_node_voltages.get_mut(&node.name).unwrap().push(0.0);
+            let voltages = match mna_system.node_map.get(&node.id) {
+                Some(&index) => solutions.iter().map(|solution| solution[index]).collect(),
+                None => vec![0.0; solutions.len()],
+            };
+            all_node_voltages.insert(node.name.clone(), voltages);
         }
-        for vs in circuit.voltage_sources() {
-            all_currents.get_mut(&vs.name).unwrap().push(0.0);
+        let mut all_currents: HashMap<String, Vec<f64>> = HashMap::new();
+        for vs in circuit.branch_components() {
+            let index = mna_system.voltage_source_map[&vs.name];
+            all_currents.insert(vs.name.clone(), solutions.iter().map(|solution| solution[index]).collect());
         }
 
-        // Time stepping loop
-        for step in 1..num_steps {
-            let current_time = step as f64 * tstep;
-            time_points.push(current_time);
+        self.results.push(SimulationResult {
+            analysis_type: AnalysisType::Transient { tstep, tstop },
+            time_points,
+            node_voltages: all_node_voltages,
+            currents: all_currents,
+            convergence_info,
+            total_time: start_time.elapsed().as_secs_f64(),
+            success,
+            outer_sweep_values: Vec::new(),
+            ac_node_voltages: HashMap::new(),
+            ac_currents: HashMap::new(),
+        });
 
-            debug!("Transient step {}: t = {:.6}s", step, current_time);
+        self.mna_system = Some(mna_system);
+        
+        info!("Transient analysis completed with {} time steps", steps);
+        
+        Ok(())
+    }
 
-            // Assemble system for this time step
-            mna_system.assemble_transient(circuit, tstep, &prev_voltages)?;
+    /// Run small-signal AC analysis at the frequencies of `sweep`. The nonlinear devices
+    /// are linearized at the DC operating point and the complex system (G + jωC)x = b,
+    /// driven by the AC magnitude and phase of the sources, is solved per frequency.
+    pub fn run_ac_analysis(&mut self, sweep: &Sweep) -> Result<()> {
+        info!("Starting AC analysis");
+        let frequencies = sweep.values()?;
 
-            // Solve the system
-            let (sparse_matrix, rhs) = mna_system.to_sparse();
-            let (solution, solver_stats) = self.solver.solve_sparse(&sparse_matrix, &rhs)?;
-            
-            mna_system.update_solution(&solution)?;
+        let circuit = self.circuit.as_ref()
+            .ok_or_else(|| anyhow!("No circuit loaded"))?;
+        let mut mna_system = self.mna_system.take()
+            .ok_or_else(|| anyhow!("No MNA system available"))?;
 
-            // Update previous voltages for next iteration
-            prev_voltages = mna_system.get_node_voltages();
+        let start_time = std::time::Instant::now();
+        let linearized = self.solve_operating_point(&mut mna_system, circuit, None, &HashMap::new())
+            .map_err(|e| anyhow!("AC operating point: {}", e))
+            .and_then(|newton| {
+                let operating_point = DVector::from_vec(newton.solution.clone());
+                Ok((newton, mna_system.assemble_ac(circuit, &operating_point)?))
+            });
+        self.mna_system = Some(mna_system);
+        let (newton, mut ac_system) = linearized?;
+        let mna_system = self.mna_system.as_ref().unwrap();
 
-            // Store results
+        let mut node_voltages: HashMap<String, Vec<Complex64>> = HashMap::new();
+        let mut currents: HashMap<String, Vec<Complex64>> = HashMap::new();
+        for &frequency in &frequencies {
+            let solution = ac_system.solve(frequency)?;
             for node in &circuit.nodes {
-                let voltage = mna_system.get_node_voltage(node.id)?;
-                all_node_voltages.get_mut(&node.name).unwrap().push(voltage);
+                let voltage = mna_system.node_map.get(&node.id).map_or(Complex64::new(0.0, 0.0), |&idx| solution[idx]);
+                node_voltages.entry(node.name.clone()).or_default().push(voltage);
+            }
+            for component in circuit.branch_components() {
+                let branch = mna_system.voltage_source_map[&component.name];
+                currents.entry(component.name.clone()).or_default().push(solution[branch]);
             }
+        }
 
-            for vs in circuit.voltage_sources() {
-                let current = mna_system.get_voltage_source_current(&vs.name)?;
-                all_currents.get_mut(&vs.name).unwrap().push(current);
+        self.results.push(SimulationResult {
+            analysis_type: AnalysisType::Ac { sweep: sweep.clone() },
+            time_points: frequencies,
+            node_voltages: HashMap::new(),
+            currents: HashMap::new(),
+            convergence_info: newton.iterations,
+            total_time: start_time.elapsed().as_secs_f64(),
+            success: newton.success,
+            outer_sweep_values: Vec::new(),
+            ac_node_voltages: node_voltages,
+            ac_currents: currents,
+        });
+
+        info!("AC analysis completed in {:.3}ms", start_time.elapsed().as_millis());
+        Ok(())
+    }
+
+    /// Solve the system built by `assemble` with Newton-Raphson, relinearizing the
+    /// nonlinear devices around each iterate. Linear circuits take a single iteration.
+    fn solve_newton<F>(&self, mna_system: &mut MnaSystem, circuit: &Circuit, point: usize, initial_guess: Option<&[f64]>,
+                       step: Option<ChargeStep>, mut assemble: F) -> Result<NewtonSolution>
+    where
+        F: FnMut(&mut MnaSystem) -> Result<()>,
+    {
+        let nonlinear = !circuit.nonlinear_components().is_empty();
+        let mut solution = initial_guess.map_or_else(|| vec![0.0; mna_system.size], <[f64]>::to_vec);
+        let mut iterations = Vec::new();
+        let mut success = true;
+        mna_system.reset_junctions();
+
+        for iteration in 0..self.config.max_iterations.max(1) {
+            assemble(mna_system)?;
+            let limited = mna_system.stamp_nonlinear(circuit, &solution, step)?;
+
+            let (sparse_matrix, rhs) = mna_system.as_sparse();
+            let (next, solver_stats) = self.solver.solve_sparse_from(sparse_matrix, rhs, Some(&solution))?;
+            if next.iter().any(|value| !value.is_finite()) {
+                return Err(anyhow!("Newton iteration {} produced a non-finite solution", iteration));
             }
+            success &= solver_stats.success;
 
-            convergence_info.push(ConvergenceInfo {
-                iteration: step,
+            let (max_change, within_tolerance) = self.check_convergence(&solution, &next, mna_system.num_nodes);
+            // 非线性电路至少迭代两次，且本次迭代没有发生结电压限制
+            let converged = !nonlinear || (iteration > 0 && within_tolerance && !limited);
+            debug!("Newton iteration {}: max change {:.3e}, limited {}", iteration, max_change, limited);
+
+            iterations.push(ConvergenceInfo {
+                iteration,
                 residual_norm: solver_stats.residual_norm,
                 solve_time: solver_stats.solve_time,
                 solver_method: format!("{:?}", solver_stats.method_used),
+                point,
+                max_change,
+                limited,
+                converged,
             });
+            solution = next;
 
-            // Check for convergence issues
-            if !solver_stats.success {
-                warn!("Convergence issue at t = {:.6}s", current_time);
+            if converged {
+                mna_system.update_solution(&solution)?;
+                return Ok(NewtonSolution { solution, iterations, success });
             }
         }
 
-        let start_time = std::time::Instant::now();
-        self.results = Some(SimulationResult {
-            analysis_type: AnalysisType::Transient { tstep, tstop },
-            time_points,
-            node_voltages: all_node_voltages,
-            currents: all_currents,
-            convergence_info,
-            total_time: start_time.elapsed().as_secs_f64(),
-            success: true,
-        });
+        Err(anyhow!("Newton-Raphson did not converge in {} iterations", self.config.max_iterations))
+    }
 
-        self.mna_system = Some(mna_system);
-        
-        info!("Transient analysis completed with {} time points", num_steps);
-        
-        Ok(())
+    /// Solve the DC operating point, with the independent sources at their values at
+    /// `time` if given. The `.NODESET` nodes are first held at their voltages and then
+    /// released, so the final solve starts from the nodeset solution; the `forced`
+    /// nodes (the `.IC` voltages for transient analysis) stay held throughout.
+    fn solve_operating_point(&self, mna_system: &mut MnaSystem, circuit: &Circuit, time: Option<f64>,
+                             forced: &HashMap<String, f64>) -> Result<NewtonSolution> {
+        let assemble = |mna: &mut MnaSystem, held: &HashMap<String, f64>| -> Result<()> {
+            match time {
+                Some(time) => mna.assemble_dc_at(circuit, time)?,
+                None => mna.assemble_dc(circuit)?,
+            }
+            mna.stamp_node_forcing(circuit, held)
+        };
+
+        let mut seeded = None;
+        if !circuit.nodesets.is_empty() {
+            let mut held = circuit.nodesets.clone();
+            held.extend(forced.iter().map(|(node, &voltage)| (node.clone(), voltage)));
+            seeded = Some(self.solve_newton(mna_system, circuit, 0, None, None, |mna| assemble(mna, &held))?);
+        }
+        let guess = seeded.as_ref().map(|newton| newton.solution.as_slice());
+        let mut newton = self.solve_newton(mna_system, circuit, 0, guess, None, |mna| assemble(mna, forced))?;
+        if let Some(seeded) = seeded {
+            newton.success &= seeded.success;
+            newton.iterations.splice(0..0, seeded.iterations);
+        }
+        Ok(newton)
+    }
+
+    /// SPICE convergence test between two iterates: node voltages must agree within
+    /// RELTOL*max(|v|) + VNTOL, branch currents within RELTOL*max(|i|) + ABSTOL.
+    /// Returns the largest change and whether every unknown is within tolerance.
+    fn check_convergence(&self, previous: &[f64], next: &[f64], num_nodes: usize) -> (f64, bool) {
+        let mut max_change: f64 = 0.0;
+        let mut within_tolerance = true;
+        for (i, (&old, &new)) in previous.iter().zip(next).enumerate() {
+            let change = (new - old).abs();
+            let absolute = if i < num_nodes { self.config.vntol } else { self.config.abstol };
+            let tolerance = self.config.reltol * old.abs().max(new.abs()) + absolute;
+            max_change = max_change.max(change);
+            within_tolerance &= change <= tolerance;
+        }
+        (max_change, within_tolerance)
     }
 
     /// Get simulation results
     pub fn get_results(&self) -> Option<&SimulationResult> {
-        self.results.as_ref()
+        self.results.last()
+    }
+
+    /// Results of every analysis run since the netlist was loaded, in order
+    pub fn get_all_results(&self) -> &[SimulationResult] {
+        &self.results
     }
 
-    /// Export simulation results to file
+    /// Export simulation results to file. With several results each goes to its own
+    /// file, numbered in order: `out.csv` becomes `out_1.csv`, `out_2.csv`, ...
     pub fn export_results(&self, filename: &str, format: OutputFormat) -> Result<()> {
-        let results = self.results.as_ref()
-            .ok_or_else(|| anyhow!("No simulation results available"))?;
+        if self.results.is_empty() {
+            return Err(anyhow!("No simulation results available"));
+        }
 
-        match format {
-            OutputFormat::Csv => self.export_csv(results, filename),
-            OutputFormat::Json => self.export_json(results, filename),
+        for (index, results) in self.results.iter().enumerate() {
+            let filename = if self.results.len() == 1 {
+                filename.to_string()
+            } else {
+                numbered_filename(filename, index + 1)
+            };
+            match format {
+                OutputFormat::Csv => self.export_csv(results, &filename)?,
+                OutputFormat::Json => self.export_json(results, &filename)?,
+            }
         }
+        Ok(())
     }
 
     /// Export results to CSV format
@@ -404,19 +903,26 @@ impl Simulator {
         let file = File::create(filename)?;
         let mut writer = Writer::from_writer(file);
 
-        // Create header
-        let mut header = vec!["time".to_string()];
+        // Create header, with the outer sweep variable first for nested DC sweeps
+        let (axis, outer_axis) = results.axis_names();
+        let mut header: Vec<String> = outer_axis.into_iter().chain(std::iter::once(axis)).collect();
         for node_name in results.node_voltages.keys() {
             header.push(format!("V({})", node_name));
         }
         for current_name in results.currents.keys() {
             header.push(format!("I({})", current_name));
         }
+        let ac_columns = results.ac_columns();
+        header.extend(ac_columns.iter().map(|(name, _)| name.clone()));
         writer.write_record(&header)?;
 
         // Write data
         for (i, &time) in results.time_points.iter().enumerate() {
-            let mut record = vec![time.to_string()];
+            let mut record: Vec<String> = results.outer_sweep_values.get(i)
+                .map(|value| value.to_string())
+                .into_iter()
+                .collect();
+            record.push(time.to_string());
             
             for node_name in results.node_voltages.keys() {
                 let voltage = results.node_voltages[node_name].get(i).unwrap_or(&0.0);
@@ -427,6 +933,10 @@ impl Simulator {
                 let current = results.currents[current_name].get(i).unwrap_or(&0.0);
                 record.push(current.to_string());
             }
+
+            for (_, values) in &ac_columns {
+                record.push(values.get(i).unwrap_or(&0.0).to_string());
+            }
             
             writer.write_record(&record)?;
         }
@@ -449,7 +959,10 @@ impl Simulator {
 
     /// Print simulation summary
     pub fn print_summary(&self) {
-        if let Some(results) = &self.results {
+        if self.results.is_empty() {
+            println!("No simulation results available");
+        }
+        for results in &self.results {
             println!("\n=== Simulation Summary ===");
             println!("Analysis type: {:?}", results.analysis_type);
             println!("Total simulation time: {:.3}ms", results.total_time * 1000.0);
@@ -474,6 +987,20 @@ impl Simulator {
                 }
             }
 
+            if let Some(&frequency) = results.time_points.last().filter(|_| !results.ac_node_voltages.is_empty()) {
+                println!("\nAC response at {:.6e}Hz (magnitude, phase):", frequency);
+                for (node_name, voltages) in &results.ac_node_voltages {
+                    if let Some(voltage) = voltages.last() {
+                        println!("  V({}): {:.6e}V, {:.3}°", node_name, voltage.norm(), voltage.arg().to_degrees());
+                    }
+                }
+                for (source_name, currents) in &results.ac_currents {
+                    if let Some(current) = currents.last() {
+                        println!("  I({}): {:.6e}A, {:.3}°", source_name, current.norm(), current.arg().to_degrees());
+                    }
+                }
+            }
+
             // Convergence statistics
             if !results.convergence_info.is_empty() {
                 let total_iterations: usize = results.convergence_info.len();
@@ -485,12 +1012,64 @@ impl Simulator {
                 println!("  Total iterations: {}", total_iterations);
                 println!("  Average residual norm: {:.2e}", avg_residual);
             }
-        } else {
-            println!("No simulation results available");
         }
     }
 }
 
+/// `filename` with `_index` inserted before its extension
+fn numbered_filename(filename: &str, index: usize) -> String {
+    let path = std::path::Path::new(filename);
+    let stem = path.file_stem().and_then(|stem| stem.to_str()).unwrap_or(filename);
+    let name = match path.extension().and_then(|extension| extension.to_str()) {
+        Some(extension) => format!("{}_{}.{}", stem, index, extension),
+        None => format!("{}_{}", stem, index),
+    };
+    path.with_file_name(name).to_string_lossy().into_owned()
+}
+
+/// Starting point of a UIC transient analysis: the `.IC` node voltages and the `IC=`
+/// inductor currents, everything else zero. The `IC=` voltages of the capacitors become
+/// their initial charges, since they need not agree with the node voltages.
+fn initial_condition_solution(mna_system: &mut MnaSystem, circuit: &Circuit) -> DVector<f64> {
+    let mut solution = DVector::zeros(mna_system.size);
+    for (node, &voltage) in &circuit.initial_conditions {
+        if let Some(&index) = circuit.get_node_id(node).and_then(|id| mna_system.node_map.get(&id)) {
+            solution[index] = voltage;
+        }
+    }
+    for component in &circuit.components {
+        let Some(value) = component.initial_condition else {
+            continue;
+        };
+        match component.component_type {
+            ComponentType::Inductor => solution[mna_system.voltage_source_map[&component.name]] = value,
+            ComponentType::Capacitor => mna_system.set_initial_charge(&component.name, component.value * value),
+            _ => {}
+        }
+    }
+    solution
+}
+
+/// Solution at time `t`, linearly interpolated between the accepted time points
+fn interpolate_solution(times: &[f64], solutions: &[DVector<f64>], t: f64) -> DVector<f64> {
+    let index = times.partition_point(|&time| time <= t).clamp(1, times.len() - 1);
+    let (t0, t1) = (times[index - 1], times[index]);
+    let fraction = ((t - t0) / (t1 - t0)).clamp(0.0, 1.0);
+    &solutions[index - 1] + (&solutions[index] - &solutions[index - 1]) * fraction
+}
+
+/// Find the component a DC sweep varies: an independent source or a resistor
+fn find_sweep_target(circuit: &Circuit, name: &str) -> Result<usize> {
+    let index = circuit.components.iter()
+        .position(|comp| comp.name.eq_ignore_ascii_case(name))
+        .ok_or_else(|| anyhow!("Source component '{}' not found", name))?;
+
+    match circuit.components[index].component_type {
+        ComponentType::VoltageSource | ComponentType::CurrentSource | ComponentType::Resistor => Ok(index),
+        ref other => Err(anyhow!("Cannot sweep {} ({:?})", name, other)),
+    }
+}
+
 impl Default for Simulator {
     fn default() -> Self {
         Self::new()
@@ -501,6 +1080,9 @@ impl Default for Simulator {
 mod tests {
     use super::*;
     use crate::circuit::{Circuit, Component};
+    use crate::parser::Analysis;
+    use crate::sweep::SweepKind;
+    use crate::solver::SolverMethod;
 
     #[test]
     fn test_simulator_operating_point() {
@@ -525,6 +1107,11 @@ mod tests {
             subcircuits: Vec::new(),
             parameters: std::collections::HashMap::new(),
             analyses: Vec::new(),
+            models: HashMap::new(),
+            temperature: None,
+            options: Vec::new(),
+            initial_conditions: Vec::new(),
+            nodesets: Vec::new(),
         };
         
         simulator.load_netlist_from_parsed(netlist).unwrap();
@@ -536,4 +1123,623 @@ mod tests {
         assert!(results.success);
         assert_eq!(results.time_points.len(), 1);
     }
-} 
\ No newline at end of file
+
+    #[test]
+    fn test_dc_sweep_applies_and_restores_value() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("Divider\nV1 1 0 DC 1\nR1 1 2 1k\nR2 2 0 2k\n.end").unwrap();
+        let mut simulator = Simulator::new();
+        simulator.load_netlist_from_parsed(netlist).unwrap();
+
+        // 反向扫描，步长符号不影响方向
+        simulator.run_dc_sweep("V1", 3.0, 0.0, 1.0).unwrap();
+        let results = simulator.get_results().unwrap();
+        assert_eq!(results.time_points, vec![3.0, 2.0, 1.0, 0.0]);
+        for (&vin, &vout) in results.time_points.iter().zip(&results.node_voltages["2"]) {
+            assert!((vout - vin * 2.0 / 3.0).abs() < 1e-9);
+        }
+
+        simulator.run_dc_sweep("R2", 1e3, 3e3, -1e3).unwrap();
+        let vout = &simulator.get_results().unwrap().node_voltages["2"];
+        assert!((vout[0] - 0.5).abs() < 1e-9 && (vout[2] - 0.75).abs() < 1e-9);
+
+        assert!(simulator.run_dc_sweep("R2", -1.0, 1.0, 1.0).is_err());
+        let circuit = simulator.circuit.as_ref().unwrap();
+        assert_eq!(circuit.components[0].value, 1.0);
+        assert_eq!(circuit.components[2].value, 2e3);
+    }
+
+    #[test]
+    fn test_nested_dc_sweep() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("Two sources\nV1 1 0 DC 0\nV2 2 0 DC 0\nR1 1 3 1k\nR2 2 3 1k\nR3 3 0 1k\n.end").unwrap();
+        let mut simulator = Simulator::new();
+        simulator.load_netlist_from_parsed(netlist).unwrap();
+
+        simulator.run_dc_analysis(&Sweep::linear("V1", 0.0, 3.0, 1.5), Some(&Sweep::linear("V2", 0.0, 3.0, 3.0))).unwrap();
+        let results = simulator.get_results().unwrap();
+        assert_eq!(results.time_points, vec![0.0, 1.5, 3.0, 0.0, 1.5, 3.0]);
+        assert_eq!(results.outer_sweep_values, vec![0.0, 0.0, 0.0, 3.0, 3.0, 3.0]);
+        assert_eq!(results.curves(), vec![(Some(0.0), 0..3), (Some(3.0), 3..6)]);
+        assert_eq!(results.axis_names(), ("V1".to_string(), Some("V2".to_string())));
+
+        // V(3) = (V1 + V2) / 3
+        for i in 0..6 {
+            let expected = (results.time_points[i] + results.outer_sweep_values[i]) / 3.0;
+            assert!((results.node_voltages["3"][i] - expected).abs() < 1e-9);
+        }
+        let circuit = simulator.circuit.as_ref().unwrap();
+        assert_eq!(circuit.components[1].value, 0.0);
+    }
+
+    #[test]
+    fn test_newton_diode_operating_point() {
+        let mut circuit = Circuit::new("Diode".to_string());
+        circuit.add_component(Component::new_voltage_source("V1".to_string(), "1".to_string(), "0".to_string(), 5.0)).unwrap();
+        circuit.add_component(Component::new_resistor("R1".to_string(), "1".to_string(), "2".to_string(), 1000.0)).unwrap();
+        circuit.add_component(Component::new_diode("D1".to_string(), "2".to_string(), "0".to_string(), None)).unwrap();
+        let netlist = crate::parser::SpiceNetlist {
+            title: circuit.title.clone(),
+            components: circuit.components.clone(),
+            nodes: circuit.nodes.clone(),
+            subcircuits: Vec::new(),
+            parameters: HashMap::new(),
+            analyses: Vec::new(),
+            models: HashMap::new(),
+            temperature: None,
+            options: Vec::new(),
+            initial_conditions: Vec::new(),
+            nodesets: Vec::new(),
+        };
+
+        let mut simulator = Simulator::new();
+        simulator.load_netlist_from_parsed(netlist.clone()).unwrap();
+        simulator.run_operating_point().unwrap();
+        let results = simulator.get_results().unwrap();
+
+        // KCL at the diode: (5 - vd) / 1k = Is * (exp(vd / Vt) - 1)
+        let vd = results.node_voltages["2"][0];
+        let (diode_current, _) = crate::devices::junction_current(vd, 1e-14, crate::devices::thermal_voltage(27.0));
+        assert!(vd > 0.6 && vd < 0.75, "vd = {}", vd);
+        assert!(((5.0 - vd) / 1000.0 - diode_current).abs() < 1e-6 * diode_current);
+
+        let iterations = &results.convergence_info;
+        assert!(iterations.len() > 2);
+        assert!(iterations.iter().any(|info| info.limited));
+        assert!(iterations.last().unwrap().converged);
+
+        let mut simulator = Simulator::with_config(SimulatorConfig { max_iterations: 3, ..SimulatorConfig::default() });
+        simulator.load_netlist_from_parsed(netlist).unwrap();
+        assert!(simulator.run_operating_point().is_err());
+    }
+
+    #[test]
+    fn test_diode_model_series_resistance() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("\
+Diode with RS
+V1 1 0 DC 5
+R1 1 2 1k
+D1 2 0 dmod
+.model dmod D(IS=1e-12 N=1.5 RS=50)
+.end").unwrap();
+        let mut simulator = Simulator::new();
+        simulator.load_netlist_from_parsed(netlist).unwrap();
+        simulator.run_operating_point().unwrap();
+
+        let mna = simulator.mna_system.as_ref().unwrap();
+        let internal = mna.internal_nodes["D1#anode"];
+        let v2 = simulator.get_results().unwrap().node_voltages["2"][0];
+        let vj = mna.unknowns[internal];
+        let current = (5.0 - v2) / 1000.0;
+
+        // 串联电阻上的压降等于 I*RS，结电流满足二极管方程
+        assert!((v2 - vj - current * 50.0).abs() < 1e-6);
+        let nvt = 1.5 * crate::devices::thermal_voltage(27.0);
+        let (junction, _) = crate::devices::junction_current(vj, 1e-12, nvt);
+        assert!((junction - current).abs() < 1e-3 * current);
+    }
+
+    #[test]
+    fn test_mosfet_bias_point() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("\
+Common source
+VDD vdd 0 5
+VG g 0 2
+RD vdd d 2k
+M1 d g 0 0 nch W=10u L=1u
+VIN in 0 2.5
+M2 out in 0 0 nch W=10u L=1u
+M3 out in vdd vdd pch W=10u L=1u
+.model nch NMOS(VTO=1 KP=100u LAMBDA=0.01)
+.model pch PMOS(VTO=-1 KP=100u LAMBDA=0.01)
+.end").unwrap();
+        let mut simulator = Simulator::new();
+        simulator.load_netlist_from_parsed(netlist).unwrap();
+        simulator.run_operating_point().unwrap();
+        let results = simulator.get_results().unwrap();
+
+        // 饱和区 KCL：(5 - Vd) / 2k = beta/2 * (Vgs - Vt)^2 * (1 + lambda*Vd)
+        let vd = results.node_voltages["d"][0];
+        assert!(vd > 1.0 && vd < 5.0, "vd = {}", vd);
+        assert!(((5.0 - vd) / 2000.0 - 0.5e-3 * (1.0 + 0.01 * vd)).abs() < 1e-9);
+
+        // 对称的 CMOS 反相器在输入为电源一半时输出也为电源一半
+        let out = results.node_voltages["out"][0];
+        assert!((out - 2.5).abs() < 1e-3, "out = {}", out);
+    }
+
+    #[test]
+    fn test_bjt_bias_point() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("\
+Common emitter
+VCC vcc 0 5
+RB1 vcc b1 430k
+RC1 vcc c1 2k
+Q1 c1 b1 0 qn
+RB2 b2 0 430k
+RC2 c2 0 2k
+Q2 c2 b2 vcc qp
+RB3 vcc b3 100k
+RC3 vcc c3 1k
+Q3 c3 b3 0 0 qr 2
+.model qn NPN(IS=1e-15 BF=100)
+.model qp PNP(IS=1e-15 BF=100)
+.model qr NPN(IS=1e-15 BF=100 VAF=50 IKF=20m RB=200 RE=5 RC=20)
+.end").unwrap();
+        let mut simulator = Simulator::new();
+        simulator.load_netlist_from_parsed(netlist).unwrap();
+        simulator.run_operating_point().unwrap();
+        let results = simulator.get_results().unwrap();
+        let v = |node: &str| results.node_voltages[node][0];
+
+        // 前向放大区：Ic = BF * Ib
+        let ib = (5.0 - v("b1")) / 430e3;
+        let ic = (5.0 - v("c1")) / 2e3;
+        assert!((ic / ib - 100.0).abs() < 0.1, "beta = {}", ic / ib);
+        let vt = crate::devices::thermal_voltage(27.0);
+        assert!((ic - 1e-15 * (v("b1") / vt).exp()).abs() < 1e-3 * ic);
+
+        // PNP 与 NPN 对称
+        assert!((v("c2") - (5.0 - v("c1"))).abs() < 1e-6);
+        assert!((v("b2") - (5.0 - v("b1"))).abs() < 1e-6);
+
+        // 串联电阻引入内部节点
+        let mna = simulator.mna_system.as_ref().unwrap();
+        assert_eq!(mna.internal_nodes.len(), 3);
+        let emitter = mna.unknowns[mna.internal_nodes["Q3#emitter"]];
+        let ie = (5.0 - v("b3")) / 100e3 + (5.0 - v("c3")) / 1e3;
+        assert!((emitter - ie * 2.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_jfet_bias_point() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("\
+Self-biased JFET
+VDD vdd 0 12
+RD vdd d 2k
+J1 d 0 s jn
+RS s 0 500
+VSS vss 0 -12
+RD2 vss d2 2k
+J2 d2 0 s2 jp
+RS2 s2 0 500
+.model jn NJF(VTO=-2 BETA=1m RD=10 RS=10)
+.model jp PJF(VTO=-2 BETA=1m RD=10 RS=10)
+.end").unwrap();
+        let mut simulator = Simulator::new();
+        simulator.load_netlist_from_parsed(netlist).unwrap();
+        simulator.run_operating_point().unwrap();
+        let results = simulator.get_results().unwrap();
+        let v = |node: &str| results.node_voltages[node][0];
+
+        // 饱和区：Id = BETA * (Vgs - VTO)^2，Vgs = -Id * (RS + rs)
+        let id = v("s") / 500.0;
+        let vgs = -id * 510.0;
+        assert!((id - 1e-3 * (vgs + 2.0).powi(2)).abs() < 1e-6 * id, "id = {}", id);
+        assert!(((12.0 - v("d")) / 2000.0 - id).abs() < 1e-9);
+
+        // PJF 与 NJF 对称
+        assert!((v("s2") + v("s")).abs() < 1e-6);
+        assert!((v("d2") + v("d")).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_controlled_sources() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("\
+Controlled sources
+V1 in 0 1
+Vsense in s 0
+Rs s 0 1k
+E1 e 0 in 0 10
+Re e 0 1k
+G1 0 g in 0 1m
+Rg g 0 1k
+F1 0 f vsense 2
+Rf f 0 1k
+H1 h 0 Vsense 500
+Rh h 0 1k
+.end").unwrap();
+        let mut simulator = Simulator::new();
+        simulator.load_netlist_from_parsed(netlist).unwrap();
+        simulator.run_operating_point().unwrap();
+        let results = simulator.get_results().unwrap();
+        let v = |node: &str| results.node_voltages[node][0];
+
+        assert!((v("e") - 10.0).abs() < 1e-9);
+        assert!((v("g") - 1.0).abs() < 1e-9);
+        assert!((v("f") - 2.0).abs() < 1e-9);
+        assert!((v("h") - 0.5).abs() < 1e-9);
+        // E 和 H 的支路电流也会输出
+        assert!((results.currents["Vsense"][0] - 1e-3).abs() < 1e-12);
+        assert!((results.currents["E1"][0] + 10e-3).abs() < 1e-12);
+
+        // F 的输出节点也是控制电压源的端点：两者在矩阵中的元素要叠加
+        let netlist = parser.parse_netlist("Shared node\nV1 b 0 1\nVsense b c 0\nF1 c 0 Vsense 2\nRc c 0 1k\n.end").unwrap();
+        simulator.load_netlist_from_parsed(netlist).unwrap();
+        simulator.run_operating_point().unwrap();
+        let results = simulator.get_results().unwrap();
+        assert!((results.node_voltages["c"][0] - 1.0).abs() < 1e-9);
+        assert!((results.currents["Vsense"][0] + 1e-3).abs() < 1e-12);
+
+        let mut circuit = Circuit::new("Bad".to_string());
+        circuit.add_component(Component::new_resistor("R1".to_string(), "1".to_string(), "0".to_string(), 1.0)).unwrap();
+        circuit.add_component(Component::new_controlled_source("F1".to_string(),
+            ComponentType::Cccs { control: "R1".to_string() }, vec!["1".to_string(), "0".to_string()], 1.0)).unwrap();
+        assert!(circuit.validate().is_err());
+    }
+
+    #[test]
+    fn test_inductor_branch_current() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("RL\nV1 1 0 DC 1\nR1 1 2 1k\nL1 2 0 1m\n.options interp\n.end").unwrap();
+        let mut simulator = Simulator::new();
+        simulator.load_netlist_from_parsed(netlist).unwrap();
+
+        // 直流下电感是理想短路
+        simulator.run_operating_point().unwrap();
+        let results = simulator.get_results().unwrap();
+        assert_eq!(results.node_voltages["2"][0], 0.0);
+        assert!((results.currents["L1"][0] - 1e-3).abs() < 1e-15);
+
+        // UIC 从零状态开始的阶跃响应，时间常数 L/R = 1us
+        simulator.run_transient(&TransientSpec { uic: true, ..TransientSpec::new(1e-8, 2e-6) }).unwrap();
+        let results = simulator.get_results().unwrap();
+        let current = &results.currents["L1"];
+        assert!(current[1] < 2e-5);
+        let expected = 1e-3 * (1.0 - (-1.0f64).exp());
+        assert!((current[100] - expected).abs() < 1e-2 * expected);
+        assert!((results.node_voltages["2"][100] - 1e3 * (1e-3 - current[100])).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_coupled_inductors() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("\
+Transformer
+V1 in 0 DC 1
+L1 in 0 1m
+L2 out 0 4m
+K1 L1 L2 1
+Rload out 0 1k
+.end").unwrap();
+        let mut simulator = Simulator::new();
+        simulator.load_netlist_from_parsed(netlist).unwrap();
+
+        // 全耦合时次级电压为初级的 sqrt(L2/L1) = 2 倍，与负载无关；
+        // 直流下电压源被电感短路，只能用 UIC 起步
+        assert!(simulator.run_transient_analysis(1e-7, 1e-6).is_err());
+        simulator.run_transient(&TransientSpec { uic: true, ..TransientSpec::new(1e-7, 1e-6) }).unwrap();
+        let results = simulator.get_results().unwrap();
+        for &voltage in &results.node_voltages["out"][1..] {
+            assert!((voltage - 2.0).abs() < 1e-9);
+        }
+        let secondary = *results.currents["L2"].last().unwrap();
+        assert!((secondary + 2e-3).abs() < 1e-12);
+
+        let coupled = |couplings: &str| {
+            let text = format!("Coupling\nV1 a 0 1\nLa a 0 1m\nLb a 0 1m\nLc a 0 1m\nC1 a 0 1n\n{}\n.end", couplings);
+            let netlist = parser.parse_netlist(&text).unwrap();
+            Simulator::new().load_netlist_from_parsed(netlist)
+        };
+        assert!(coupled("K1 La Lb 0.5\nK2 Lb Lc 0.5").is_ok());
+        assert!(coupled("K1 La Lb Lc 0.9").is_ok());
+        // 互感矩阵不正定、重复耦合、引用非电感元件都应报错
+        assert!(coupled("K1 La Lb 1\nK2 La Lc 1\nK3 Lb Lc 0.1").is_err());
+        assert!(coupled("K1 La Lb 0.5\nK2 Lb La 0.5").is_err());
+        assert!(coupled("K1 La C1 0.5").is_err());
+        assert!(coupled("K1 La Lx 0.5").is_err());
+        assert!(coupled("K1 La Lb 1.5").is_err());
+    }
+
+    #[test]
+    fn test_integration_methods() {
+        let parser = SpiceParser::new();
+        let ringing = |options: &str| {
+            let text = format!("LC tank\nV1 in 0 DC 1\nL1 in out 1m\nC1 out 0 1u\n{}\n.end", options);
+            // 限制最大步长，按固定网格比较各方法的数值阻尼
+            let config = SimulatorConfig { tmax: Some(1e-6), interpolate: true, ..SimulatorConfig::default() };
+            let mut simulator = Simulator::with_config(config);
+            simulator.load_netlist_from_parsed(parser.parse_netlist(&text).unwrap()).unwrap();
+            simulator.run_transient(&TransientSpec { uic: true, ..TransientSpec::new(1e-6, 1e-3) }).unwrap();
+            let results = simulator.get_results().unwrap().clone();
+            (simulator.config.clone(), results.node_voltages["out"].clone())
+        };
+
+        // 无损 LC 从零状态起步：v(out) = 1 - cos(t/sqrt(LC))，梯形法保持振幅
+        let (config, trap) = ringing("");
+        assert_eq!(config.integration_method, IntegrationMethod::Trapezoidal);
+        for (step, voltage) in trap.iter().enumerate() {
+            let expected = 1.0 - (step as f64 * 1e-6 / (1e-9f64).sqrt()).cos();
+            assert!((voltage - expected).abs() < 0.01, "step {}: {} vs {}", step, voltage, expected);
+        }
+
+        // Gear 公式有数值阻尼，阶数越低阻尼越大
+        let (config, gear) = ringing(".options method=gear maxord=2 reltol=1e-4 itl4=20");
+        assert_eq!((config.integration_method, config.max_order, config.reltol), (IntegrationMethod::Gear, 2, 1e-4));
+        let (_, euler) = ringing(".option method=gear maxord=1");
+        let last_peak = |voltages: &[f64]| voltages[800..].iter().copied().fold(f64::MIN, f64::max);
+        assert!(last_peak(&trap) > 1.99);
+        assert!(last_peak(&gear) < last_peak(&trap) && last_peak(&gear) > 1.9);
+        assert!(last_peak(&euler) < 1.8);
+
+        let mut simulator = Simulator::new();
+        let netlist = parser.parse_netlist("Bad\nR1 1 0 1k\n.options method=euler\n.end").unwrap();
+        assert!(simulator.load_netlist_from_parsed(netlist).is_err());
+    }
+
+    #[test]
+    fn test_adaptive_timestep() {
+        let parser = SpiceParser::new();
+        let ringing = |options: &str| {
+            let text = format!("LC tank\nV1 in 0 DC 1\nL1 in out 1m\nC1 out 0 1u\n{}\n.end", options);
+            let mut simulator = Simulator::new();
+            simulator.load_netlist_from_parsed(parser.parse_netlist(&text).unwrap()).unwrap();
+            simulator.run_transient(&TransientSpec { uic: true, ..TransientSpec::new(1e-6, 1e-3) }).unwrap();
+            let results = simulator.get_results().unwrap();
+            let error = results.time_points.iter().zip(&results.node_voltages["out"])
+                .map(|(&t, &v)| (v - (1.0 - (t / (1e-9f64).sqrt()).cos())).abs())
+                .fold(0.0, f64::max);
+            (results.time_points.clone(), error)
+        };
+
+        // 步长由截断误差决定，远少于 tstop/tstep 个点；收紧 RELTOL 时误差随之减小
+        let (times, error) = ringing("");
+        assert!(times.len() < 200 && error < 0.5, "{} points, error {}", times.len(), error);
+        let (tight_times, tight_error) = ringing(".options reltol=1e-5 trtol=1");
+        assert!(tight_times.len() > times.len() && tight_error < 0.01, "{} points, error {}", tight_times.len(), tight_error);
+        assert!(times.windows(2).all(|pair| pair[1] > pair[0]) && times.last() == Some(&1e-3));
+    }
+
+    #[test]
+    fn test_transient_reuses_factorization() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("RC\nV1 in 0 PULSE(0 1 0 1n 1n 5u 10u)\nR1 in out 1k\nC1 out 0 1n\nD1 out 0 dmod\n.model dmod D\n.end").unwrap();
+        let mut simulator = Simulator::new();
+        simulator.load_netlist_from_parsed(netlist).unwrap();
+        simulator.run_transient(&TransientSpec::new(1e-7, 2e-5)).unwrap();
+
+        // 接地电容只落在已有的对角元上，工作点与各时间步结构相同：只分析、分解一次，其余全部重分解
+        let counts = simulator.solver.factorization().counts();
+        let iterations = simulator.get_results().unwrap().convergence_info.len();
+        assert_eq!((counts.analyses, counts.factorizations), (1, 1), "{:?}", counts);
+        assert!(counts.refactorizations + 1 >= iterations, "{:?}", counts);
+    }
+
+    #[test]
+    fn test_operating_point_keeps_factorization() {
+        // 200 个未知量的梯形网络：自动选择仍用稀疏 LU，且不丢弃已有的分解
+        let mut text = String::from("Ladder\nV1 1 0 DC 1\n");
+        for i in 1..200 {
+            text.push_str(&format!("R{} {} {} 1k\nRg{} {} 0 1meg\n", i, i, i + 1, i, i + 1));
+        }
+        let netlist = SpiceParser::new().parse_netlist(&(text + ".end")).unwrap();
+        let mut simulator = Simulator::new();
+        simulator.load_netlist_from_parsed(netlist).unwrap();
+        simulator.run_operating_point().unwrap();
+        simulator.run_operating_point().unwrap();
+
+        assert_eq!(simulator.solver.method(), SolverMethod::Lu);
+        let counts = simulator.solver.factorization().counts();
+        assert_eq!((counts.analyses, counts.factorizations, counts.refactorizations), (1, 1, 1));
+    }
+
+    #[test]
+    fn test_trapezoidal_restarts_at_source_edges() {
+        // 时间常数远小于步长，梯形法跨过阶跃后会使电容电流正负振荡
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("Stiff RC\nV1 in 0 PULSE(0 1 5u 0 0 1)\nR1 in out 1\nC1 out 0 1n\n.end").unwrap();
+        let mut simulator = Simulator::new();
+        simulator.load_netlist_from_parsed(netlist).unwrap();
+        simulator.run_transient_analysis(1e-6, 2e-5).unwrap();
+        let results = simulator.get_results().unwrap();
+        for (&time, current) in results.time_points.iter().zip(&results.currents["V1"]) {
+            assert!(time < 6e-6 || current.abs() < 1e-4, "t = {}: {}", time, current);
+        }
+        assert!((results.node_voltages["out"].last().unwrap() - 1.0).abs() < 1e-5);
+    }
+
+    #[test]
+    fn test_transient_hits_source_breakpoints() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("\
+RC pulse
+V1 1 0 PULSE(0 5 0 1n 1n 500n 1u)
+R1 1 2 1k
+C1 2 0 1n
+.end").unwrap();
+        let mut simulator = Simulator::new();
+        simulator.load_netlist_from_parsed(netlist).unwrap();
+        let circuit = simulator.circuit.as_ref().unwrap();
+        let breakpoints: Vec<f64> = std::iter::successors(circuit.next_breakpoint(0.0), |&time| circuit.next_breakpoint(time))
+            .take_while(|&time| time <= 2e-6)
+            .collect();
+        assert_eq!(breakpoints.len(), 8);
+        simulator.run_transient_analysis(1e-8, 2e-6).unwrap();
+        let results = simulator.get_results().unwrap();
+
+        // 每个拐点都是时间点，其后一步不超过到下一个拐点间距的 1/10
+        for (i, &breakpoint) in breakpoints.iter().enumerate() {
+            let index = results.time_points.iter().position(|&t| t == breakpoint)
+                .unwrap_or_else(|| panic!("breakpoint {} missed", breakpoint));
+            let gap = breakpoints.get(i + 1).unwrap_or(&2e-6) - breakpoint;
+            if let Some(next) = results.time_points.get(index + 1) {
+                assert!(next - breakpoint <= 0.1 * gap * (1.0 + 1e-9), "step after {}: {}", breakpoint, next - breakpoint);
+            }
+        }
+        // 上升沿结束时源电压恰为 5V
+        let index = results.time_points.iter().position(|&t| t == 1e-9).unwrap();
+        assert!((results.node_voltages["1"][index] - 5.0).abs() < 1e-9);
+
+        // 累加周期得到的拐点 4.9999...us 与终点合并，不留极小的一步
+        simulator.run_transient_analysis(1e-8, 5e-6).unwrap();
+        assert_eq!(simulator.get_results().unwrap().time_points.last(), Some(&5e-6));
+    }
+
+    #[test]
+    fn test_initial_conditions() {
+        let parser = SpiceParser::new();
+        let rc = |cards: &str, uic: bool| {
+            let text = format!("RC\nV1 in 0 DC 1\nR1 in out 1k\nC1 out 0 1u IC=-1\n{}\n.end", cards);
+            let mut simulator = Simulator::new();
+            simulator.load_netlist_from_parsed(parser.parse_netlist(&text).unwrap()).unwrap();
+            simulator.run_transient(&TransientSpec { uic, ..TransientSpec::new(1e-5, 1e-3) }).unwrap();
+            let voltages = &simulator.get_results().unwrap().node_voltages["out"];
+            (voltages[0], *voltages.last().unwrap())
+        };
+
+        // 默认从直流工作点起步，电路已处于稳态
+        let (start, end) = rc("", false);
+        assert!((start - 1.0).abs() < 1e-9 && (end - 1.0).abs() < 1e-6);
+
+        // .IC 在工作点中固定节点电压，之后按时间常数 1ms 充电
+        let expected = 1.0 - (-1.0f64).exp();
+        let (start, end) = rc(".ic V(out)=0", false);
+        assert!(start.abs() < 1e-6 && (end - expected).abs() < 1e-2, "{} {}", start, end);
+
+        // UIC 跳过工作点，电容从 IC=-1 开始充电
+        let (_, end) = rc("", true);
+        assert!((end - (1.0 - 2.0 * (-1.0f64).exp())).abs() < 1e-2, "{}", end);
+
+        // .NODESET 只是初值，放开后收敛到同一个工作点
+        let mut simulator = Simulator::new();
+        let netlist = parser.parse_netlist("Divider\nV1 in 0 1\nR1 in out 1k\nR2 out 0 1k\n.nodeset V(out)=5\n.end").unwrap();
+        simulator.load_netlist_from_parsed(netlist).unwrap();
+        simulator.run_operating_point().unwrap();
+        assert!((simulator.get_results().unwrap().node_voltages["out"][0] - 0.5).abs() < 1e-12);
+
+        let netlist = parser.parse_netlist("Bad\nR1 1 0 1k\n.ic V(2)=1\n.end").unwrap();
+        assert!(Simulator::new().load_netlist_from_parsed(netlist).is_err());
+    }
+
+    #[test]
+    fn test_netlist_analyses() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("\
+RC
+V1 in 0 DC 1 AC 1
+R1 in out 1k
+C1 out 0 1u
+.OP
+.TRAN 10u 2m 1m UIC
+.Dc V1 0 1 0.5
+.ac dec 2 1 100
+.end").unwrap();
+        let mut simulator = Simulator::new();
+        simulator.load_netlist_from_parsed(netlist).unwrap();
+        assert_eq!(simulator.analyses().len(), 4);
+        simulator.run_netlist_analyses().unwrap();
+
+        // 每个分析各有一份结果，顺序与网表一致
+        let results = simulator.get_all_results();
+        assert!(matches!(results[0].analysis_type, AnalysisType::Operating));
+        assert!(matches!(results[1].analysis_type, AnalysisType::Transient { .. }));
+        assert!(matches!(results[2].analysis_type, AnalysisType::DcSweep { .. }));
+        assert!(matches!(results[3].analysis_type, AnalysisType::Ac { .. }));
+        assert_eq!(results[0].node_voltages["out"], vec![1.0]);
+        assert_eq!(results[2].time_points, vec![0.0, 0.5, 1.0]);
+        assert_eq!(results[3].time_points.len(), 5);
+
+        // tstart 之前的点不输出；UIC 从零开始充电
+        let transient = &results[1];
+        assert!(transient.time_points[0] >= 1e-3 && transient.time_points[0] < 1.1e-3);
+        let expected = 1.0 - (-2.0f64).exp();
+        assert!((transient.node_voltages["out"].last().unwrap() - expected).abs() < 1e-2);
+
+        let dir = tempfile::tempdir().unwrap();
+        let output = dir.path().join("rc.csv");
+        simulator.export_results(output.to_str().unwrap(), OutputFormat::Csv).unwrap();
+        for index in 1..=4 {
+            assert!(dir.path().join(format!("rc_{}.csv", index)).exists());
+        }
+        assert_eq!(numbered_filename("out", 2), "out_2");
+    }
+
+    #[test]
+    fn test_ac_filters() {
+        let parser = SpiceParser::new();
+        let netlist = parser.parse_netlist("\
+Filters
+V1 in 0 AC 1
+R1 in lp 1k
+C1 lp 0 1u
+R2 in hp 1k
+L2 hp 0 1m
+.ac dec 10 1 1meg
+.end").unwrap();
+        assert!(matches!(netlist.analyses[0], Analysis::Ac { points: 10, .. }));
+        let mut simulator = Simulator::new();
+        simulator.load_netlist_from_parsed(netlist).unwrap();
+
+        // 分别在 RC 低通和 RL 高通的转折频率处求解
+        let corner_rc = 1.0 / (2.0 * std::f64::consts::PI * 1e3 * 1e-6);
+        let corner_rl = 1e3 / (2.0 * std::f64::consts::PI * 1e-3);
+        simulator.run_ac_analysis(&Sweep::new("frequency", SweepKind::List(vec![corner_rc, corner_rl]))).unwrap();
+        let results = simulator.get_results().unwrap();
+        assert_eq!(results.time_points, vec![corner_rc, corner_rl]);
+
+        let low_pass = results.ac_node_voltages["lp"][0];
+        assert!((low_pass.norm() - 0.5f64.sqrt()).abs() < 1e-9);
+        assert!((low_pass.arg().to_degrees() + 45.0).abs() < 1e-6);
+        let high_pass = results.ac_node_voltages["hp"][1];
+        assert!((high_pass.norm() - 0.5f64.sqrt()).abs() < 1e-9);
+        assert!((high_pass.arg().to_degrees() - 45.0).abs() < 1e-6);
+        // 电感电流为 V(hp) / jωL
+        let omega = 2.0 * std::f64::consts::PI * corner_rl;
+        assert!((results.ac_currents["L2"][1] - high_pass / Complex64::new(0.0, omega * 1e-3)).norm() < 1e-12);
+        assert!(results.ac_columns().iter().any(|(name, _)| name == "VP(lp)"));
+    }
+
+    #[test]
+    fn test_ac_linearizes_at_operating_point() {
+        let netlist = "\
+Common source
+VDD vdd 0 5
+VG in 0 DC 1.5 AC 1
+RG in g 10k
+RD vdd d 10k
+M1 d g 0 0 nmod W=10u L=1u
+.model nmod NMOS(VTO=0.7 KP=50u LAMBDA=0.02 TOX=20n)
+.end";
+        let parser = SpiceParser::new();
+        let mut simulator = Simulator::new();
+        simulator.load_netlist_from_parsed(parser.parse_netlist(netlist).unwrap()).unwrap();
+
+        // 低频增益应等于直流传输特性的斜率
+        simulator.run_dc_sweep("VG", 1.5, 1.5001, 1e-4).unwrap();
+        let dc = &simulator.get_results().unwrap().node_voltages["d"];
+        let slope = (dc[1] - dc[0]) / 1e-4;
+        assert!(slope < -1.0);
+
+        simulator.run_ac_analysis(&Sweep::frequency("dec", 1, 1.0, 1e12).unwrap()).unwrap();
+        let gain = &simulator.get_results().unwrap().ac_node_voltages["d"];
+        assert!((gain[0].re - slope).abs() < 1e-3 * slope.abs());
+        assert!(gain[0].im.abs() < 1e-6);
+        // 栅源电容在高频时旁路栅极，增益下降
+        assert!(gain.last().unwrap().norm() < 0.5 * slope.abs());
+    }
+}
@@ -0,0 +1,970 @@
+use sprs::CsMat;
+use num_complex::Complex64;
+use anyhow::{anyhow, Result};
+use std::collections::BTreeSet;
+use std::fmt::Debug;
+use std::ops::{AddAssign, Div, DivAssign, Mul, SubAssign};
+use log::debug;
+
+/// Default relative threshold of partial pivoting: the diagonal entry stays the pivot
+/// unless it is smaller than this fraction of the largest candidate in its column
+pub const DEFAULT_PIVOT_TOLERANCE: f64 = 1e-3;
+
+/// Default limit of the pivot growth up to which a refactorization with the previous
+/// pivot sequence is accepted
+pub const DEFAULT_MAX_PIVOT_GROWTH: f64 = 1e8;
+
+const NONE: usize = usize::MAX;
+
+/// Entry type of the sparse LU: real for DC and transient analysis, complex for AC
+pub trait LuScalar: Copy + Default + PartialEq + Debug + AddAssign + SubAssign + DivAssign
+    + Mul<Output = Self> + Div<Output = Self> {
+    /// Magnitude compared by the pivot threshold and the pivot growth
+    fn magnitude(self) -> f64;
+}
+
+impl LuScalar for f64 {
+    fn magnitude(self) -> f64 {
+        self.abs()
+    }
+}
+
+impl LuScalar for Complex64 {
+    fn magnitude(self) -> f64 {
+        self.norm()
+    }
+}
+
+/// Fill-in and operation counts of a sparse LU factorization
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct LuStats {
+    /// Diagonal blocks of the block triangular form
+    pub blocks: usize,
+    /// Entries of the matrix
+    pub nnz_matrix: usize,
+    /// Entries of L (without its unit diagonal) and U, including the off-diagonal blocks
+    pub nnz_factors: usize,
+    /// Entries of the factors that are not entries of the matrix
+    pub fill_in: usize,
+    /// Floating point operations of the numeric factorization
+    pub flops: usize,
+    /// Columns whose pivot had to be taken off the diagonal
+    pub off_diagonal_pivots: usize,
+    /// Largest growth of U over the matrix within a column (see `SparseLu::refactor`)
+    pub pivot_growth: f64,
+    /// Whether the pivot sequence of the previous factorization was reused
+    pub refactored: bool,
+}
+
+/// Matrix in compressed sparse column form
+#[derive(Debug, Clone, Default)]
+struct Csc<T> {
+    col_ptr: Vec<usize>,
+    row_idx: Vec<usize>,
+    values: Vec<T>,
+}
+
+impl<T: Copy + Default> Csc<T> {
+    fn with_columns(n: usize) -> Csc<T> {
+        let mut col_ptr = Vec::with_capacity(n + 1);
+        col_ptr.push(0);
+        Csc { col_ptr, row_idx: Vec::new(), values: Vec::new() }
+    }
+
+    fn from_matrix(matrix: &CsMat<T>) -> Csc<T> {
+        let csc = if matrix.is_csc() { matrix.clone() } else { matrix.to_other_storage() };
+        let mut result = Csc::with_columns(csc.cols());
+        for column in csc.outer_iterator() {
+            for (row, &value) in column.iter() {
+                result.row_idx.push(row);
+                result.values.push(value);
+            }
+            result.close_column();
+        }
+        result
+    }
+
+    fn column(&self, col: usize) -> std::ops::Range<usize> {
+        self.col_ptr[col]..self.col_ptr[col + 1]
+    }
+
+    fn close_column(&mut self) {
+        self.col_ptr.push(self.row_idx.len());
+    }
+
+    /// Row-wise pattern: the columns of the entries of each row
+    fn row_pattern(&self, n: usize) -> Vec<Vec<usize>> {
+        let mut rows = vec![Vec::new(); n];
+        for col in 0..n {
+            for p in self.column(col) {
+                rows[self.row_idx[p]].push(col);
+            }
+        }
+        rows
+    }
+}
+
+/// Symbolic analysis of a sparsity pattern, shared by all matrices with that pattern:
+/// the permutation to block upper triangular form (maximum transversal followed by
+/// strongly connected components) and an approximate minimum degree ordering of each
+/// diagonal block on the pattern of B + B^T
+#[derive(Debug, Clone)]
+pub struct LuSymbolic {
+    n: usize,
+    /// Pattern that was analysed
+    col_ptr: Vec<usize>,
+    row_idx: Vec<usize>,
+    /// Row matched to each column and column matched to each row
+    matched_row: Vec<usize>,
+    matched_col: Vec<usize>,
+    block_of: Vec<usize>,
+    /// Columns of each diagonal block in elimination order
+    block_columns: Vec<Vec<usize>>,
+}
+
+impl LuSymbolic {
+    /// Analyse the pattern of the square matrix `matrix`
+    pub fn analyze<T: LuScalar>(matrix: &CsMat<T>) -> Result<LuSymbolic> {
+        if matrix.rows() != matrix.cols() {
+            return Err(anyhow!("Matrix must be square"));
+        }
+        LuSymbolic::analyze_csc(matrix.rows(), &Csc::from_matrix(matrix))
+    }
+
+    fn analyze_csc<T: LuScalar>(n: usize, a: &Csc<T>) -> Result<LuSymbolic> {
+        let rows = a.row_pattern(n);
+
+        // 最大横截：每列匹配一行，使置换后对角线结构非零
+        let matched_row = maximum_transversal(n, a)?;
+        let mut matched_col = vec![NONE; n];
+        for (col, &row) in matched_row.iter().enumerate() {
+            matched_col[row] = col;
+        }
+
+        // 以列为节点：列 j 依赖于其匹配行中出现的列，强连通分量即对角块
+        let adjacency: Vec<&[usize]> = (0..n).map(|col| rows[matched_row[col]].as_slice()).collect();
+        let (component, blocks) = strongly_connected_components(&adjacency);
+        // Tarjan 先给出汇点分量，块上三角形式中它们排在最后
+        let block_of: Vec<usize> = component.iter().map(|&c| blocks - 1 - c).collect();
+        let mut block_columns = vec![Vec::new(); blocks];
+        for col in 0..n {
+            block_columns[block_of[col]].push(col);
+        }
+
+        // 块内按 B + B^T 的近似最小度排序，行随其匹配列一起置换
+        let mut local_of_col = vec![NONE; n];
+        for (block, columns) in block_columns.iter_mut().enumerate().filter(|(_, columns)| columns.len() > 1) {
+            for (local, &col) in columns.iter().enumerate() {
+                local_of_col[col] = local;
+            }
+            let mut pattern = vec![Vec::new(); columns.len()];
+            for (local, &col) in columns.iter().enumerate() {
+                for p in a.column(col) {
+                    let other = matched_col[a.row_idx[p]];
+                    if other != col && block_of[other] == block {
+                        pattern[local].push(local_of_col[other]);
+                        pattern[local_of_col[other]].push(local);
+                    }
+                }
+            }
+            for neighbours in &mut pattern {
+                neighbours.sort_unstable();
+                neighbours.dedup();
+            }
+            *columns = approximate_minimum_degree(pattern).into_iter().map(|local| columns[local]).collect();
+        }
+
+        Ok(LuSymbolic {
+            n,
+            col_ptr: a.col_ptr.clone(),
+            row_idx: a.row_idx.clone(),
+            matched_row,
+            matched_col,
+            block_of,
+            block_columns,
+        })
+    }
+
+    /// Whether `matrix` has exactly the analysed pattern
+    pub fn matches<T: LuScalar>(&self, matrix: &CsMat<T>) -> bool {
+        matrix.rows() == self.n && matrix.cols() == self.n && self.matches_csc(&Csc::from_matrix(matrix))
+    }
+
+    fn matches_csc<T>(&self, a: &Csc<T>) -> bool {
+        a.col_ptr == self.col_ptr && a.row_idx == self.row_idx
+    }
+}
+
+/// Sparse LU factorization in the style of KLU. Each diagonal block of the symbolic
+/// analysis is factored by left-looking Gilbert-Peierls LU with threshold partial
+/// pivoting, which keeps the diagonal as pivot when it is large enough. Only the
+/// diagonal blocks are factored; the blocks above them are used as they are in the solve.
+#[derive(Debug, Clone)]
+pub struct SparseLu<T = f64> {
+    n: usize,
+    /// Original row and column of each position of the permuted matrix
+    row_perm: Vec<usize>,
+    col_perm: Vec<usize>,
+    /// Permuted position of each original row
+    position_of_row: Vec<usize>,
+    /// First position of each diagonal block, followed by n
+    block_starts: Vec<usize>,
+    /// Unit lower triangular factor without its diagonal, in permuted positions
+    lower: Csc<T>,
+    /// Strictly upper triangular part of U within the diagonal blocks, rows ascending
+    upper: Csc<T>,
+    diagonal: Vec<T>,
+    /// Entries above the diagonal blocks, by permuted position
+    off_diagonal: Csc<T>,
+    stats: LuStats,
+}
+
+impl<T: LuScalar> SparseLu<T> {
+    /// Analyse and factor the square matrix `matrix` with relative pivot threshold `pivot_tolerance`
+    pub fn factor(matrix: &CsMat<T>, pivot_tolerance: f64) -> Result<SparseLu<T>> {
+        let symbolic = LuSymbolic::analyze(matrix)?;
+        SparseLu::numeric(&symbolic, matrix, pivot_tolerance)
+    }
+
+    /// Factor `matrix`, which must have the pattern of `symbolic`, choosing pivots afresh
+    pub fn numeric(symbolic: &LuSymbolic, matrix: &CsMat<T>, pivot_tolerance: f64) -> Result<SparseLu<T>> {
+        let a = Csc::from_matrix(matrix);
+        if !symbolic.matches_csc(&a) {
+            return Err(anyhow!("Sparse LU failed: matrix pattern differs from the symbolic analysis"));
+        }
+        SparseLu::numeric_csc(symbolic, &a, pivot_tolerance)
+    }
+
+    fn numeric_csc(symbolic: &LuSymbolic, a: &Csc<T>, pivot_tolerance: f64) -> Result<SparseLu<T>> {
+        let n = symbolic.n;
+        let blocks = symbolic.block_columns.len();
+        let mut lu = SparseLu {
+            n,
+            row_perm: vec![NONE; n],
+            col_perm: Vec::with_capacity(n),
+            position_of_row: vec![NONE; n],
+            block_starts: Vec::with_capacity(blocks + 1),
+            lower: Csc::with_columns(n),
+            upper: Csc::with_columns(n),
+            diagonal: Vec::with_capacity(n),
+            off_diagonal: Csc::with_columns(n),
+            stats: LuStats { blocks, nnz_matrix: a.row_idx.len(), ..LuStats::default() },
+        };
+        let mut local_of_col = vec![NONE; n];
+
+        for (block, order) in symbolic.block_columns.iter().enumerate() {
+            let start = lu.col_perm.len();
+            lu.block_starts.push(start);
+            for (local, &col) in order.iter().enumerate() {
+                local_of_col[col] = local;
+            }
+
+            // 块内局部矩阵；上方各块中的元素进入非对角部分
+            let mut local = Csc::with_columns(order.len());
+            for &col in order {
+                for p in a.column(col) {
+                    let row = a.row_idx[p];
+                    let owner = symbolic.matched_col[row];
+                    if symbolic.block_of[owner] == block {
+                        local.row_idx.push(local_of_col[owner]);
+                        local.values.push(a.values[p]);
+                    } else {
+                        lu.off_diagonal.row_idx.push(lu.position_of_row[row]);
+                        lu.off_diagonal.values.push(a.values[p]);
+                    }
+                }
+                local.close_column();
+                lu.off_diagonal.close_column();
+            }
+
+            let factor = factor_block(&local, pivot_tolerance)
+                .map_err(|column| anyhow!("Sparse LU failed: matrix is singular at column {}", order[column]))?;
+            for (local_row, &pivot) in factor.pivot_of_row.iter().enumerate() {
+                let row = symbolic.matched_row[order[local_row]];
+                lu.position_of_row[row] = start + pivot;
+                lu.row_perm[start + pivot] = row;
+            }
+            for k in 0..order.len() {
+                for p in factor.lower.column(k) {
+                    lu.lower.row_idx.push(start + factor.lower.row_idx[p]);
+                    lu.lower.values.push(factor.lower.values[p]);
+                }
+                lu.lower.close_column();
+                // 行号升序即拓扑序，供重分解按固定主元顺序消去
+                let mut entries: Vec<(usize, T)> = factor.upper.column(k)
+                    .map(|p| (start + factor.upper.row_idx[p], factor.upper.values[p]))
+                    .collect();
+                entries.sort_unstable_by_key(|&(row, _)| row);
+                for (row, value) in entries {
+                    lu.upper.row_idx.push(row);
+                    lu.upper.values.push(value);
+                }
+                lu.upper.close_column();
+            }
+            lu.diagonal.extend_from_slice(&factor.diagonal);
+            lu.col_perm.extend_from_slice(order);
+            lu.stats.flops += factor.flops;
+            lu.stats.off_diagonal_pivots += factor.off_diagonal_pivots;
+        }
+        lu.block_starts.push(n);
+
+        lu.stats.nnz_factors = lu.lower.row_idx.len() + lu.upper.row_idx.len() + n + lu.off_diagonal.row_idx.len();
+        lu.stats.fill_in = lu.stats.nnz_factors.saturating_sub(lu.stats.nnz_matrix);
+        lu.stats.pivot_growth = lu.pivot_growth(a);
+        Ok(lu)
+    }
+
+    /// Refactor `matrix`, which must have the pattern of the factored matrix, keeping the
+    /// previous pivot sequence and the patterns of L and U. Fails on a zero pivot; the
+    /// factors are then unusable until the next successful factorization.
+    pub fn refactor(&mut self, matrix: &CsMat<T>) -> Result<()> {
+        self.refactor_csc(&Csc::from_matrix(matrix))
+    }
+
+    fn refactor_csc(&mut self, a: &Csc<T>) -> Result<()> {
+        let mut x = vec![T::default(); self.n];
+        let mut off_diagonal = 0;
+        self.stats.flops = 0;
+
+        for block in 0..self.block_starts.len() - 1 {
+            let start = self.block_starts[block];
+            for k in start..self.block_starts[block + 1] {
+                let col = self.col_perm[k];
+                for p in a.column(col) {
+                    let position = self.position_of_row[a.row_idx[p]];
+                    if position >= start {
+                        x[position] += a.values[p];
+                    } else {
+                        self.off_diagonal.values[off_diagonal] = a.values[p];
+                        off_diagonal += 1;
+                    }
+                }
+
+                for q in self.upper.column(k) {
+                    let j = self.upper.row_idx[q];
+                    let value = x[j];
+                    x[j] = T::default();
+                    self.upper.values[q] = value;
+                    let column = self.lower.column(j);
+                    self.stats.flops += 2 * column.len();
+                    for l in column {
+                        x[self.lower.row_idx[l]] -= self.lower.values[l] * value;
+                    }
+                }
+
+                let pivot = x[k];
+                x[k] = T::default();
+                if pivot.magnitude() == 0.0 || !pivot.magnitude().is_finite() {
+                    return Err(anyhow!("Sparse LU refactorization failed: zero pivot at column {}", col));
+                }
+                self.diagonal[k] = pivot;
+                for l in self.lower.column(k) {
+                    let row = self.lower.row_idx[l];
+                    self.lower.values[l] = x[row] / pivot;
+                    x[row] = T::default();
+                    self.stats.flops += 1;
+                }
+            }
+        }
+
+        self.stats.off_diagonal_pivots = 0;
+        self.stats.pivot_growth = self.pivot_growth(a);
+        self.stats.refactored = true;
+        Ok(())
+    }
+
+    /// Largest ratio over the columns between the magnitude of U and of the matrix
+    /// within the diagonal blocks; large values mean the pivots have become unstable
+    fn pivot_growth(&self, a: &Csc<T>) -> f64 {
+        let mut growth: f64 = 1.0;
+        for block in 0..self.block_starts.len() - 1 {
+            let start = self.block_starts[block];
+            for k in start..self.block_starts[block + 1] {
+                let matrix_max = a.column(self.col_perm[k])
+                    .filter(|&p| self.position_of_row[a.row_idx[p]] >= start)
+                    .fold(0.0_f64, |max, p| max.max(a.values[p].magnitude()));
+                let factor_max = self.upper.column(k)
+                    .fold(self.diagonal[k].magnitude(), |max, q| max.max(self.upper.values[q].magnitude()));
+                if matrix_max > 0.0 {
+                    growth = growth.max(factor_max / matrix_max);
+                }
+            }
+        }
+        growth
+    }
+
+    /// Solve A x = b with the factorization
+    pub fn solve(&self, rhs: &[T]) -> Vec<T> {
+        let mut y: Vec<T> = self.row_perm.iter().map(|&row| rhs[row]).collect();
+
+        // 自最后一块向前回代，每解完一块即减去其列在上方各块中的贡献
+        for block in (0..self.block_starts.len() - 1).rev() {
+            let (start, end) = (self.block_starts[block], self.block_starts[block + 1]);
+            for k in start..end {
+                let value = y[k];
+                for p in self.lower.column(k) {
+                    y[self.lower.row_idx[p]] -= self.lower.values[p] * value;
+                }
+            }
+            for k in (start..end).rev() {
+                y[k] /= self.diagonal[k];
+                let value = y[k];
+                for p in self.upper.column(k) {
+                    y[self.upper.row_idx[p]] -= self.upper.values[p] * value;
+                }
+            }
+            for k in start..end {
+                let value = y[k];
+                for p in self.off_diagonal.column(k) {
+                    y[self.off_diagonal.row_idx[p]] -= self.off_diagonal.values[p] * value;
+                }
+            }
+        }
+
+        let mut solution = vec![T::default(); self.n];
+        for (k, &col) in self.col_perm.iter().enumerate() {
+            solution[col] = y[k];
+        }
+        solution
+    }
+
+    /// Fill-in and operation counts of the factorization
+    pub fn stats(&self) -> LuStats {
+        self.stats
+    }
+}
+
+/// How often a `LuFactorization` has analysed, factored and refactored
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct FactorizationCounts {
+    pub analyses: usize,
+    pub factorizations: usize,
+    pub refactorizations: usize,
+}
+
+/// Sparse LU kept between solves of matrices with the same pattern, as in the Newton
+/// iterations, time steps and sweep points of one circuit. The symbolic analysis is redone
+/// only when the pattern changes; otherwise the new values are refactored with the previous
+/// pivot sequence, falling back to a factorization with fresh pivoting when a pivot
+/// vanishes or the pivot growth exceeds the allowed limit.
+#[derive(Debug, Clone)]
+pub struct LuFactorization<T = f64> {
+    symbolic: Option<LuSymbolic>,
+    numeric: Option<SparseLu<T>>,
+    counts: FactorizationCounts,
+}
+
+impl<T> Default for LuFactorization<T> {
+    fn default() -> Self {
+        LuFactorization { symbolic: None, numeric: None, counts: FactorizationCounts::default() }
+    }
+}
+
+impl<T: LuScalar> LuFactorization<T> {
+    pub fn new() -> Self {
+        LuFactorization::default()
+    }
+
+    /// Factor `matrix`, reusing as much of the previous factorization as its pattern allows
+    pub fn factor(&mut self, matrix: &CsMat<T>, pivot_tolerance: f64, max_pivot_growth: f64) -> Result<LuStats> {
+        if matrix.rows() != matrix.cols() {
+            return Err(anyhow!("Matrix must be square"));
+        }
+        let a = Csc::from_matrix(matrix);
+
+        let symbolic = match self.symbolic.take() {
+            Some(symbolic) if symbolic.n == matrix.rows() && symbolic.matches_csc(&a) => symbolic,
+            _ => {
+                self.numeric = None;
+                self.counts.analyses += 1;
+                LuSymbolic::analyze_csc(matrix.rows(), &a)?
+            }
+        };
+        let symbolic = self.symbolic.insert(symbolic);
+
+        if let Some(mut lu) = self.numeric.take() {
+            if lu.refactor_csc(&a).is_ok() && lu.stats.pivot_growth <= max_pivot_growth {
+                self.counts.refactorizations += 1;
+                return Ok(self.numeric.insert(lu).stats);
+            }
+            debug!("Pivot growth too large or zero pivot in refactorization, pivoting again");
+        }
+
+        let lu = SparseLu::numeric_csc(symbolic, &a, pivot_tolerance)?;
+        self.counts.factorizations += 1;
+        Ok(self.numeric.insert(lu).stats)
+    }
+
+    /// Solve with the last successful factorization
+    pub fn solve(&self, rhs: &[T]) -> Result<Vec<T>> {
+        let lu = self.numeric.as_ref().ok_or_else(|| anyhow!("No LU factorization available"))?;
+        if rhs.len() != lu.n {
+            return Err(anyhow!("Matrix and RHS dimensions don't match"));
+        }
+        Ok(lu.solve(rhs))
+    }
+
+    pub fn counts(&self) -> FactorizationCounts {
+        self.counts
+    }
+
+    /// Forget the factorization, e.g. when a new circuit is loaded
+    pub fn clear(&mut self) {
+        self.symbolic = None;
+        self.numeric = None;
+    }
+}
+
+/// LU factors of one diagonal block, in pivot order
+struct BlockFactor<T> {
+    lower: Csc<T>,
+    upper: Csc<T>,
+    diagonal: Vec<T>,
+    /// Pivot position of each local row
+    pivot_of_row: Vec<usize>,
+    flops: usize,
+    off_diagonal_pivots: usize,
+}
+
+/// Left-looking Gilbert-Peierls LU of a block whose preferred pivot of column k is row k.
+/// On a zero pivot the failing local column is returned.
+fn factor_block<T: LuScalar>(a: &Csc<T>, tolerance: f64) -> std::result::Result<BlockFactor<T>, usize> {
+    let n = a.col_ptr.len() - 1;
+    let mut factor = BlockFactor {
+        lower: Csc::with_columns(n),
+        upper: Csc::with_columns(n),
+        diagonal: Vec::with_capacity(n),
+        pivot_of_row: vec![NONE; n],
+        flops: 0,
+        off_diagonal_pivots: 0,
+    };
+    let mut x = vec![T::default(); n];
+    let mut visited = vec![NONE; n];
+    let mut postorder = Vec::with_capacity(n);
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+
+    for k in 0..n {
+        // 符号阶段：L x = A(:,k) 的非零结构，逆后序即拓扑序
+        postorder.clear();
+        for p in a.column(k) {
+            let start = a.row_idx[p];
+            if visited[start] == k {
+                continue;
+            }
+            visited[start] = k;
+            stack.push((start, 0));
+            while let Some(&mut (row, ref mut next)) = stack.last_mut() {
+                let pivot = factor.pivot_of_row[row];
+                let children = if pivot == NONE { 0..0 } else { factor.lower.column(pivot) };
+                if *next < children.len() {
+                    let child = factor.lower.row_idx[children.start + *next];
+                    *next += 1;
+                    if visited[child] != k {
+                        visited[child] = k;
+                        stack.push((child, 0));
+                    }
+                } else {
+                    stack.pop();
+                    postorder.push(row);
+                }
+            }
+        }
+
+        // 数值阶段：稀疏三角求解
+        for p in a.column(k) {
+            x[a.row_idx[p]] += a.values[p];
+        }
+        for &row in postorder.iter().rev() {
+            let pivot = factor.pivot_of_row[row];
+            if pivot == NONE {
+                continue;
+            }
+            let value = x[row];
+            let column = factor.lower.column(pivot);
+            factor.flops += 2 * column.len();
+            for q in column {
+                x[factor.lower.row_idx[q]] -= factor.lower.values[q] * value;
+            }
+        }
+
+        // 门限部分选主元：对角元足够大时优先保留
+        let mut pivot_row = NONE;
+        let mut largest = 0.0;
+        for &row in &postorder {
+            if factor.pivot_of_row[row] == NONE && x[row].magnitude() > largest {
+                largest = x[row].magnitude();
+                pivot_row = row;
+            }
+        }
+        if pivot_row == NONE || !largest.is_finite() {
+            for &row in &postorder {
+                x[row] = T::default();
+            }
+            return Err(k);
+        }
+        if pivot_row != k {
+            if visited[k] == k && factor.pivot_of_row[k] == NONE && x[k].magnitude() >= tolerance * largest && x[k] != T::default() {
+                pivot_row = k;
+            } else {
+                factor.off_diagonal_pivots += 1;
+            }
+        }
+        let pivot = x[pivot_row];
+
+        for &row in &postorder {
+            let position = factor.pivot_of_row[row];
+            if position != NONE {
+                factor.upper.row_idx.push(position);
+                factor.upper.values.push(x[row]);
+            } else if row != pivot_row {
+                factor.lower.row_idx.push(row);
+                factor.lower.values.push(x[row] / pivot);
+                factor.flops += 1;
+            }
+            x[row] = T::default();
+        }
+        factor.upper.close_column();
+        factor.lower.close_column();
+        factor.diagonal.push(pivot);
+        factor.pivot_of_row[pivot_row] = k;
+    }
+
+    // L 的行号换成主元位置
+    for row in &mut factor.lower.row_idx {
+        *row = factor.pivot_of_row[*row];
+    }
+    Ok(factor)
+}
+
+/// Row to place on the diagonal of each column so that every diagonal entry of the
+/// row-permuted matrix is nonzero, as needed by the pivots of the incomplete
+/// factorizations. Explicit zeros do not count, and larger entries are tried first.
+pub fn diagonal_matching(matrix: &CsMat<f64>) -> Result<Vec<usize>> {
+    if matrix.rows() != matrix.cols() {
+        return Err(anyhow!("Matrix must be square"));
+    }
+    let a = Csc::from_matrix(matrix);
+    let mut nonzero = Csc::with_columns(matrix.cols());
+    for col in 0..matrix.cols() {
+        let mut entries: Vec<(usize, f64)> = a.column(col)
+            .filter(|&p| a.values[p] != 0.0)
+            .map(|p| (a.row_idx[p], a.values[p]))
+            .collect();
+        entries.sort_by(|x, y| y.1.abs().total_cmp(&x.1.abs()));
+        for (row, value) in entries {
+            nonzero.row_idx.push(row);
+            nonzero.values.push(value);
+        }
+        nonzero.close_column();
+    }
+    maximum_transversal(matrix.rows(), &nonzero)
+}
+
+/// Maximum transversal by augmenting paths: the row matched to each column, so that
+/// the permuted matrix has a zero-free diagonal. Fails if the matrix is structurally singular.
+fn maximum_transversal<T: Copy + Default>(n: usize, a: &Csc<T>) -> Result<Vec<usize>> {
+    let mut matched_row = vec![NONE; n];
+    let mut matched_col = vec![NONE; n];
+
+    // 先做廉价匹配
+    for (col, matched) in matched_row.iter_mut().enumerate() {
+        if let Some(p) = a.column(col).find(|&p| matched_col[a.row_idx[p]] == NONE) {
+            *matched = a.row_idx[p];
+            matched_col[a.row_idx[p]] = col;
+        }
+    }
+
+    let mut visited = vec![NONE; n];
+    let mut stack: Vec<(usize, usize)> = Vec::new();
+    for root in 0..n {
+        if matched_row[root] != NONE {
+            continue;
+        }
+        // 深度优先寻找增广路径，栈中为路径上的列
+        stack.clear();
+        stack.push((root, a.col_ptr[root]));
+        let mut free_row = NONE;
+        while let Some(&mut (col, ref mut next)) = stack.last_mut() {
+            if *next == a.col_ptr[col + 1] {
+                stack.pop();
+                continue;
+            }
+            let row = a.row_idx[*next];
+            *next += 1;
+            if visited[row] == root {
+                continue;
+            }
+            visited[row] = root;
+            if matched_col[row] == NONE {
+                free_row = row;
+                break;
+            }
+            let owner = matched_col[row];
+            stack.push((owner, a.col_ptr[owner]));
+        }
+        if free_row == NONE {
+            return Err(anyhow!("Matrix is structurally singular (column {} has no nonzero to pivot on)", root));
+        }
+        let mut row = free_row;
+        for &(col, _) in stack.iter().rev() {
+            let previous = matched_row[col];
+            matched_row[col] = row;
+            matched_col[row] = col;
+            row = previous;
+        }
+    }
+    Ok(matched_row)
+}
+
+/// Tarjan's strongly connected components of a directed graph, without recursion.
+/// Returns the component of each node and the number of components; components are
+/// numbered in the order they are completed, so every edge leads to an equal or lower number.
+fn strongly_connected_components(adjacency: &[&[usize]]) -> (Vec<usize>, usize) {
+    let n = adjacency.len();
+    let mut index = vec![NONE; n];
+    let mut low = vec![0; n];
+    let mut on_stack = vec![false; n];
+    let mut component = vec![NONE; n];
+    let mut stack = Vec::new();
+    let mut calls: Vec<(usize, usize)> = Vec::new();
+    let mut counter = 0;
+    let mut components = 0;
+
+    for root in 0..n {
+        if index[root] != NONE {
+            continue;
+        }
+        index[root] = counter;
+        low[root] = counter;
+        counter += 1;
+        stack.push(root);
+        on_stack[root] = true;
+        calls.push((root, 0));
+
+        while let Some(&mut (node, ref mut next)) = calls.last_mut() {
+            if let Some(&target) = adjacency[node].get(*next) {
+                *next += 1;
+                if index[target] == NONE {
+                    index[target] = counter;
+                    low[target] = counter;
+                    counter += 1;
+                    stack.push(target);
+                    on_stack[target] = true;
+                    calls.push((target, 0));
+                } else if on_stack[target] {
+                    low[node] = low[node].min(index[target]);
+                }
+                continue;
+            }
+
+            calls.pop();
+            if let Some(&(parent, _)) = calls.last() {
+                low[parent] = low[parent].min(low[node]);
+            }
+            if low[node] == index[node] {
+                while let Some(member) = stack.pop() {
+                    on_stack[member] = false;
+                    component[member] = components;
+                    if member == node {
+                        break;
+                    }
+                }
+                components += 1;
+            }
+        }
+    }
+    (component, components)
+}
+
+/// Approximate minimum degree ordering of a symmetric pattern given as adjacency lists
+/// without self loops. Eliminated variables become elements of a quotient graph, and
+/// degrees are bounded from above as in Amestoy, Davis and Duff instead of recomputed.
+fn approximate_minimum_degree(mut variables: Vec<Vec<usize>>) -> Vec<usize> {
+    let n = variables.len();
+    let mut elements: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut members: Vec<Vec<usize>> = vec![Vec::new(); n];
+    let mut eliminated = vec![false; n];
+    let mut absorbed = vec![false; n];
+    let mut degree: Vec<usize> = variables.iter().map(Vec::len).collect();
+    let mut queue: BTreeSet<(usize, usize)> = (0..n).map(|i| (degree[i], i)).collect();
+    let mut mark = vec![NONE; n];
+    let mut external = vec![0; n];
+    let mut external_mark = vec![NONE; n];
+    let mut order = Vec::with_capacity(n);
+
+    for k in 0..n {
+        let (_, pivot) = queue.pop_first().expect("every variable is queued");
+        eliminated[pivot] = true;
+        order.push(pivot);
+
+        // 新元素的变量集合 Lp：主元的相邻变量与其相邻元素的并，被包含的元素随之吸收
+        let mut new_element = Vec::new();
+        mark[pivot] = k;
+        let neighbours = std::mem::take(&mut variables[pivot]);
+        let old_elements = std::mem::take(&mut elements[pivot]);
+        for &e in &old_elements {
+            if absorbed[e] {
+                continue;
+            }
+            absorbed[e] = true;
+            for i in std::mem::take(&mut members[e]) {
+                if mark[i] != k {
+                    mark[i] = k;
+                    new_element.push(i);
+                }
+            }
+        }
+        for i in neighbours {
+            if !eliminated[i] && mark[i] != k {
+                mark[i] = k;
+                new_element.push(i);
+            }
+        }
+
+        // |Le \ Lp|：对 Lp 中每个变量所在的元素递减计数
+        for &i in &new_element {
+            for &e in &elements[i] {
+                if absorbed[e] {
+                    continue;
+                }
+                if external_mark[e] != k {
+                    external_mark[e] = k;
+                    external[e] = members[e].len();
+                }
+                external[e] -= 1;
+            }
+        }
+
+        let size = new_element.len();
+        for &i in &new_element {
+            variables[i].retain(|&j| !eliminated[j] && mark[j] != k);
+            let mut bound = variables[i].len() + size - 1;
+            elements[i].retain(|&e| {
+                if absorbed[e] {
+                    return false;
+                }
+                if external[e] == 0 {
+                    // 完全包含在 Lp 中的元素被主动吸收
+                    absorbed[e] = true;
+                    return false;
+                }
+                bound += external[e];
+                true
+            });
+            elements[i].push(pivot);
+
+            let updated = bound.min(degree[i] + size - 1).min(n - k - 1);
+            queue.remove(&(degree[i], i));
+            degree[i] = updated;
+            queue.insert((updated, i));
+        }
+        members[pivot] = new_element;
+    }
+    order
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::{DMatrix, DVector};
+    use sprs::TriMat;
+
+    fn sparse(n: usize, entries: &[(usize, usize, f64)]) -> CsMat<f64> {
+        let mut triplets = TriMat::new((n, n));
+        for &(row, col, value) in entries {
+            triplets.add_triplet(row, col, value);
+        }
+        triplets.to_csc()
+    }
+
+    #[test]
+    fn test_matches_dense_solve() {
+        // 含零对角的 MNA 式矩阵：电压源支路行列
+        let entries = [
+            (0, 0, 1e-3), (0, 1, -1e-3), (0, 4, 1.0),
+            (1, 0, -1e-3), (1, 1, 3e-3), (1, 2, -2e-3),
+            (2, 1, -2e-3), (2, 2, 2.5e-3), (2, 3, 1.0),
+            (3, 2, 1.0), (3, 3, 0.0),
+            (4, 0, 1.0),
+            (5, 5, 2.0), (5, 1, 0.5),
+        ];
+        let matrix = sparse(6, &entries);
+        let rhs = [0.0, 1e-3, 0.0, 2.0, 5.0, 1.0];
+
+        let lu = SparseLu::factor(&matrix, DEFAULT_PIVOT_TOLERANCE).unwrap();
+        let solution = lu.solve(&rhs);
+
+        let mut dense = DMatrix::zeros(6, 6);
+        for &(row, col, value) in &entries {
+            dense[(row, col)] += value;
+        }
+        let expected = dense.lu().solve(&DVector::from_row_slice(&rhs)).unwrap();
+        for (value, expected) in solution.iter().zip(expected.iter()) {
+            assert!((value - expected).abs() < 1e-9 * expected.abs().max(1.0), "{} vs {}", value, expected);
+        }
+        assert!(lu.stats().blocks > 1);
+        assert!(lu.stats().flops > 0);
+    }
+
+    #[test]
+    fn test_ordering_limits_fill_in() {
+        // 箭头矩阵：先消去中心节点会填满整个矩阵，最小度排序把它留到最后
+        let n = 50;
+        let mut entries = vec![(0, 0, n as f64)];
+        for i in 1..n {
+            entries.extend([(i, i, 2.0), (0, i, 1.0), (i, 0, 1.0)]);
+        }
+        let lu = SparseLu::factor(&sparse(n, &entries), DEFAULT_PIVOT_TOLERANCE).unwrap();
+        let stats = lu.stats();
+        assert_eq!(stats.fill_in, 0);
+        assert_eq!(stats.nnz_factors, stats.nnz_matrix);
+
+        let rhs: Vec<f64> = (0..n).map(|i| i as f64).collect();
+        let solution = lu.solve(&rhs);
+        let mut dense = DMatrix::zeros(n, n);
+        for &(row, col, value) in &entries {
+            dense[(row, col)] = value;
+        }
+        let residual = dense * DVector::from_vec(solution) - DVector::from_vec(rhs);
+        assert!(residual.norm() < 1e-10);
+    }
+
+    #[test]
+    fn test_refactorization() {
+        let mut factorization = LuFactorization::new();
+        let first = sparse(2, &[(0, 0, 1.0), (0, 1, 1.0), (1, 0, 1.0), (1, 1, 0.5)]);
+        factorization.factor(&first, DEFAULT_PIVOT_TOLERANCE, 1e8).unwrap();
+
+        // 同一结构的新数值沿用原主元顺序
+        let second = sparse(2, &[(0, 0, 2.0), (0, 1, 1.0), (1, 0, 1.0), (1, 1, 3.0)]);
+        let stats = factorization.factor(&second, DEFAULT_PIVOT_TOLERANCE, 1e8).unwrap();
+        assert!(stats.refactored);
+        let solution = factorization.solve(&[3.0, 4.0]).unwrap();
+        assert!((solution[0] - 1.0).abs() < 1e-12 && (solution[1] - 1.0).abs() < 1e-12);
+
+        // 原对角主元变得极小：主元增长过大，重新选主元
+        let third = sparse(2, &[(0, 0, 1e-12), (0, 1, 1.0), (1, 0, 1.0), (1, 1, 0.5)]);
+        let stats = factorization.factor(&third, DEFAULT_PIVOT_TOLERANCE, 1e8).unwrap();
+        assert!(!stats.refactored);
+        assert_eq!(stats.off_diagonal_pivots, 2);
+        let solution = factorization.solve(&[1.0, 1.5]).unwrap();
+        assert!((solution[0] - 1.0).abs() < 1e-9 && (solution[1] - 1.0).abs() < 1e-9);
+
+        // 结构改变时重新做符号分析
+        let diagonal = sparse(2, &[(0, 0, 2.0), (1, 1, 4.0)]);
+        factorization.factor(&diagonal, DEFAULT_PIVOT_TOLERANCE, 1e8).unwrap();
+        assert_eq!(factorization.solve(&[2.0, 2.0]).unwrap(), vec![1.0, 0.5]);
+        assert_eq!(factorization.counts(), FactorizationCounts { analyses: 2, factorizations: 3, refactorizations: 1 });
+    }
+
+    #[test]
+    fn test_singular_matrices() {
+        // 结构奇异：第 1 列为空
+        let structural = sparse(2, &[(0, 0, 1.0), (1, 0, 1.0)]);
+        assert!(SparseLu::factor(&structural, DEFAULT_PIVOT_TOLERANCE).is_err());
+
+        // 数值奇异：两行相同
+        let numerical = sparse(2, &[(0, 0, 1.0), (0, 1, 2.0), (1, 0, 1.0), (1, 1, 2.0)]);
+        assert!(SparseLu::factor(&numerical, DEFAULT_PIVOT_TOLERANCE).is_err());
+    }
+}
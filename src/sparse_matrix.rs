@@ -0,0 +1,173 @@
+use nalgebra::DMatrix;
+use sprs::CsMat;
+use std::ops::{Index, IndexMut};
+
+/// Square matrix in compressed sparse column form whose pattern is fixed up front, so
+/// that assembly only zeroes the values and adds stamps into their slots. The slots a
+/// component stamps are resolved once into its `StampSlots`; the solvers borrow the
+/// matrix as it is, without copying the pattern. The pattern never changes: writing
+/// outside it is a bug in the pattern and panics.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SparseMatrix {
+    csc: CsMat<f64>,
+}
+
+impl SparseMatrix {
+    /// Matrix of `size` x `size` with zero values at the given `(row, col)` positions
+    pub fn from_pattern(size: usize, entries: impl IntoIterator<Item = (usize, usize)>) -> SparseMatrix {
+        let mut columns = vec![Vec::new(); size];
+        for (row, col) in entries {
+            columns[col].push(row);
+        }
+
+        let mut col_ptr = Vec::with_capacity(size + 1);
+        let mut row_idx = Vec::new();
+        col_ptr.push(0);
+        for mut rows in columns {
+            rows.sort_unstable();
+            rows.dedup();
+            row_idx.extend(rows);
+            col_ptr.push(row_idx.len());
+        }
+        let values = vec![0.0; row_idx.len()];
+        SparseMatrix { csc: CsMat::new_csc((size, size), col_ptr, row_idx, values) }
+    }
+
+    pub fn size(&self) -> usize {
+        self.csc.cols()
+    }
+
+    /// Number of entries of the pattern
+    pub fn nnz(&self) -> usize {
+        self.csc.nnz()
+    }
+
+    /// Index of the value slot of `(row, col)`, if it is in the pattern
+    pub fn slot(&self, row: usize, col: usize) -> Option<usize> {
+        let col_ptr = self.csc.indptr();
+        let col_ptr = col_ptr.raw_storage();
+        let start = col_ptr[col];
+        self.csc.indices()[start..col_ptr[col + 1]].binary_search(&row).ok().map(|offset| start + offset)
+    }
+
+    /// Zero all values, keeping the pattern
+    pub fn clear(&mut self) {
+        self.csc.data_mut().fill(0.0);
+    }
+
+    pub fn values(&self) -> &[f64] {
+        self.csc.data()
+    }
+
+    /// Values in slot order, for stamping through precomputed slots
+    pub fn values_mut(&mut self) -> &mut [f64] {
+        self.csc.data_mut()
+    }
+
+    /// The matrix in compressed sparse column form for the solvers; entries of the pattern
+    /// that are zero at present are kept, so the pattern does not change between assemblies
+    pub fn as_csmat(&self) -> &CsMat<f64> {
+        &self.csc
+    }
+
+    /// Entries of the pattern as `(row, col, value)`, column by column
+    pub fn entries(&self) -> impl Iterator<Item = (usize, usize, f64)> + '_ {
+        self.csc.iter().map(|(&value, (row, col))| (row, col, value))
+    }
+
+    /// Dense copy, for inspecting small systems
+    pub fn to_dense(&self) -> DMatrix<f64> {
+        let mut dense = DMatrix::zeros(self.size(), self.size());
+        for (row, col, value) in self.entries() {
+            dense[(row, col)] = value;
+        }
+        dense
+    }
+}
+
+/// Value slots of all positions among the terminals of a component (its nodes, internal
+/// nodes and branch currents), resolved once from the pattern so that each stamp writes
+/// its slot directly
+#[derive(Debug, Clone, Default)]
+pub struct StampSlots {
+    terminals: Vec<usize>,
+    /// Slot of `(terminals[i], terminals[j])` at `i * terminals.len() + j`
+    slots: Vec<Option<usize>>,
+}
+
+impl StampSlots {
+    pub fn new(matrix: &SparseMatrix, terminals: Vec<usize>) -> StampSlots {
+        let slots = terminals.iter()
+            .flat_map(|&row| terminals.iter().map(move |&col| matrix.slot(row, col)))
+            .collect();
+        StampSlots { terminals, slots }
+    }
+
+    /// Slot of `(row, col)`. Panics unless both are terminals and the position is in the pattern.
+    pub fn slot(&self, row: usize, col: usize) -> usize {
+        let position = |index: usize| self.terminals.iter().position(|&terminal| terminal == index);
+        match (position(row), position(col)) {
+            (Some(i), Some(j)) => self.slots[i * self.terminals.len() + j],
+            _ => None,
+        }
+        .unwrap_or_else(|| panic!("Stamp at ({}, {}) is outside the matrix pattern", row, col))
+    }
+}
+
+impl Index<(usize, usize)> for SparseMatrix {
+    type Output = f64;
+
+    /// Value at `(row, col)`, zero outside the pattern
+    fn index(&self, (row, col): (usize, usize)) -> &f64 {
+        match self.slot(row, col) {
+            Some(slot) => &self.csc.data()[slot],
+            None => &0.0,
+        }
+    }
+}
+
+impl IndexMut<(usize, usize)> for SparseMatrix {
+    /// Panics if `(row, col)` is outside the pattern
+    fn index_mut(&mut self, (row, col): (usize, usize)) -> &mut f64 {
+        let slot = self.slot(row, col)
+            .unwrap_or_else(|| panic!("Stamp at ({}, {}) is outside the matrix pattern", row, col));
+        &mut self.csc.data_mut()[slot]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_stamps_fill_pattern_slots() {
+        let mut matrix = SparseMatrix::from_pattern(3, [(0, 0), (1, 1), (2, 2), (0, 1), (1, 0), (0, 0)]);
+        assert_eq!(matrix.nnz(), 5);
+        matrix[(0, 0)] += 2.0;
+        matrix[(0, 1)] -= 1.0;
+        matrix[(0, 0)] += 1.0;
+        assert_eq!((matrix[(0, 0)], matrix[(0, 1)], matrix[(2, 0)]), (3.0, -1.0, 0.0));
+
+        let dense = matrix.to_dense();
+        assert_eq!((dense[(2, 0)], dense[(1, 0)], dense[(0, 0)]), (0.0, 0.0, 3.0));
+
+        // 预先解析的值槽与按位置查找的一致
+        let slots = StampSlots::new(&matrix, vec![1, 0]);
+        matrix.values_mut()[slots.slot(1, 0)] += 5.0;
+        assert_eq!(matrix[(1, 0)], 5.0);
+
+        matrix.clear();
+        assert_eq!(matrix.nnz(), 5);
+        assert!(matrix.values().iter().all(|&value| value == 0.0));
+        let csc = matrix.as_csmat();
+        assert!(csc.is_csc() && csc.nnz() == 5);
+    }
+
+    #[test]
+    #[should_panic(expected = "(2, 0)")]
+    fn test_stamp_outside_pattern_is_rejected() {
+        let mut matrix = SparseMatrix::from_pattern(3, [(0, 0), (1, 1), (2, 2)]);
+        // 模式之外的印记说明模式推导有误，直接报错而不扩展模式
+        matrix[(2, 0)] = 4.0;
+    }
+}
@@ -0,0 +1,139 @@
+use anyhow::{anyhow, Result};
+use serde::{Deserialize, Serialize};
+
+/// A swept variable (source or resistor name) and the values it steps through
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Sweep {
+    pub name: String,
+    pub kind: SweepKind,
+}
+
+/// How the values of a sweep are generated
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum SweepKind {
+    /// Fixed increment from `start` to `stop` inclusive
+    Linear { start: f64, stop: f64, step: f64 },
+    /// `points` logarithmically spaced values per decade
+    Decade { start: f64, stop: f64, points: usize },
+    /// `points` logarithmically spaced values per octave
+    Octave { start: f64, stop: f64, points: usize },
+    /// Explicit values, in order
+    List(Vec<f64>),
+}
+
+impl Sweep {
+    pub fn new(name: &str, kind: SweepKind) -> Self {
+        Sweep { name: name.to_string(), kind }
+    }
+
+    /// Linear sweep from `start` to `stop` in increments of `step`
+    pub fn linear(name: &str, start: f64, stop: f64, step: f64) -> Self {
+        Sweep::new(name, SweepKind::Linear { start, stop, step })
+    }
+
+    /// Linear sweep with a fixed number of points (`LIN np start stop`)
+    pub fn linear_points(name: &str, points: usize, start: f64, stop: f64) -> Result<Self> {
+        if points == 0 {
+            return Err(anyhow!("Sweep of {} needs at least one point", name));
+        }
+        let step = if points > 1 { (stop - start) / (points - 1) as f64 } else { 0.0 };
+        Ok(Sweep::linear(name, start, stop, step))
+    }
+
+    /// Frequency sweep of an AC analysis (`DEC|OCT|LIN points fstart fstop`)
+    pub fn frequency(sweep_type: &str, points: usize, fstart: f64, fstop: f64) -> Result<Self> {
+        if fstart < 0.0 || fstop < fstart {
+            return Err(anyhow!("AC sweep needs 0 <= fstart <= fstop, got {} to {}", fstart, fstop));
+        }
+        match sweep_type.to_uppercase().as_str() {
+            "DEC" => Ok(Sweep::new("frequency", SweepKind::Decade { start: fstart, stop: fstop, points })),
+            "OCT" => Ok(Sweep::new("frequency", SweepKind::Octave { start: fstart, stop: fstop, points })),
+            "LIN" => Sweep::linear_points("frequency", points, fstart, fstop),
+            other => Err(anyhow!("Unknown AC sweep type: {}", other)),
+        }
+    }
+
+    /// All values of the sweep, in sweep order
+    pub fn values(&self) -> Result<Vec<f64>> {
+        match &self.kind {
+            SweepKind::Linear { start, stop, step } => linear_values(*start, *stop, *step),
+            SweepKind::Decade { start, stop, points } => log_values(*start, *stop, *points, 10.0),
+            SweepKind::Octave { start, stop, points } => log_values(*start, *stop, *points, 2.0),
+            SweepKind::List(values) if values.is_empty() => {
+                Err(anyhow!("LIST sweep of {} has no values", self.name))
+            }
+            SweepKind::List(values) => Ok(values.clone()),
+        }
+    }
+}
+
+/// Values of a linear sweep from `start` to `stop` inclusive.
+/// The direction is taken from `start -> stop`, so the sign of `step` does not matter.
+fn linear_values(start: f64, stop: f64, step: f64) -> Result<Vec<f64>> {
+    if start == stop {
+        return Ok(vec![start]);
+    }
+    if step == 0.0 || !step.is_finite() {
+        return Err(anyhow!("Invalid sweep step: {}", step));
+    }
+
+    let step = step.abs().copysign(stop - start);
+    // 容差避免浮点误差丢掉最后一个点
+    let intervals = ((stop - start) / step * (1.0 + 1e-9)).floor() as usize;
+    Ok((0..=intervals).map(|i| start + i as f64 * step).collect())
+}
+
+/// Logarithmic sweep with `points` values per factor of `base`; `stop` may be below `start`
+fn log_values(start: f64, stop: f64, points: usize, base: f64) -> Result<Vec<f64>> {
+    if points == 0 {
+        return Err(anyhow!("Logarithmic sweep needs at least one point per interval"));
+    }
+    if start == 0.0 || stop == 0.0 || start.signum() != stop.signum() {
+        return Err(anyhow!("Logarithmic sweep from {} to {} must not cross or touch zero", start, stop));
+    }
+
+    let span = (stop / start).log(base) * points as f64;
+    let intervals = (span.abs() * (1.0 + 1e-9)).floor() as usize;
+    let direction = span.signum();
+    Ok((0..=intervals)
+        .map(|i| start * base.powf(direction * i as f64 / points as f64))
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_linear_values() {
+        assert_eq!(Sweep::linear("V1", 0.0, 1.0, 0.25).values().unwrap().len(), 5);
+        assert_eq!(Sweep::linear("V1", 0.0, 0.3, 0.1).values().unwrap().len(), 4);
+        assert_eq!(Sweep::linear("V1", 1.0, 0.0, -0.5).values().unwrap(), vec![1.0, 0.5, 0.0]);
+        assert_eq!(Sweep::linear("V1", 2.0, 2.0, 0.0).values().unwrap(), vec![2.0]);
+        assert!(Sweep::linear("V1", 0.0, 1.0, 0.0).values().is_err());
+        assert_eq!(Sweep::linear_points("V1", 3, 0.0, 1.0).unwrap().values().unwrap(), vec![0.0, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn test_log_and_list_values() {
+        let decade = Sweep::new("I1", SweepKind::Decade { start: 1e-6, stop: 1e-3, points: 2 });
+        let values = decade.values().unwrap();
+        assert_eq!(values.len(), 7);
+        assert!((values[1] - 1e-6 * 10f64.sqrt()).abs() < 1e-15);
+        assert!((values[6] - 1e-3).abs() < 1e-15);
+
+        let octave = Sweep::new("V1", SweepKind::Octave { start: 8.0, stop: 1.0, points: 1 });
+        let values = octave.values().unwrap();
+        assert_eq!(values.len(), 4);
+        assert!((values[3] - 1.0).abs() < 1e-12);
+
+        assert!(Sweep::new("V1", SweepKind::Decade { start: -1.0, stop: 1.0, points: 5 }).values().is_err());
+        assert_eq!(Sweep::new("V1", SweepKind::List(vec![3.0, 1.0])).values().unwrap(), vec![3.0, 1.0]);
+
+        let frequencies = Sweep::frequency("dec", 10, 1.0, 1e3).unwrap().values().unwrap();
+        assert_eq!(frequencies.len(), 31);
+        assert_eq!(Sweep::frequency("LIN", 3, 0.0, 2e3).unwrap().values().unwrap(), vec![0.0, 1e3, 2e3]);
+        assert!(Sweep::frequency("dec", 10, 0.0, 1e3).unwrap().values().is_err());
+        assert!(Sweep::frequency("log", 10, 1.0, 1e3).is_err());
+    }
+}
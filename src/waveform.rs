@@ -0,0 +1,355 @@
+use std::f64::consts::PI;
+use std::fs;
+use std::path::Path;
+use serde::{Deserialize, Serialize};
+use anyhow::{anyhow, Result};
+
+/// Time-dependent value of an independent source
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Waveform {
+    /// PULSE(V1 V2 TD TR TF PW PER)
+    Pulse {
+        v1: f64,
+        v2: f64,
+        delay: f64,
+        rise: f64,
+        fall: f64,
+        width: f64,
+        period: f64,
+    },
+    /// SIN(VO VA FREQ TD THETA PHASE), phase in degrees
+    Sin {
+        offset: f64,
+        amplitude: f64,
+        frequency: f64,
+        delay: f64,
+        damping: f64,
+        phase: f64,
+    },
+    /// EXP(V1 V2 TD1 TAU1 TD2 TAU2)
+    Exp {
+        v1: f64,
+        v2: f64,
+        rise_delay: f64,
+        rise_tau: f64,
+        fall_delay: f64,
+        fall_tau: f64,
+    },
+    /// PWL(T1 V1 T2 V2 ...) with optional repeat start time and delay
+    Pwl {
+        points: Vec<(f64, f64)>,
+        repeat: Option<f64>,
+        delay: f64,
+    },
+    /// SFFM(VO VA FC MDI FS)
+    Sffm {
+        offset: f64,
+        amplitude: f64,
+        carrier: f64,
+        modulation_index: f64,
+        signal: f64,
+    },
+}
+
+impl Waveform {
+    /// Build a PULSE/SIN/EXP/SFFM waveform from its positional arguments.
+    ///
+    /// Unspecified edge times default to an ideal step, and an unspecified
+    /// pulse width or period to a single pulse that never ends.
+    pub fn from_values(kind: &str, values: &[f64]) -> Result<Waveform> {
+        let kind = kind.to_uppercase();
+        if !matches!(kind.as_str(), "PULSE" | "SIN" | "EXP" | "SFFM") {
+            return Err(anyhow!("Unknown source waveform: {}", kind));
+        }
+        if values.len() < 2 {
+            return Err(anyhow!("{} needs at least 2 values, got {}", kind, values.len()));
+        }
+        if values.len() > 7 {
+            return Err(anyhow!("Too many values for {}: {}", kind, values.len()));
+        }
+        let arg = |index: usize, default: f64| values.get(index).copied().unwrap_or(default);
+
+        let waveform = match kind.as_str() {
+            "PULSE" => Waveform::Pulse {
+                v1: values[0],
+                v2: values[1],
+                delay: arg(2, 0.0),
+                rise: arg(3, 0.0),
+                fall: arg(4, 0.0),
+                width: arg(5, f64::INFINITY),
+                period: arg(6, f64::INFINITY),
+            },
+            "SIN" => Waveform::Sin {
+                offset: values[0],
+                amplitude: values[1],
+                frequency: arg(2, 0.0),
+                delay: arg(3, 0.0),
+                damping: arg(4, 0.0),
+                phase: arg(5, 0.0),
+            },
+            "EXP" => Waveform::Exp {
+                v1: values[0],
+                v2: values[1],
+                rise_delay: arg(2, 0.0),
+                rise_tau: arg(3, 0.0),
+                fall_delay: arg(4, f64::INFINITY),
+                fall_tau: arg(5, 0.0),
+            },
+            _ => Waveform::Sffm {
+                offset: values[0],
+                amplitude: values[1],
+                carrier: arg(2, 0.0),
+                modulation_index: arg(3, 0.0),
+                signal: arg(4, 0.0),
+            },
+        };
+        waveform.validate()?;
+        Ok(waveform)
+    }
+
+    /// Build a PWL waveform from its time/value pairs
+    pub fn pwl(points: Vec<(f64, f64)>, repeat: Option<f64>, delay: f64) -> Result<Waveform> {
+        let waveform = Waveform::Pwl { points, repeat, delay };
+        waveform.validate()?;
+        Ok(waveform)
+    }
+
+    fn validate(&self) -> Result<()> {
+        match self {
+            Waveform::Pulse { rise, fall, width, period, .. }
+                if *rise < 0.0 || *fall < 0.0 || *width < 0.0 || *period <= 0.0 =>
+            {
+                Err(anyhow!("PULSE times must be non-negative"))
+            }
+            Waveform::Exp { rise_tau, fall_tau, .. } if *rise_tau < 0.0 || *fall_tau < 0.0 => {
+                Err(anyhow!("EXP time constants must be non-negative"))
+            }
+            Waveform::Pwl { points, .. } if points.is_empty() => {
+                Err(anyhow!("PWL needs at least one point"))
+            }
+            Waveform::Pwl { points, .. } if points.windows(2).any(|pair| pair[1].0 < pair[0].0) => {
+                Err(anyhow!("PWL time values must be non-decreasing"))
+            }
+            Waveform::Pwl { points, repeat: Some(start), .. }
+                if *start < points[0].0 || *start >= points[points.len() - 1].0 =>
+            {
+                Err(anyhow!("PWL repeat time {} is outside the waveform", start))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Value of the waveform at time `t`
+    pub fn value_at(&self, t: f64) -> f64 {
+        match self {
+            Waveform::Pulse { v1, v2, delay, rise, fall, width, period } => {
+                if t < *delay {
+                    return *v1;
+                }
+                let mut local = t - delay;
+                if period.is_finite() {
+                    local %= period;
+                }
+                if local < *rise {
+                    v1 + (v2 - v1) * local / rise
+                } else if local < rise + width {
+                    *v2
+                } else if local < rise + width + fall {
+                    v2 + (v1 - v2) * (local - rise - width) / fall
+                } else {
+                    *v1
+                }
+            }
+            Waveform::Sin { offset, amplitude, frequency, delay, damping, phase } => {
+                let phase = phase * PI / 180.0;
+                if t < *delay {
+                    offset + amplitude * phase.sin()
+                } else {
+                    let local = t - delay;
+                    offset + amplitude * (-local * damping).exp() * (2.0 * PI * frequency * local + phase).sin()
+                }
+            }
+            Waveform::Exp { v1, v2, rise_delay, rise_tau, fall_delay, fall_tau } => {
+                let charge = |elapsed: f64, tau: f64| {
+                    if tau > 0.0 { 1.0 - (-elapsed / tau).exp() } else { 1.0 }
+                };
+                let mut value = *v1;
+                if t > *rise_delay {
+                    value += (v2 - v1) * charge(t - rise_delay, *rise_tau);
+                }
+                if t > *fall_delay {
+                    value += (v1 - v2) * charge(t - fall_delay, *fall_tau);
+                }
+                value
+            }
+            Waveform::Pwl { points, repeat, delay } => {
+                let mut local = t - delay;
+                let end = points.last().unwrap().0;
+                if let Some(start) = repeat {
+                    if local > end {
+                        local = start + (local - start) % (end - start);
+                    }
+                }
+                interpolate(points, local)
+            }
+            Waveform::Sffm { offset, amplitude, carrier, modulation_index, signal } => {
+                offset + amplitude * (2.0 * PI * carrier * t + modulation_index * (2.0 * PI * signal * t).sin()).sin()
+            }
+        }
+    }
+
+    /// First time after `after` where the waveform or its slope changes abruptly:
+    /// pulse edges, PWL vertices and the start of delayed SIN and EXP segments.
+    /// Periodic corners are computed from the period index, so a long run of pulses
+    /// costs nothing until transient analysis reaches it.
+    pub fn next_breakpoint(&self, after: f64) -> Option<f64> {
+        match self {
+            Waveform::Pulse { delay, rise, fall, width, period, .. } => {
+                let corners = [0.0, *rise, rise + width, rise + width + fall];
+                let corners = corners.iter().map(|corner| delay + corner).filter(|t| t.is_finite());
+                if !period.is_finite() {
+                    return first_after(corners, after);
+                }
+                // 由周期序号直接定位，前后各多看一个周期以容纳舍入和跨周期的边沿
+                let index = ((after - delay) / period).floor().max(0.0);
+                let starts = [index - 1.0, index, index + 1.0].into_iter().filter(|&k| k >= 0.0).map(|k| k * period);
+                first_after(starts.flat_map(|start| corners.clone().map(move |corner| start + corner)), after)
+            }
+            Waveform::Sin { delay, .. } if *delay > 0.0 => (*delay > after).then_some(*delay),
+            Waveform::Exp { rise_delay, fall_delay, .. } => first_after([*rise_delay, *fall_delay].into_iter(), after),
+            Waveform::Pwl { points, repeat, delay } => {
+                let end = points[points.len() - 1].0;
+                match repeat {
+                    // 重复段从 start 之后的各顶点开始循环
+                    Some(start) if end > *start && after >= delay + end => {
+                        let cycle = end - start;
+                        let index = ((after - delay - end) / cycle).floor().max(0.0);
+                        let offsets = [index - 1.0, index, index + 1.0].into_iter().filter(|&j| j >= 0.0)
+                            .map(|j| delay + end - start + j * cycle);
+                        let repeated = offsets.flat_map(|offset| {
+                            points.iter().filter(|(time, _)| time > start).map(move |(time, _)| offset + time)
+                        });
+                        first_after(repeated, after)
+                    }
+                    _ => first_after(points.iter().map(|(time, _)| delay + time), after),
+                }
+            }
+            _ => None,
+        }
+    }
+
+    /// Value used for the DC operating point when no DC value is given
+    pub fn initial_value(&self) -> f64 {
+        self.value_at(0.0)
+    }
+}
+
+/// Linear interpolation in a sorted PWL table, holding the end values outside it
+fn interpolate(points: &[(f64, f64)], t: f64) -> f64 {
+    let (first, last) = (points[0], points[points.len() - 1]);
+    if t <= first.0 {
+        return first.1;
+    }
+    if t >= last.0 {
+        return last.1;
+    }
+    let index = points.partition_point(|&(time, _)| time <= t);
+    let (t0, v0) = points[index - 1];
+    let (t1, v1) = points[index];
+    if t1 == t0 {
+        v1
+    } else {
+        v0 + (v1 - v0) * (t - t0) / (t1 - t0)
+    }
+}
+
+/// Read PWL points from a two-column text file (time, value)
+pub fn read_pwl_file(path: &Path) -> Result<Vec<(f64, f64)>> {
+    let content = fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read PWL file '{}': {}", path.display(), e))?;
+
+    let mut points = Vec::new();
+    for (index, line) in content.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with(['*', '#', ';']) {
+            continue;
+        }
+        let fields: Vec<&str> = line.split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|field| !field.is_empty())
+            .collect();
+        if fields.len() != 2 {
+            return Err(anyhow!("{}:{}: expected 'time value'", path.display(), index + 1));
+        }
+        let parse = |field: &str| crate::expression::parse_number(field)
+            .map_err(|e| anyhow!("{}:{}: {}", path.display(), index + 1, e));
+        points.push((parse(fields[0])?, parse(fields[1])?));
+    }
+    Ok(points)
+}
+
+/// Earliest of `times` that lies after `after`
+fn first_after(times: impl Iterator<Item = f64>, after: f64) -> Option<f64> {
+    times.filter(|&time| time > after).min_by(f64::total_cmp)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Breakpoints in (0, `tstop`], found one after another
+    fn breakpoints(waveform: &Waveform, tstop: f64) -> Vec<f64> {
+        std::iter::successors(waveform.next_breakpoint(0.0), |&time| waveform.next_breakpoint(time))
+            .take_while(|&time| time <= tstop)
+            .collect()
+    }
+
+    #[test]
+    fn test_pulse() {
+        let pulse = Waveform::from_values("pulse", &[0.0, 5.0, 1e-9, 1e-9, 1e-9, 5e-9, 10e-9]).unwrap();
+        assert_eq!(pulse.value_at(0.0), 0.0);
+        assert!((pulse.value_at(1.5e-9) - 2.5).abs() < 1e-9);
+        assert_eq!(pulse.value_at(4e-9), 5.0);
+        assert!((pulse.value_at(7.5e-9) - 2.5).abs() < 1e-9);
+        assert_eq!(pulse.value_at(9e-9), 0.0);
+        // 第二个周期
+        assert_eq!(pulse.value_at(14e-9), 5.0);
+    }
+
+    #[test]
+    fn test_sin_exp_sffm() {
+        let sin = Waveform::from_values("SIN", &[1.0, 2.0, 1e3, 1e-3]).unwrap();
+        assert_eq!(sin.value_at(0.5e-3), 1.0);
+        assert!((sin.value_at(1.25e-3) - 3.0).abs() < 1e-9);
+
+        let exp = Waveform::from_values("EXP", &[0.0, 1.0, 0.0, 1e-6, 5e-6, 1e-6]).unwrap();
+        assert!((exp.value_at(1e-6) - (1.0 - (-1.0f64).exp())).abs() < 1e-12);
+        assert!(exp.value_at(20e-6).abs() < 1e-6);
+
+        let sffm = Waveform::from_values("SFFM", &[0.5, 1.0, 1e6, 0.0, 1e3]).unwrap();
+        assert!((sffm.value_at(0.25e-6) - 1.5).abs() < 1e-9);
+        assert_eq!(sffm.initial_value(), 0.5);
+    }
+
+    #[test]
+    fn test_pwl_repeat() {
+        let pwl = Waveform::pwl(vec![(0.0, 0.0), (1.0, 1.0), (2.0, 0.0)], Some(0.0), 0.5).unwrap();
+        assert_eq!(pwl.value_at(0.25), 0.0);
+        assert_eq!(pwl.value_at(1.0), 0.5);
+        assert_eq!(pwl.value_at(1.5), 1.0);
+        assert_eq!(pwl.value_at(3.0), 0.5);
+        assert!(Waveform::pwl(vec![(1.0, 0.0), (0.5, 1.0)], None, 0.0).is_err());
+        assert_eq!(breakpoints(&pwl, 4.0), vec![0.5, 1.5, 2.5, 3.5]);
+        assert_eq!(pwl.next_breakpoint(100.6), Some(101.5));
+    }
+
+    #[test]
+    fn test_breakpoints() {
+        let pulse = Waveform::from_values("PULSE", &[0.0, 1.0, 1.0, 1.0, 1.0, 2.0, 10.0]).unwrap();
+        assert_eq!(breakpoints(&pulse, 12.0), vec![1.0, 2.0, 4.0, 5.0, 11.0, 12.0]);
+        // 远处的拐点由周期序号直接算出
+        assert_eq!(pulse.next_breakpoint(1e6 + 0.5), Some(1e6 + 1.0));
+        let step = Waveform::from_values("PULSE", &[0.0, 1.0, 1.0]).unwrap();
+        assert_eq!(breakpoints(&step, 10.0), vec![1.0]);
+        assert!(Waveform::from_values("SIN", &[0.0, 1.0, 1e3]).unwrap().next_breakpoint(0.0).is_none());
+    }
+}